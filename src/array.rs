@@ -0,0 +1,142 @@
+// Editing Arrays and Arrays of Tables without leaving the file looking edited.
+//
+// `toml_edit` gives every element after the first a leading space, so inserting or removing
+// the first element would otherwise leave the array looking like `[ 2, 3]` or `[ 0,1, 2]`.
+//
+// The tables of an Array of Tables are written out in order of their position in the
+// document, so a table added to one is moved to sit after the table before it.
+
+use toml_edit::{Array, ArrayOfTables, Item, Table, Value};
+
+use crate::error::{Error, Result};
+
+// insert a value before `index` (which must be no more than the length)
+pub fn insert(array: &mut Array, index: usize, value: Value) {
+    array.insert(index, value);
+
+    // the new first element takes over the old first element's (lack of) leading whitespace
+    if index == 0 && array.len() > 1 {
+        let prefix = array.get(1).and_then(|old| old.decor().prefix().cloned());
+        let first = array.get_mut(0).expect("just inserted");
+
+        if let Some(prefix) = prefix {
+            first.decor_mut().set_prefix(prefix);
+        }
+        array
+            .get_mut(1)
+            .expect("checked length")
+            .decor_mut()
+            .set_prefix(" ");
+    }
+}
+
+// remove the value at `index` (which must be in range)
+pub fn remove(array: &mut Array, index: usize) -> Value {
+    let removed = array.remove(index);
+
+    // the new first element takes over the removed element's leading whitespace
+    if index == 0 {
+        if let (Some(first), Some(prefix)) = (array.get_mut(0), removed.decor().prefix()) {
+            first.decor_mut().set_prefix(prefix.clone());
+        }
+    }
+
+    removed
+}
+
+// read every element as one type (e.g. to copy the Array into a LabVIEW array), failing on the
+// first element that `convert` doesn't accept
+pub fn values<T>(
+    array: &Array,
+    expected: &str,
+    convert: impl Fn(&Value) -> Option<T>,
+) -> Result<Vec<T>> {
+    array
+        .iter()
+        .enumerate()
+        .map(|(index, value)| {
+            convert(value).ok_or_else(|| {
+                Error::wrong_type(
+                    &format!("Array element {} (the Array is mixed)", index),
+                    expected,
+                )
+            })
+        })
+        .collect()
+}
+
+// the last position in the document of a table, or of any table inside it
+pub fn last_position(table: &Table) -> Option<usize> {
+    table
+        .iter()
+        .filter_map(|(_, item)| match item {
+            Item::Table(table) => last_position(table),
+            Item::ArrayOfTables(array) => array.iter().filter_map(last_position).max(),
+            _ => None,
+        })
+        .chain(table.position())
+        .max()
+}
+
+// the first position in the document of a table, or of any table inside it
+pub fn first_position(table: &Table) -> Option<usize> {
+    table
+        .iter()
+        .filter_map(|(_, item)| match item {
+            Item::Table(table) => first_position(table),
+            Item::ArrayOfTables(array) => array.iter().filter_map(first_position).min(),
+            _ => None,
+        })
+        .chain(table.position())
+        .min()
+}
+
+// move a table, and every table inside it, to a position in the document
+// (tables at the same position keep their order, so the tables inside stay where they were)
+pub fn set_position(table: &mut Table, position: usize) {
+    table.set_position(position);
+
+    for (_, item) in table.iter_mut() {
+        match item {
+            Item::Table(table) => set_position(table, position),
+            Item::ArrayOfTables(array) => {
+                for table in array.iter_mut() {
+                    set_position(table, position)
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// insert a table before `index` (which must be no more than the length)
+pub fn insert_table(array: &mut ArrayOfTables, index: usize, mut table: Table) {
+    let position = match index {
+        0 => array.get(0).and_then(Table::position),
+        index => array.get(index - 1).and_then(last_position),
+    };
+    if let Some(position) = position {
+        set_position(&mut table, position);
+    }
+
+    // `ArrayOfTables` can only push, so rebuild it around the new table
+    let mut tables: Vec<Table> = array.iter().cloned().collect();
+    tables.insert(index, table);
+    array.clear();
+    array.extend(tables);
+}
+
+// add a table to the end
+pub fn push_table(array: &mut ArrayOfTables, table: Table) {
+    insert_table(array, array.len(), table)
+}
+
+// replace the table at `index` (which must be in range), in the same place in the document
+pub fn replace_table(array: &mut ArrayOfTables, index: usize, mut table: Table) {
+    let existing = array.get_mut(index).expect("index is in range");
+
+    if let Some(position) = existing.position() {
+        set_position(&mut table, position);
+    }
+    *existing = table;
+}
@@ -0,0 +1,267 @@
+// Conversion between TOML datetimes and LabVIEW timestamps.
+//
+// A LabVIEW timestamp is a signed 64-bit count of seconds since 1904-01-01 00:00:00 UTC, plus an
+// unsigned 64-bit fraction of a second (in units of 2^-64 s). TOML has four kinds of datetime and
+// only an offset datetime is a fixed point in time, so the others are converted as follows:
+//
+//   - a local datetime is the wall-clock time at a given UTC offset (0 for UTC)
+//   - a local date is midnight at the start of that day, at a given UTC offset
+//   - a local time is that time of day on 1904-01-01, i.e. the seconds since midnight
+//     (and a timestamp converts to the time of day at the given offset)
+
+use labview_interop::types::LVTime;
+use toml_edit::{Date, Datetime, Offset, Time};
+
+use crate::error::{Error, ErrorCode, Result};
+
+// the kinds of TOML datetime, as passed to and from LabVIEW (an enum)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum DatetimeKind {
+    OffsetDatetime = 0,
+    LocalDatetime = 1,
+    LocalDate = 2,
+    LocalTime = 3,
+}
+
+impl DatetimeKind {
+    pub fn of(datetime: &Datetime) -> Self {
+        match (datetime.date, datetime.time, datetime.offset) {
+            (Some(_), Some(_), Some(_)) => DatetimeKind::OffsetDatetime,
+            (Some(_), Some(_), None) => DatetimeKind::LocalDatetime,
+            (Some(_), None, _) => DatetimeKind::LocalDate,
+            (None, _, _) => DatetimeKind::LocalTime,
+        }
+    }
+}
+
+impl TryFrom<i32> for DatetimeKind {
+    type Error = Error;
+
+    fn try_from(kind: i32) -> Result<Self> {
+        match kind {
+            0 => Ok(DatetimeKind::OffsetDatetime),
+            1 => Ok(DatetimeKind::LocalDatetime),
+            2 => Ok(DatetimeKind::LocalDate),
+            3 => Ok(DatetimeKind::LocalTime),
+            _ => Err(invalid(format!("unknown datetime kind {}", kind))),
+        }
+    }
+}
+
+// the parts of a datetime, with any parts that its kind doesn't have set to 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DatetimeParts {
+    pub year: i32,
+    pub month: i32,
+    pub day: i32,
+    pub hour: i32,
+    pub minute: i32,
+    pub second: i32,
+    pub nanosecond: i32,
+    pub offset_minutes: i32,
+}
+
+const SECONDS_PER_DAY: i64 = 86_400;
+const NANOSECONDS_PER_SECOND: u128 = 1_000_000_000;
+// days from 1904-01-01 (the LabVIEW epoch) to 1970-01-01 (the epoch of `days_from_civil`)
+const LABVIEW_EPOCH_DAYS: i64 = -24_107;
+
+fn invalid(message: String) -> Error {
+    Error::new(
+        ErrorCode::InvalidValue,
+        format!("Invalid datetime: {}", message),
+    )
+}
+
+fn offset_minutes(offset: Option<Offset>) -> i32 {
+    match offset {
+        Some(Offset::Custom { minutes }) => minutes as i32,
+        Some(Offset::Z) | None => 0,
+    }
+}
+
+impl DatetimeParts {
+    pub fn from_datetime(datetime: &Datetime) -> Self {
+        let mut parts = DatetimeParts {
+            offset_minutes: offset_minutes(datetime.offset),
+            ..Default::default()
+        };
+
+        if let Some(date) = datetime.date {
+            parts.year = date.year as i32;
+            parts.month = date.month as i32;
+            parts.day = date.day as i32;
+        }
+        if let Some(time) = datetime.time {
+            parts.hour = time.hour as i32;
+            parts.minute = time.minute as i32;
+            parts.second = time.second as i32;
+            parts.nanosecond = time.nanosecond as i32;
+        }
+
+        parts
+    }
+
+    // build a datetime of the given kind, checking every part it uses is in range
+    // (an offset of 0 is written as `Z`)
+    pub fn to_datetime(self, kind: DatetimeKind) -> Result<Datetime> {
+        let in_range =
+            |name: &str, value: i32, min: i32, max: i32| match (min..=max).contains(&value) {
+                true => Ok(value),
+                false => Err(invalid(format!(
+                    "{} {} is not in {}..={}",
+                    name, value, min, max
+                ))),
+            };
+
+        let has_date = kind != DatetimeKind::LocalTime;
+        let has_time = kind != DatetimeKind::LocalDate;
+
+        let date = match has_date {
+            true => {
+                let year = in_range("year", self.year, 0, 9999)?;
+                let month = in_range("month", self.month, 1, 12)?;
+                let day = in_range("day", self.day, 1, days_in_month(year, month))?;
+                Some(Date {
+                    year: year as u16,
+                    month: month as u8,
+                    day: day as u8,
+                })
+            }
+            false => None,
+        };
+
+        let time = match has_time {
+            true => Some(Time {
+                hour: in_range("hour", self.hour, 0, 23)? as u8,
+                minute: in_range("minute", self.minute, 0, 59)? as u8,
+                // TOML allows a leap second
+                second: in_range("second", self.second, 0, 60)? as u8,
+                nanosecond: in_range("nanosecond", self.nanosecond, 0, 999_999_999)? as u32,
+            }),
+            false => None,
+        };
+
+        let offset = match kind {
+            DatetimeKind::OffsetDatetime => {
+                match in_range("offset", self.offset_minutes, -1439, 1439)? {
+                    0 => Some(Offset::Z),
+                    minutes => Some(Offset::Custom {
+                        minutes: minutes as i16,
+                    }),
+                }
+            }
+            _ => None,
+        };
+
+        Ok(Datetime { date, time, offset })
+    }
+}
+
+// convert a datetime to a LabVIEW timestamp
+// `local_offset_minutes` is the UTC offset a local datetime or local date is taken to be at
+pub fn to_timestamp(datetime: &Datetime, local_offset_minutes: i32) -> LVTime {
+    let days = match datetime.date {
+        Some(date) => {
+            days_from_civil(date.year as i64, date.month as i64, date.day as i64)
+                - LABVIEW_EPOCH_DAYS
+        }
+        None => 0,
+    };
+
+    let (seconds_of_day, nanosecond) = match datetime.time {
+        Some(time) => (
+            time.hour as i64 * 3600 + time.minute as i64 * 60 + time.second as i64,
+            time.nanosecond,
+        ),
+        None => (0, 0),
+    };
+
+    let offset_minutes = match (datetime.date, datetime.offset) {
+        // a local time has no date, so it is never shifted
+        (None, _) => 0,
+        (Some(_), Some(offset)) => offset_minutes(Some(offset)),
+        (Some(_), None) => local_offset_minutes,
+    };
+
+    let seconds = days * SECONDS_PER_DAY + seconds_of_day - offset_minutes as i64 * 60;
+
+    // round the fraction up, so converting back (which rounds down) gives the same nanoseconds
+    let fraction = ((nanosecond as u128) << 64).div_ceil(NANOSECONDS_PER_SECOND) as u64;
+
+    LVTime::from_parts(seconds as u64, fraction)
+}
+
+// convert a LabVIEW timestamp to a datetime of the given kind
+// an offset datetime is written at `offset_minutes`, and a local datetime, local date or local
+// time is the wall-clock time at that offset
+pub fn from_timestamp(
+    timestamp: LVTime,
+    kind: DatetimeKind,
+    offset_minutes: i32,
+) -> Result<Datetime> {
+    let (seconds, fraction) = timestamp.to_parts();
+
+    // the seconds are signed in LabVIEW (a timestamp before 1904 is negative)
+    let seconds = (seconds as i64).saturating_add(offset_minutes as i64 * 60);
+    let days = seconds.div_euclid(SECONDS_PER_DAY);
+    let seconds_of_day = seconds.rem_euclid(SECONDS_PER_DAY) as i32;
+    let (year, month, day) = civil_from_days(days + LABVIEW_EPOCH_DAYS);
+
+    let parts = DatetimeParts {
+        year: i32::try_from(year).map_err(|_| invalid(format!("year {} is out of range", year)))?,
+        month: month as i32,
+        day: day as i32,
+        hour: seconds_of_day / 3600,
+        minute: seconds_of_day / 60 % 60,
+        second: seconds_of_day % 60,
+        nanosecond: ((fraction as u128 * NANOSECONDS_PER_SECOND) >> 64) as i32,
+        offset_minutes,
+    };
+
+    parts.to_datetime(kind)
+}
+
+fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i32, month: i32) -> i32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// days since 1970-01-01 of a date in the proleptic Gregorian calendar
+// (from Howard Hinnant's `days_from_civil`, http://howardhinnant.github.io/date_algorithms.html)
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+// the date of a number of days since 1970-01-01 (the inverse of `days_from_civil`)
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
@@ -0,0 +1,142 @@
+// The whitespace and comments around keys, values and table headers (`toml_edit::Decor`).
+//
+// A prefix comes before (e.g. the comment lines above a key, or above a `[table]` header) and a
+// suffix after (e.g. the comment at the end of a `key = value` line, which belongs to the value).
+// They are written out exactly as given, so they are checked to hold only whitespace and
+// comments that can't swallow the TOML after them.
+
+use toml_edit::{Decor, RawString};
+
+use crate::error::{Error, ErrorCode, Result};
+
+// the prefix and suffix of a decor ("" for any that isn't set)
+pub fn get(decor: &Decor) -> (String, String) {
+    let text = |raw: Option<&RawString>| raw.and_then(RawString::as_str).unwrap_or("").to_string();
+    (text(decor.prefix()), text(decor.suffix()))
+}
+
+// where a decor sits, which decides what it can hold without breaking the file
+#[derive(Clone, Copy)]
+pub enum Place {
+    // a key in a Table: the lines above it, and the spaces before the `=`
+    Key,
+    // a Table's `[header]`: the lines above it, and the rest of its line
+    Header,
+    // a value on a `key = value` line (or one not in a document yet): the spaces after the `=`,
+    // and the rest of the line
+    Value,
+    // a value in an Array, which can be split over lines, but can't have a comment after it
+    // (it would swallow the `,` or `]`)
+    ArrayElement,
+    // a key or value in an InlineTable, which must stay on one line
+    Inline,
+}
+
+// what a prefix or suffix can hold
+#[derive(Clone, Copy, PartialEq)]
+enum Allowed {
+    // spaces and tabs
+    Blanks,
+    // spaces, tabs and line endings
+    LineEndings,
+    // whole lines: spaces, tabs, line endings and comments that end with a line ending
+    Lines,
+    // the end of a line: spaces and tabs, then a comment that runs to the end of the line
+    EndOfLine,
+}
+
+impl Place {
+    fn name(self) -> &'static str {
+        match self {
+            Place::Key => "key",
+            Place::Header => "table header",
+            Place::Value => "value",
+            Place::ArrayElement => "value in an array",
+            Place::Inline => "key or value in an inline table",
+        }
+    }
+
+    // what the prefix and the suffix can hold
+    fn allowed(self) -> (Allowed, Allowed) {
+        match self {
+            Place::Key => (Allowed::Lines, Allowed::Blanks),
+            Place::Header => (Allowed::Lines, Allowed::EndOfLine),
+            Place::Value => (Allowed::Blanks, Allowed::EndOfLine),
+            Place::ArrayElement => (Allowed::LineEndings, Allowed::LineEndings),
+            Place::Inline => (Allowed::Blanks, Allowed::Blanks),
+        }
+    }
+}
+
+// replace the prefix and suffix of a decor, checking they can go where it sits
+pub fn set(decor: &mut Decor, place: Place, prefix: &str, suffix: &str) -> Result<()> {
+    let (prefix_allowed, suffix_allowed) = place.allowed();
+    check(prefix, "prefix", place, prefix_allowed)?;
+    check(suffix, "suffix", place, suffix_allowed)?;
+
+    decor.set_prefix(prefix);
+    decor.set_suffix(suffix);
+    Ok(())
+}
+
+// clear a decor that can't go where it is going (e.g. that of a value set on its own, with a
+// comment after it, moving into an InlineTable), so it is written in the default style instead
+pub fn fit(decor: &mut Decor, place: Place) {
+    let (prefix, suffix) = get(decor);
+    let (prefix_allowed, suffix_allowed) = place.allowed();
+    if check(&prefix, "prefix", place, prefix_allowed).is_err()
+        || check(&suffix, "suffix", place, suffix_allowed).is_err()
+    {
+        decor.clear();
+    }
+}
+
+// check a prefix or suffix holds only what is allowed where it sits, so no comment swallows the
+// TOML after it and nothing else is mistaken for TOML
+fn check(text: &str, what: &str, place: Place, allowed: Allowed) -> Result<()> {
+    let invalid = |reason: &str| {
+        Error::new(
+            ErrorCode::InvalidValue,
+            format!(
+                "Invalid {} of a {} ({}): {:?}",
+                what,
+                place.name(),
+                reason,
+                text
+            ),
+        )
+    };
+
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        match c {
+            ' ' | '\t' => rest = &rest[1..],
+            '\n' | '\r' if matches!(allowed, Allowed::Blanks | Allowed::EndOfLine) => {
+                return Err(invalid("a line ending can't go here"))
+            }
+            '\n' => rest = &rest[1..],
+            '\r' if rest.starts_with("\r\n") => rest = &rest[2..],
+            '#' if matches!(allowed, Allowed::Blanks | Allowed::LineEndings) => {
+                return Err(invalid("a comment can't go here"))
+            }
+            '#' => {
+                let line = match (rest.find('\n'), allowed) {
+                    (Some(end), Allowed::Lines) => &rest[..end],
+                    (None, Allowed::EndOfLine) => rest,
+                    (None, _) => return Err(invalid("a comment must end with a line ending")),
+                    (Some(_), _) => {
+                        return Err(invalid("a comment here must be the end of the line"))
+                    }
+                };
+                let comment = line.strip_suffix('\r').unwrap_or(line);
+                if comment.chars().any(|c| c.is_control() && c != '\t') {
+                    return Err(invalid("control character in a comment"));
+                }
+                rest = &rest[line.len()..];
+            }
+            _ => return Err(invalid("only whitespace and `#` comments are allowed")),
+        }
+    }
+
+    Ok(())
+}
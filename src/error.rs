@@ -0,0 +1,269 @@
+// Error codes and the thread-local "last error" that every exported function reports into.
+//
+// Each `extern "C"` function returns an `i32` status code (0 on success) and, on failure,
+// records the code, a message, and its own name so LabVIEW can build an error cluster by
+// calling `toml_edit_last_error` straight afterwards.
+
+use labview_interop::types::LStrHandle;
+
+use std::{
+    any::Any,
+    cell::RefCell,
+    fmt,
+    ops::Range,
+    panic::{self, AssertUnwindSafe},
+};
+
+// status codes returned by the exported functions
+// (LabVIEW reserves 5000 to 9999 for user-defined error codes)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum ErrorCode {
+    Success = 0,
+    NullPointer = 5001,
+    InvalidString = 5002,
+    WrongType = 5003,
+    NotFound = 5004,
+    ParseError = 5005,
+    InvalidReference = 5006,
+    TooManyReferences = 5007,
+    InvalidPath = 5008,
+    InvalidValue = 5009,
+    LabviewMemory = 5010,
+    FileNotFound = 5011,
+    PermissionDenied = 5012,
+    FileError = 5013,
+    LockTimeout = 5014,
+    KeyExists = 5015,
+    Panic = 5099,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Error {
+            code,
+            message: message.into(),
+        }
+    }
+
+    // a required object reference or output pointer was null
+    pub fn null(what: &str) -> Self {
+        Error::new(ErrorCode::NullPointer, format!("{} is null", what))
+    }
+
+    // an object was not of the expected type, e.g. an Item that is not a Table
+    pub fn wrong_type(what: &str, expected: &str) -> Self {
+        Error::new(
+            ErrorCode::WrongType,
+            format!("{} is not a {}", what, expected),
+        )
+    }
+
+    // a reference was closed already, or was never returned by this library
+    pub fn invalid_reference(what: &str) -> Self {
+        Error::new(
+            ErrorCode::InvalidReference,
+            format!(
+                "{} reference is invalid (closed, or not from this library)",
+                what
+            ),
+        )
+    }
+
+    // a reference to one type of object was passed where another type was expected
+    pub fn wrong_reference_type(expected: &str, actual: &str) -> Self {
+        Error::new(
+            ErrorCode::WrongType,
+            format!(
+                "{} reference expected, but got a {} reference",
+                expected, actual
+            ),
+        )
+    }
+
+    // an index is past the end of an array
+    pub fn out_of_range(index: i32, len: usize) -> Self {
+        Error::new(
+            ErrorCode::NotFound,
+            format!("Index {} is out of range (length {})", index, len),
+        )
+    }
+
+    // a key (or table) does not exist
+    pub fn not_found(what: &str, key: &str) -> Self {
+        Error::new(ErrorCode::NotFound, format!("{} not found: {}", what, key))
+    }
+
+    // a key can't be added (or renamed to) because the table already has it
+    pub fn key_exists(key: &str) -> Self {
+        Error::new(ErrorCode::KeyExists, format!("Key already exists: {}", key))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (code {})", self.message, self.code as i32)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<toml_edit::TomlError> for Error {
+    fn from(error: toml_edit::TomlError) -> Self {
+        Error::new(ErrorCode::ParseError, error.to_string())
+    }
+}
+
+// the location of a TOML parse error, broken out so LabVIEW can highlight it in the source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseErrorDetails {
+    pub message: String,
+    // 1-based line number, or 0 if the parser didn't report a location
+    pub line: usize,
+    // 1-based column, counted in characters (not bytes) from the start of the line
+    pub column: usize,
+    // byte offsets into the UTF-8 text
+    pub span: Range<usize>,
+    // the full text of the offending line, without its line ending
+    pub source_line: String,
+}
+
+impl ParseErrorDetails {
+    pub fn new(source: &str, error: &toml_edit::TomlError) -> Self {
+        let message = match error.message().trim() {
+            "" => "TOML parse error".to_string(),
+            message => message.to_string(),
+        };
+
+        let span = match error.span() {
+            Some(span) => span,
+            None => {
+                return ParseErrorDetails {
+                    message,
+                    line: 0,
+                    column: 0,
+                    span: 0..0,
+                    source_line: String::new(),
+                }
+            }
+        };
+
+        // the span can point just past the end of the text (e.g. an unexpected EOF)
+        let start = span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |index| index + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |index| start + index);
+
+        ParseErrorDetails {
+            message,
+            line: source[..start].matches('\n').count() + 1,
+            column: source[line_start..start].chars().count() + 1,
+            span,
+            source_line: source[line_start..line_end]
+                .trim_end_matches('\r')
+                .to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LastError {
+    pub code: i32,
+    pub message: String,
+    pub function: String,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<LastError>> = const { RefCell::new(None) };
+}
+
+// record (or clear) the error of the most recent call on this thread
+pub fn set_last_error(function: &str, error: Option<&Error>) {
+    LAST_ERROR.with(|last_error| {
+        *last_error.borrow_mut() = error.map(|error| LastError {
+            code: error.code as i32,
+            message: error.message.clone(),
+            function: function.to_string(),
+        });
+    });
+}
+
+// the error of the most recent call on this thread, if it failed
+pub fn last_error() -> Option<LastError> {
+    LAST_ERROR.with(|last_error| last_error.borrow().clone())
+}
+
+// describe a caught panic using its payload (the message passed to `panic!`, `expect`, etc.)
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    let detail = if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.as_str()
+    } else {
+        "unknown cause"
+    };
+
+    format!("Internal error (panic): {}", detail)
+}
+
+// run the body of an exported function, recording its outcome as the last error
+// and converting it to the status code returned to LabVIEW
+//
+// a panic must never unwind into LabVIEW (it takes down the whole process), so any
+// panic in `body` is caught here and reported like any other error
+pub fn call(function: &str, body: impl FnOnce() -> Result<()>) -> i32 {
+    let result = panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|payload| {
+        Err(Error::new(
+            ErrorCode::Panic,
+            panic_message(payload.as_ref()),
+        ))
+    });
+
+    match result {
+        Ok(()) => {
+            set_last_error(function, None);
+            ErrorCode::Success as i32
+        }
+        Err(error) => {
+            set_last_error(function, Some(&error));
+            error.code as i32
+        }
+    }
+}
+
+// return the code, message and function name of the last failed call on this thread
+// code is 0 and both strings are empty if the last call succeeded
+#[no_mangle]
+pub extern "C" fn toml_edit_last_error(
+    code: *mut i32,
+    message: LStrHandle,
+    function: LStrHandle,
+) -> i32 {
+    let last_error = last_error().unwrap_or(LastError {
+        code: ErrorCode::Success as i32,
+        message: String::new(),
+        function: String::new(),
+    });
+
+    // reading the last error must not overwrite it, so this doesn't go through `call`
+    let result = panic::catch_unwind(|| -> Result<()> {
+        *crate::ffi::out(code, "Code output")? = last_error.code;
+        crate::ffi::write_str(message, &last_error.message)?;
+        crate::ffi::write_str(function, &last_error.function)
+    });
+
+    match result {
+        Ok(Ok(())) => ErrorCode::Success as i32,
+        Ok(Err(error)) => error.code as i32,
+        Err(_) => ErrorCode::Panic as i32,
+    }
+}
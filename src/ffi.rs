@@ -0,0 +1,253 @@
+// Helpers for moving data across the LabVIEW <-> Rust boundary without panicking.
+
+use dlopen2::wrapper::{Container, WrapperApi};
+use labview_interop::memory::UHandle;
+use labview_interop::types::{LStrHandle, LVArrayHandle, LVBool, LVTime};
+use std::sync::OnceLock;
+
+use crate::error::{Error, ErrorCode, Result};
+
+// borrow an output parameter, so it can be written to
+pub fn out<'a, T>(ptr: *mut T, what: &str) -> Result<&'a mut T> {
+    unsafe { ptr.as_mut() }.ok_or_else(|| Error::null(what))
+}
+
+// read a LabVIEW string into a Rust string
+pub fn read_str(handle: LStrHandle) -> Result<String> {
+    let string = unsafe { handle.as_ref() }
+        .map_err(|error| Error::new(ErrorCode::InvalidString, error.to_string()))?;
+    Ok(string.to_rust_string().into_owned())
+}
+
+// read the bytes of a LabVIEW string, such as flattened data, as they are
+pub fn read_bytes(handle: LStrHandle) -> Result<Vec<u8>> {
+    let string = unsafe { handle.as_ref() }
+        .map_err(|error| Error::new(ErrorCode::InvalidString, error.to_string()))?;
+    Ok(string.as_slice().to_vec())
+}
+
+// write a Rust string into a LabVIEW string handle
+pub fn write_str(mut handle: LStrHandle, value: &str) -> Result<()> {
+    handle
+        .set_str(value)
+        .map_err(|error| Error::new(ErrorCode::InvalidString, error.to_string()))
+}
+
+// read a LabVIEW timestamp passed by pointer
+// (LabVIEW only aligns a timestamp to 8 bytes, but `LVTime` is 16-byte aligned, so it is copied)
+pub fn read_time(ptr: *const LVTime, what: &str) -> Result<LVTime> {
+    if ptr.is_null() {
+        return Err(Error::null(what));
+    }
+    Ok(unsafe { ptr.read_unaligned() })
+}
+
+// write a LabVIEW timestamp to an output parameter
+pub fn write_time(ptr: *mut LVTime, time: LVTime, what: &str) -> Result<()> {
+    if ptr.is_null() {
+        return Err(Error::null(what));
+    }
+    unsafe { ptr.write_unaligned(time) };
+    Ok(())
+}
+
+// the LabVIEW memory manager functions that `labview-interop` doesn't wrap, found in the
+// LabVIEW process that loaded this library
+#[derive(WrapperApi)]
+struct MemoryManager {
+    // resizes (or allocates, if the handle is null) an array handle for `size` elements
+    #[dlopen2_name = "NumericArrayResize"]
+    numeric_array_resize:
+        unsafe extern "C" fn(type_code: i32, dims: i32, handle: *mut usize, size: usize) -> i32,
+    #[dlopen2_name = "DSDisposeHandle"]
+    dispose_handle: unsafe extern "C" fn(handle: usize) -> i32,
+}
+
+fn memory_manager() -> Result<&'static Container<MemoryManager>> {
+    static MEMORY_MANAGER: OnceLock<Option<Container<MemoryManager>>> = OnceLock::new();
+
+    MEMORY_MANAGER
+        .get_or_init(|| unsafe { Container::load_self() }.ok())
+        .as_ref()
+        .ok_or_else(|| {
+            Error::new(
+                ErrorCode::LabviewMemory,
+                "The LabVIEW memory manager is unavailable (not running in LabVIEW)",
+            )
+        })
+}
+
+fn memory_error(function: &str, code: i32) -> Error {
+    Error::new(
+        ErrorCode::LabviewMemory,
+        format!("{} failed with LabVIEW error {}", function, code),
+    )
+}
+
+// an element of a 1D LabVIEW array, with the type code that `NumericArrayResize` lays it out by
+pub trait ArrayElement {
+    const TYPE_CODE: i32;
+}
+
+impl ArrayElement for i16 {
+    const TYPE_CODE: i32 = 0x02; // iW
+}
+
+impl ArrayElement for i64 {
+    const TYPE_CODE: i32 = 0x04; // iQ
+}
+
+impl ArrayElement for f64 {
+    const TYPE_CODE: i32 = 0x0A; // fD
+}
+
+impl ArrayElement for LVBool {
+    const TYPE_CODE: i32 = 0x05; // uB
+}
+
+// an array of strings is an array of pointer-sized handles
+impl ArrayElement for LStrHandle {
+    #[cfg(target_pointer_width = "64")]
+    const TYPE_CODE: i32 = 0x08; // uQ
+    #[cfg(target_pointer_width = "32")]
+    const TYPE_CODE: i32 = 0x07; // uL
+}
+
+// the offset of the data in a 1D array, after the i32 length
+// (64-bit LabVIEW aligns the data to its element size, 32-bit LabVIEW packs it)
+fn data_offset<T>() -> usize {
+    match cfg!(target_pointer_width = "64") {
+        true => std::mem::align_of::<T>().max(4),
+        false => 4,
+    }
+}
+
+// the memory block a handle points to (null for a null handle, which LabVIEW can pass for an
+// empty array)
+//
+// the handle is read as a plain double pointer, as LabVIEW passes it, rather than through
+// `LVArray`, so this also works for 32-bit LabVIEW's packed layout
+fn array_block<T>(handle: &LVArrayHandle<1, T>) -> *mut u8 {
+    let handle = handle.0 as *mut *mut u8;
+    match handle.is_null() {
+        true => std::ptr::null_mut(),
+        false => unsafe { *handle },
+    }
+}
+
+// the number of elements in a LabVIEW array
+fn array_len<T>(handle: &LVArrayHandle<1, T>) -> usize {
+    let block = array_block(handle);
+    match block.is_null() {
+        true => 0,
+        false => unsafe { (block as *const i32).read_unaligned() }.max(0) as usize,
+    }
+}
+
+// a pointer to the first element of a (non-null) LabVIEW array
+fn array_data<T: ArrayElement>(handle: &LVArrayHandle<1, T>) -> *mut T {
+    unsafe { array_block(handle).add(data_offset::<T>()) as *mut T }
+}
+
+// read a 1D LabVIEW array into a Vec
+pub fn read_array<T: ArrayElement>(handle: &LVArrayHandle<1, T>) -> Vec<T> {
+    let data = array_data(handle);
+    (0..array_len(handle))
+        .map(|index| unsafe { data.add(index).read_unaligned() })
+        .collect()
+}
+
+// resize a 1D LabVIEW array (passed as a pointer to its handle, so an empty array's null
+// handle can be replaced) and set its length
+// the elements are left as they were, so any new ones must be written by the caller
+fn resize_array<T: ArrayElement>(handle: &mut LVArrayHandle<1, T>, len: usize) -> Result<()> {
+    let code = unsafe {
+        (memory_manager()?.numeric_array_resize)(
+            T::TYPE_CODE,
+            1,
+            handle as *mut LVArrayHandle<1, T> as *mut usize,
+            len,
+        )
+    };
+    if code != 0 {
+        return Err(memory_error("NumericArrayResize", code));
+    }
+
+    let len = i32::try_from(len).map_err(|_| memory_error("NumericArrayResize", -1))?;
+    unsafe { (array_block(handle) as *mut i32).write_unaligned(len) };
+    Ok(())
+}
+
+// write a slice into a 1D LabVIEW array, resizing it to fit
+pub fn write_array<T: ArrayElement + Copy>(
+    handle: &mut LVArrayHandle<1, T>,
+    values: &[T],
+) -> Result<()> {
+    resize_array(handle, values.len())?;
+
+    let data = array_data(handle);
+    for (index, value) in values.iter().enumerate() {
+        unsafe { data.add(index).write_unaligned(*value) };
+    }
+    Ok(())
+}
+
+// read a 1D LabVIEW array of strings (a null element is an empty string)
+pub fn read_str_array(handle: &LVArrayHandle<1, LStrHandle>) -> Result<Vec<String>> {
+    read_array(handle)
+        .into_iter()
+        .map(|string| match string.0.is_null() {
+            true => Ok(String::new()),
+            false => read_str(string),
+        })
+        .collect()
+}
+
+// write strings into a 1D LabVIEW array of strings, resizing it to fit
+pub fn write_str_array(
+    handle: &mut LVArrayHandle<1, LStrHandle>,
+    values: &[impl AsRef<str>],
+) -> Result<()> {
+    let memory_manager = memory_manager()?;
+    let old_len = array_len(handle);
+
+    // free the strings that won't fit, before they are cut off
+    for index in values.len()..old_len {
+        let string = unsafe { array_data(handle).add(index).read_unaligned() };
+        if !string.0.is_null() {
+            unsafe { (memory_manager.dispose_handle)(string.0 as usize) };
+        }
+    }
+
+    resize_array(handle, values.len())?;
+    let data = array_data(handle);
+
+    for (index, value) in values.iter().enumerate() {
+        let element = unsafe { data.add(index) };
+
+        // new elements hold whatever was in memory, so start them as null (empty) strings
+        if index >= old_len {
+            unsafe { element.write_unaligned(UHandle(std::ptr::null_mut())) };
+        }
+
+        // a null string must be allocated before it can be written
+        let mut string = unsafe { element.read_unaligned() };
+        if string.0.is_null() {
+            let code = unsafe {
+                (memory_manager.numeric_array_resize)(
+                    0x05, // uB
+                    1,
+                    &mut string as *mut LStrHandle as *mut usize,
+                    0,
+                )
+            };
+            if code != 0 {
+                return Err(memory_error("NumericArrayResize", code));
+            }
+            unsafe { element.write_unaligned(UHandle(string.0)) };
+        }
+
+        write_str(string, value.as_ref())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,399 @@
+// Reading and writing TOML files, so LabVIEW doesn't have to handle the text itself.
+//
+// `toml_edit` always writes `\n` line endings, so a Document read from a file that used `\r\n`
+// (judged by its first line ending) is saved with `\r\n` too, wherever it is saved. A UTF-8
+// byte order mark isn't valid TOML, so it is skipped when reading, and written back when saving
+// a Document read from a file that had one. A Document that wasn't read from a file takes the
+// layout of the file it is saved over, if there is one.
+//
+// A file is saved by writing a temporary file next to it, flushing it to disk and renaming it
+// over the original, so a power cut mid-save leaves either the old file or the new one, never
+// a truncated mix of the two.
+//
+// Programs sharing a file can coordinate with an advisory lock. The file itself is replaced on
+// every save (which would drop a lock held on it), so the lock is taken on `<path>.lock` next
+// to it instead. The lock file is left in place afterwards, as deleting it could let two
+// programs lock different files of the same name.
+
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions, TryLockError};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use toml_edit::{Document, Item, Table};
+
+use crate::array;
+use crate::error::{Error, ErrorCode, Result};
+use crate::handle::Handle;
+
+const BOM: &str = "\u{feff}";
+
+// how a file is laid out, so saving what was read from it keeps the same layout
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Format {
+    bom: bool,
+    crlf: bool,
+}
+
+impl Format {
+    fn of(text: &str) -> Self {
+        Format {
+            bom: text.starts_with(BOM),
+            crlf: text
+                .find('\n')
+                .is_some_and(|newline| text[..newline].ends_with('\r')),
+        }
+    }
+
+    // lay out text written by `toml_edit` (which only uses `\n`) in this format
+    // a `\r\n` already in the text (e.g. one set in a comment) is left as it is
+    fn apply(self, text: &str) -> String {
+        let text = match self.crlf {
+            true => {
+                let mut crlf = String::with_capacity(text.len());
+                let mut after_cr = false;
+                for c in text.chars() {
+                    if c == '\n' && !after_cr {
+                        crlf.push('\r');
+                    }
+                    crlf.push(c);
+                    after_cr = c == '\r';
+                }
+                crlf
+            }
+            false => text.to_string(),
+        };
+        match self.bom {
+            true => format!("{}{}", BOM, text),
+            false => text,
+        }
+    }
+}
+
+// the format of each open Document that was read from a file, by its reference
+static FORMATS: Mutex<BTreeMap<Handle, Format>> = Mutex::new(BTreeMap::new());
+
+fn formats() -> std::sync::MutexGuard<'static, BTreeMap<Handle, Format>> {
+    FORMATS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// remember the format of the file a Document was read from, to save it the same way
+pub fn remember(doc: Handle, format: Format) {
+    formats().insert(doc, format);
+}
+
+// forget the format of a Document that has been closed
+pub fn forget(doc: Handle) {
+    formats().remove(&doc);
+}
+
+// the format of the file a Document was read from, if it was read from one
+pub fn format_of(doc: Handle) -> Option<Format> {
+    formats().get(&doc).copied()
+}
+
+// the error for a failed file operation, with the common failures given their own codes
+pub fn io_error(action: &str, path: &Path, error: io::Error) -> Error {
+    let path = path.display();
+
+    match error.kind() {
+        io::ErrorKind::NotFound => Error::new(
+            ErrorCode::FileNotFound,
+            format!("Could not {} {}: file or directory not found", action, path),
+        ),
+        io::ErrorKind::PermissionDenied => Error::new(
+            ErrorCode::PermissionDenied,
+            format!("Could not {} {}: permission denied", action, path),
+        ),
+        _ => Error::new(
+            ErrorCode::FileError,
+            format!("Could not {} {}: {}", action, path, error),
+        ),
+    }
+}
+
+// read a file's text, without any byte order mark, and its format
+fn read(path: &Path) -> Result<(String, Format)> {
+    let bytes = fs::read(path).map_err(|error| io_error("read", path, error))?;
+
+    let text = String::from_utf8(bytes).map_err(|error| {
+        Error::new(
+            ErrorCode::ParseError,
+            format!(
+                "Could not parse {}: not valid UTF-8 (at byte {})",
+                path.display(),
+                error.utf8_error().valid_up_to()
+            ),
+        )
+    })?;
+
+    let format = Format::of(&text);
+    let text = match text.strip_prefix(BOM) {
+        Some(text) => text.to_string(),
+        None => text,
+    };
+    Ok((text, format))
+}
+
+// parse a TOML file, returning its format too
+pub fn open(path: &Path) -> Result<(Document, Format)> {
+    let (text, format) = read(path)?;
+
+    let doc = Document::from_str(&text).map_err(|error| {
+        Error::new(
+            ErrorCode::ParseError,
+            format!("Could not parse {}: {}", path.display(), error),
+        )
+    })?;
+    Ok((doc, format))
+}
+
+// the text to save to `path`, laid out in `format` (that of the file the Document was read from)
+// or, failing that, like the file already there (if there is one)
+fn text_for(path: &Path, toml: &str, format: Option<Format>) -> String {
+    // a file that can't be read is about to be replaced anyway, so it has no format to keep
+    let format = format.unwrap_or_else(|| match fs::read(path) {
+        Ok(bytes) => Format::of(&String::from_utf8_lossy(&bytes)),
+        Err(_) => Format::default(),
+    });
+    format.apply(toml)
+}
+
+// the name of the `n`th backup of a file: `config.toml.bak`, then `config.toml.bak.1`, ...
+fn backup_path(path: &Path, n: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".bak");
+    if n > 0 {
+        name.push(format!(".{}", n));
+    }
+    PathBuf::from(name)
+}
+
+// copy the current file to `.bak`, after moving each older backup along one, dropping the oldest
+fn back_up(path: &Path, backups: u32) -> Result<()> {
+    if backups == 0 || !path.exists() {
+        return Ok(());
+    }
+
+    for n in (0..backups - 1).rev() {
+        let from = backup_path(path, n);
+        if from.exists() {
+            let to = backup_path(path, n + 1);
+            fs::rename(&from, &to).map_err(|error| io_error("rotate backup", &from, error))?;
+        }
+    }
+
+    let backup = backup_path(path, 0);
+    fs::copy(path, &backup).map_err(|error| io_error("back up to", &backup, error))?;
+    Ok(())
+}
+
+// a temporary file in the same directory as `path` (so it can be renamed over it), which
+// doesn't already exist
+fn temp_path(path: &Path) -> PathBuf {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(
+        ".{}.{}.{}.tmp",
+        name,
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+// write the text to a new file and flush it all the way to disk
+fn write_synced(path: &Path, text: &str, like: Option<fs::Permissions>) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    file.write_all(text.as_bytes())?;
+
+    // the new file replaces the old one, so it gets the old one's permissions
+    if let Some(permissions) = like {
+        file.set_permissions(permissions)?;
+    }
+    file.sync_all()
+}
+
+// write a Document's text to a file, replacing anything already there, laid out in the format
+// of the file it was read from (see `text_for`)
+// the file is replaced in one step (see the top of this module), after copying the old file to
+// `.bak` if `backups` is more than 0, keeping up to that many backups
+pub fn save(path: &Path, toml: &str, format: Option<Format>, backups: u32) -> Result<()> {
+    let text = text_for(path, toml, format);
+    let permissions = fs::metadata(path)
+        .ok()
+        .map(|metadata| metadata.permissions());
+
+    let temp = temp_path(path);
+    if let Err(error) = write_synced(&temp, &text, permissions) {
+        let _ = fs::remove_file(&temp);
+        return Err(io_error("write", path, error));
+    }
+
+    let replaced = back_up(path, backups)
+        .and_then(|_| fs::rename(&temp, path).map_err(|error| io_error("write", path, error)));
+    if replaced.is_err() {
+        let _ = fs::remove_file(&temp);
+        return replaced;
+    }
+
+    // make the rename itself durable (only possible on Unix, where a directory can be opened)
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        let dir = match dir.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => dir,
+        };
+        let _ = fs::File::open(dir).and_then(|dir| dir.sync_all());
+    }
+
+    Ok(())
+}
+
+// an advisory lock on a file, released when this is dropped
+pub struct FileLock {
+    _file: fs::File,
+    // the file locked (not the `.lock` file), and whether no one else can hold the lock
+    path: PathBuf,
+    exclusive: bool,
+}
+
+// how long to wait between attempts to take a lock
+const LOCK_POLL: Duration = Duration::from_millis(10);
+
+// lock a file, shared (for reading) or exclusive (for writing), waiting up to `timeout` for
+// other programs to release it (or forever if `timeout` is `None`)
+pub fn lock(path: &Path, exclusive: bool, timeout: Option<Duration>) -> Result<FileLock> {
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let lock_path = PathBuf::from(lock_path);
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)
+        .map_err(|error| io_error("lock", &lock_path, error))?;
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    loop {
+        let locked = match exclusive {
+            true => file.try_lock(),
+            false => file.try_lock_shared(),
+        };
+
+        match locked {
+            Ok(()) => {
+                return Ok(FileLock {
+                    _file: file,
+                    path: path.to_path_buf(),
+                    exclusive,
+                })
+            }
+            Err(TryLockError::WouldBlock) => {}
+            Err(TryLockError::Error(error)) => return Err(io_error("lock", &lock_path, error)),
+        }
+
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Err(Error::new(
+                ErrorCode::LockTimeout,
+                format!(
+                    "Timed out waiting for another program to unlock {}",
+                    path.display()
+                ),
+            ));
+        }
+        thread::sleep(LOCK_POLL);
+    }
+}
+
+// parse a file to change it, starting an empty Document if it doesn't exist yet
+fn open_or_new(path: &Path) -> Result<(Document, Option<Format>)> {
+    match open(path) {
+        Err(error) if error.code == ErrorCode::FileNotFound && !path.exists() => {
+            Ok((Document::new(), None))
+        }
+        result => result.map(|(doc, format)| (doc, Some(format))),
+    }
+}
+
+// take an exclusive lock on a file and parse it (or start an empty Document if it doesn't exist
+// yet), so it can be changed and saved with `save_locked` before anyone else can change it
+pub fn lock_open(
+    path: &Path,
+    timeout: Option<Duration>,
+) -> Result<(FileLock, Document, Option<Format>)> {
+    let lock = lock(path, true, timeout)?;
+    let (doc, format) = open_or_new(path)?;
+    Ok((lock, doc, format))
+}
+
+// save a Document's text to the file an exclusive lock is held on (see `save`)
+pub fn save_locked(lock: &FileLock, toml: &str, format: Option<Format>) -> Result<()> {
+    if !lock.exclusive {
+        return Err(Error::new(
+            ErrorCode::InvalidValue,
+            format!(
+                "Could not save {}: the lock on it is shared, and saving needs an exclusive lock",
+                lock.path.display()
+            ),
+        ));
+    }
+    save(&lock.path, toml, format, 0)
+}
+
+// copy every key in `edits` into `table`, merging tables that are in both and replacing
+// anything else (keeping the position and comments of an existing value)
+// new tables are written at `position` in the document
+fn merge(table: &mut Table, edits: &Table, position: usize) {
+    for (key, edit) in edits.iter() {
+        let mut edit = edit.clone();
+        match &mut edit {
+            Item::Table(table) => array::set_position(table, position),
+            Item::ArrayOfTables(array) => {
+                for table in array.iter_mut() {
+                    array::set_position(table, position)
+                }
+            }
+            _ => {}
+        }
+
+        match (table.get_mut(key), edit) {
+            (Some(Item::Table(existing)), Item::Table(edit)) => merge(existing, &edit, position),
+            (Some(Item::Value(existing)), Item::Value(mut edit)) => {
+                *edit.decor_mut() = existing.decor().clone();
+                *existing = edit;
+            }
+            (Some(existing), edit) => *existing = edit,
+            (None, edit) => {
+                table.insert(key, edit);
+            }
+        }
+    }
+}
+
+// reload a file under an exclusive lock, merge `edits` into it (see `merge`), and save it
+// before anyone else can change it, returning the updated Document
+// a merge can only add keys or replace them; to remove or rename one, use `lock_open` and
+// `save_locked` and make the changes in between
+pub fn update(
+    path: &Path,
+    edits: &Document,
+    timeout: Option<Duration>,
+) -> Result<(Document, Option<Format>)> {
+    let (_lock, mut doc, format) = lock_open(path, timeout)?;
+
+    // anything new goes after everything already in the file
+    let position = array::last_position(doc.as_table()).map_or(0, |position| position + 1);
+    merge(doc.as_table_mut(), edits.as_table(), position);
+    save(path, &doc.to_string(), format, 0)?;
+    Ok((doc, format))
+}
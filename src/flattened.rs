@@ -0,0 +1,492 @@
+// Building TOML from LabVIEW data flattened with Flatten To String, read by its type descriptor
+// (the type string from Variant To Flattened String), so a whole cluster is written in one call.
+//
+// A type descriptor is a tree of big-endian records, one for each type: its length in bytes
+// (u16), its flags (u8, where 0x40 means the record ends with a label) and its type code (u8),
+// then whatever that type needs (such as the types of a cluster's elements), then the label as
+// a Pascal string. Flattened data is big-endian too, with the length of each string and the
+// size of each array dimension in front of it (as Flatten To String writes it by default).
+
+use labview_interop::types::LVTime;
+use toml_edit::{Array, InlineTable, Item, Table, Value};
+
+use crate::datetime::{self, DatetimeKind};
+use crate::error::{Error, ErrorCode, Result};
+use crate::path::{self, Step};
+
+// the flag of a type descriptor that ends with a label
+const HAS_LABEL: u8 = 0x40;
+
+// the flavor of a measure data type descriptor (0x54) for a timestamp
+const TIMESTAMP: u16 = 6;
+
+// how deep arrays and clusters can be nested in a type descriptor (reading each level takes
+// stack, and a stack overflow would take LabVIEW down with it)
+const MAX_DEPTH: usize = 64;
+
+// how many elements an array of elements that take no bytes (such as empty clusters) can have,
+// as the data left can't limit it
+const MAX_EMPTY_ELEMENTS: usize = 1 << 20;
+
+// a LabVIEW type, as a type descriptor describes it
+enum Type {
+    Integer {
+        size: usize,
+        signed: bool,
+    },
+    Float {
+        size: usize,
+    },
+    Boolean,
+    String,
+    Path,
+    Enum {
+        size: usize,
+        items: Vec<String>,
+    },
+    Timestamp,
+    Array {
+        dimensions: usize,
+        element: Box<Descriptor>,
+    },
+    Cluster(Vec<Descriptor>),
+}
+
+struct Descriptor {
+    label: Option<String>,
+    kind: Type,
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorCode::InvalidValue, message)
+}
+
+// where a value is, for error messages
+fn describe(path: &[Step]) -> String {
+    match path.is_empty() {
+        true => "the cluster".to_string(),
+        false => path::display(path),
+    }
+}
+
+// text from LabVIEW, which must be UTF-8
+fn text(bytes: &[u8], what: impl FnOnce() -> String) -> Result<String> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| {
+        Error::new(
+            ErrorCode::InvalidString,
+            format!("Not UTF-8 text: {}", what()),
+        )
+    })
+}
+
+// reads big-endian numbers and Pascal strings from flattened bytes
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+    what: &'static str,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8], what: &'static str) -> Self {
+        Reader { bytes, at: 0, what }
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8]> {
+        let end = self
+            .at
+            .checked_add(count)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| {
+                invalid(format!(
+                    "{} ends early, at byte {} of {}",
+                    self.what,
+                    self.at,
+                    self.bytes.len()
+                ))
+            })?;
+
+        let bytes = &self.bytes[self.at..end];
+        self.at = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().expect("took N bytes"))
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    fn i64(&mut self) -> Result<i64> {
+        Ok(i64::from_be_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.array()?))
+    }
+
+    // the length of a string or the size of an array dimension
+    fn size(&mut self, path: &[Step]) -> Result<usize> {
+        let size = i32::from_be_bytes(self.array()?);
+        usize::try_from(size)
+            .map_err(|_| invalid(format!("Negative size {}: {}", size, describe(path))))
+    }
+
+    fn pascal(&mut self) -> Result<&'a [u8]> {
+        let length = self.u8()? as usize;
+        self.take(length)
+    }
+}
+
+// read a type descriptor and every type descriptor inside it, `depth` levels inside the first
+fn descriptor(reader: &mut Reader, depth: usize) -> Result<Descriptor> {
+    let start = reader.at;
+    if depth > MAX_DEPTH {
+        return Err(invalid(format!(
+            "Type descriptor at byte {} is nested more than {} deep",
+            start, MAX_DEPTH
+        )));
+    }
+    let length = reader.u16()? as usize;
+    let flags = reader.u8()?;
+    let code = reader.u8()?;
+
+    let end = start + length;
+    if length < 4 || end > reader.bytes.len() {
+        return Err(invalid(format!(
+            "Type descriptor at byte {} has an invalid length {}",
+            start, length
+        )));
+    }
+    let unsupported = |what: &str| {
+        invalid(format!(
+            "Type descriptor at byte {} is of an unsupported type ({})",
+            start, what
+        ))
+    };
+
+    let kind = match code {
+        0x01..=0x04 => Type::Integer {
+            size: 1 << (code - 0x01),
+            signed: true,
+        },
+        0x05..=0x08 => Type::Integer {
+            size: 1 << (code - 0x05),
+            signed: false,
+        },
+        0x09 => Type::Float { size: 4 },
+        0x0A => Type::Float { size: 8 },
+        0x15..=0x17 => {
+            let count = reader.u16()?;
+            let items = (0..count)
+                .map(|index| text(reader.pascal()?, || format!("enum item {}", index)))
+                .collect::<Result<_>>()?;
+            Type::Enum {
+                size: 1 << (code - 0x15),
+                items,
+            }
+        }
+        0x21 => Type::Boolean,
+        0x30 | 0x32 => {
+            // the size of the string or path, which is always variable (-1)
+            reader.u32()?;
+            match code {
+                0x30 => Type::String,
+                _ => Type::Path,
+            }
+        }
+        0x40 => {
+            let dimensions = reader.u16()? as usize;
+            if dimensions == 0 {
+                return Err(unsupported("array of no dimensions"));
+            }
+            reader.take(dimensions * 4)?;
+            Type::Array {
+                dimensions,
+                element: Box::new(descriptor(reader, depth + 1)?),
+            }
+        }
+        0x50 => {
+            let count = reader.u16()?;
+            let elements = (0..count)
+                .map(|_| descriptor(reader, depth + 1))
+                .collect::<Result<_>>()?;
+            Type::Cluster(elements)
+        }
+        0x54 => match reader.u16()? {
+            TIMESTAMP => Type::Timestamp,
+            flavor => return Err(unsupported(&format!("waveform flavor {}", flavor))),
+        },
+        0x0B => return Err(unsupported("EXT")),
+        0x0C..=0x0E => return Err(unsupported("complex")),
+        code => return Err(unsupported(&format!("type code 0x{:02X}", code))),
+    };
+
+    let label = match flags & HAS_LABEL {
+        0 => None,
+        _ => Some(label(reader, end)?),
+    };
+    reader.at = end;
+    Ok(Descriptor { label, kind })
+}
+
+// the label that ends a type descriptor, which may come after a byte of padding
+fn label(reader: &mut Reader, end: usize) -> Result<String> {
+    for start in [reader.at, reader.at + 1] {
+        if let Some(&length) = reader.bytes.get(start) {
+            let label_end = start + 1 + length as usize;
+            if label_end == end || label_end + 1 == end {
+                reader.at = start;
+                return text(reader.pascal()?, || format!("label at byte {}", start));
+            }
+        }
+    }
+    Err(invalid(format!(
+        "Type descriptor ending at byte {} has a label that doesn't fit in it",
+        end
+    )))
+}
+
+// build a Table from a flattened cluster and its type descriptor
+// (given as LabVIEW's type string, an array of I16)
+pub fn to_table(data: &[u8], type_string: &[i16]) -> Result<Table> {
+    let type_bytes: Vec<u8> = type_string
+        .iter()
+        .flat_map(|word| word.to_be_bytes())
+        .collect();
+    let descriptor = descriptor(&mut Reader::new(&type_bytes, "Type descriptor"), 0)?;
+    let elements = match descriptor.kind {
+        Type::Cluster(elements) => elements,
+        _ => return Err(Error::wrong_type("Flattened data", "Cluster")),
+    };
+
+    let mut data = Reader::new(data, "Flattened data");
+    let table = cluster_table(&elements, &mut data, &mut Vec::new())?;
+
+    if data.at != data.bytes.len() {
+        return Err(invalid(format!(
+            "Flattened data has {} bytes left over after the cluster (is it the data for this type?)",
+            data.bytes.len() - data.at
+        )));
+    }
+    Ok(table)
+}
+
+// the labels of a cluster's elements, which become their keys (so they must be unique)
+fn labels<'a>(elements: &'a [Descriptor], path: &[Step]) -> Result<Vec<&'a str>> {
+    let mut labels: Vec<&str> = Vec::new();
+
+    for (index, element) in elements.iter().enumerate() {
+        let label = element
+            .label
+            .as_deref()
+            .filter(|label| !label.is_empty())
+            .ok_or_else(|| {
+                invalid(format!(
+                    "Element {} of {} has no label",
+                    index,
+                    describe(path)
+                ))
+            })?;
+        if labels.contains(&label) {
+            return Err(Error::key_exists(&path::display(
+                &[path, &[Step::Key(label.to_string())]].concat(),
+            )));
+        }
+        labels.push(label);
+    }
+    Ok(labels)
+}
+
+// a cluster as a table, with any clusters inside it as tables too
+fn cluster_table(
+    elements: &[Descriptor],
+    data: &mut Reader,
+    path: &mut Vec<Step>,
+) -> Result<Table> {
+    let mut table = Table::new();
+
+    for (label, element) in labels(elements, path)?.into_iter().zip(elements) {
+        path.push(Step::Key(label.to_string()));
+        let item = match &element.kind {
+            Type::Cluster(elements) => Item::Table(cluster_table(elements, data, path)?),
+            _ => Item::Value(value(element, data, path)?),
+        };
+        path.pop();
+        table.insert(label, item);
+    }
+    Ok(table)
+}
+
+fn value(descriptor: &Descriptor, data: &mut Reader, path: &mut Vec<Step>) -> Result<Value> {
+    Ok(match &descriptor.kind {
+        Type::Integer { size, signed } => Value::from(integer(data, *size, *signed, path)?),
+        Type::Float { size: 4 } => Value::from(f32::from_be_bytes(data.array()?) as f64),
+        Type::Float { .. } => Value::from(f64::from_be_bytes(data.array()?)),
+        Type::Boolean => Value::from(data.u8()? != 0),
+        Type::String => {
+            let length = data.size(path)?;
+            Value::from(text(data.take(length)?, || describe(path))?)
+        }
+        Type::Path => Value::from(path_text(data, path)?),
+        Type::Enum { size, items } => {
+            let index = integer(data, *size, false, path)?;
+            let item = items.get(index as usize).ok_or_else(|| {
+                invalid(format!(
+                    "Enum value {} is past its last item: {}",
+                    index,
+                    describe(path)
+                ))
+            })?;
+            Value::from(item.clone())
+        }
+        Type::Timestamp => {
+            let seconds = data.i64()?;
+            let fraction = data.u64()?;
+            let timestamp = LVTime::from_parts(seconds as u64, fraction);
+            Value::from(datetime::from_timestamp(
+                timestamp,
+                DatetimeKind::OffsetDatetime,
+                0,
+            )?)
+        }
+        Type::Array {
+            dimensions,
+            element,
+        } => {
+            let sizes = (0..*dimensions)
+                .map(|_| data.size(path))
+                .collect::<Result<Vec<usize>>>()?;
+            // the elements can't take more bytes than are left
+            let count = sizes
+                .iter()
+                .try_fold(1usize, |count, size| count.checked_mul(*size));
+            let problem = match min_size(element) {
+                0 if count.is_none_or(|count| count > MAX_EMPTY_ELEMENTS) => Some(format!(
+                    "is too big, for elements that take no bytes (at most {})",
+                    MAX_EMPTY_ELEMENTS
+                )),
+                0 => None,
+                size => count
+                    .is_none_or(|count| count > (data.bytes.len() - data.at) / size)
+                    .then(|| "ends early".to_string()),
+            };
+            if let Some(problem) = problem {
+                return Err(invalid(format!(
+                    "Flattened data {}, for an array of {:?} elements: {}",
+                    problem,
+                    sizes,
+                    describe(path)
+                )));
+            }
+            array(&sizes, element, data, path)?
+        }
+        Type::Cluster(elements) => {
+            let mut inline_table = InlineTable::new();
+            for (label, element) in labels(elements, path)?.into_iter().zip(elements) {
+                path.push(Step::Key(label.to_string()));
+                let value = value(element, data, path)?;
+                path.pop();
+                inline_table.insert(label, value);
+            }
+            Value::InlineTable(inline_table)
+        }
+    })
+}
+
+// the fewest bytes a value of a type can be flattened into
+fn min_size(descriptor: &Descriptor) -> usize {
+    match &descriptor.kind {
+        Type::Integer { size, .. } | Type::Float { size } | Type::Enum { size, .. } => *size,
+        Type::Boolean => 1,
+        // the length
+        Type::String => 4,
+        // `PTH0` and the length
+        Type::Path => 8,
+        // the seconds and the fraction
+        Type::Timestamp => 16,
+        // the size of each dimension, for an empty array
+        Type::Array { dimensions, .. } => 4 * dimensions,
+        Type::Cluster(elements) => elements.iter().map(min_size).sum(),
+    }
+}
+
+// an array of one or more dimensions (as arrays of arrays), with its elements in row order
+fn array(
+    sizes: &[usize],
+    element: &Descriptor,
+    data: &mut Reader,
+    path: &mut Vec<Step>,
+) -> Result<Value> {
+    let mut array = Array::new();
+
+    for index in 0..sizes[0] {
+        path.push(Step::Index(index));
+        let value = match sizes.len() {
+            1 => value(element, data, path)?,
+            _ => self::array(&sizes[1..], element, data, path)?,
+        };
+        path.pop();
+        array.push(value);
+    }
+    Ok(Value::Array(array))
+}
+
+// a signed or unsigned integer of 1, 2, 4 or 8 bytes
+fn integer(data: &mut Reader, size: usize, signed: bool, path: &[Step]) -> Result<i64> {
+    let bits = data
+        .take(size)?
+        .iter()
+        .fold(0u64, |bits, byte| bits << 8 | *byte as u64);
+
+    match signed {
+        // move the sign bit to the top, and back again to extend it
+        true => {
+            let shift = 64 - 8 * size as u32;
+            Ok(((bits << shift) as i64) >> shift)
+        }
+        false => i64::try_from(bits).map_err(|_| {
+            invalid(format!(
+                "{} is too big for a TOML integer: {}",
+                bits,
+                describe(path)
+            ))
+        }),
+    }
+}
+
+// a flattened path (`PTH0`, its length, its kind and its components) as text for this platform
+// (an empty string for Not a Path)
+fn path_text(data: &mut Reader, path: &[Step]) -> Result<String> {
+    if data.take(4)? != b"PTH0" {
+        return Err(invalid(format!("Not a flattened path: {}", describe(path))));
+    }
+    let length = data.u32()? as usize;
+    let mut flattened = Reader::new(data.take(length)?, "Flattened path");
+
+    let kind = flattened.u16()?;
+    let count = flattened.u16()?;
+    let components = (0..count)
+        .map(|_| text(flattened.pascal()?, || describe(path)))
+        .collect::<Result<Vec<String>>>()?;
+
+    let separator = std::path::MAIN_SEPARATOR_STR;
+    Ok(match (kind, components.split_first()) {
+        // an absolute path starts with the drive on Windows, and at the root elsewhere
+        (0, Some((drive, rest))) if cfg!(windows) => {
+            format!("{}:{}{}", drive, separator, rest.join(separator))
+        }
+        (0, _) => format!("{}{}", separator, components.join(separator)),
+        (1, _) => components.join(separator),
+        _ => String::new(),
+    })
+}
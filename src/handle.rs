@@ -0,0 +1,501 @@
+// The registry of objects that LabVIEW holds references to.
+//
+// LabVIEW never sees a Rust pointer. A reference is a pointer-sized ID made of a slot index and
+// the generation of that slot, and the registry checks every ID before it is used, so passing a
+// reference of the wrong type, or one that has already been closed, is reported as an error
+// instead of corrupting memory.
+//
+// A reference is either to an object of its own, or a "view" into part of another object (e.g.
+// a Table inside a Document). A view is stored as the reference of the object that owns the data
+// plus the path to reach it, and is looked up again every time it is used, so changes
+// made through a view edit the owning object in place.
+//
+// A view of an array element holds its index, so inserting or removing an element before it
+// would leave the view pointing at a different element. Each object counts how many times the
+// elements of each array in it have been moved along, and a view remembers the counts from when
+// it was made, so a view whose element has moved is "not found" rather than quietly following
+// whatever is at its old index.
+//
+// Calls can come from any LabVIEW thread at once. Each object has a lock of its own, which is
+// held while it (or a view into it) is used, so calls on different objects run in parallel and
+// calls on the same object take turns. The registry has its own lock too, but it is only held
+// for long enough to look up which object a reference refers to.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use toml_edit::{Array, ArrayOfTables, Document, InlineTable, Item, Table, Value};
+
+use crate::error::{Error, ErrorCode, Result};
+use crate::file::FileLock;
+use crate::path::{self, Node, Step};
+
+// a reference to an object, as passed to and from LabVIEW (an Unsigned Pointer-sized Integer)
+// 0 is never a valid reference, so LabVIEW can use it as "not a reference"
+pub type Handle = usize;
+
+// the low bits of a handle hold the slot index (plus 1, so that 0 is never valid) and the high
+// bits hold the slot's generation, which changes every time an object in the slot is closed
+const INDEX_BITS: u32 = if usize::BITS >= 64 { 32 } else { 20 };
+const INDEX_MASK: usize = (1 << INDEX_BITS) - 1;
+const GENERATION_MASK: usize = usize::MAX >> INDEX_BITS;
+
+// an object held by the registry
+pub enum Object {
+    Document(Document),
+    Table(Table),
+    Item(Item),
+    Value(Value),
+    InlineTable(InlineTable),
+    Array(Array),
+    ArrayOfTables(ArrayOfTables),
+    FileLock(FileLock),
+}
+
+// a part of another object, found by following `path` from the object `root` refers to
+pub struct View {
+    root: Handle,
+    path: Vec<Step>,
+    // the shift counts of the arrays along the path when the view was made (see `Owned::shifts`)
+    shifts: Vec<u64>,
+    // the type of object the view was created as
+    kind: &'static str,
+}
+
+impl Object {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Object::Document(_) => Document::NAME,
+            Object::Table(_) => Table::NAME,
+            Object::Item(_) => Item::NAME,
+            Object::Value(_) => Value::NAME,
+            Object::InlineTable(_) => InlineTable::NAME,
+            Object::Array(_) => Array::NAME,
+            Object::ArrayOfTables(_) => ArrayOfTables::NAME,
+            Object::FileLock(_) => FileLock::NAME,
+        }
+    }
+}
+
+// a type of object that can be stored in the registry
+pub trait Kind: Sized {
+    const NAME: &'static str;
+
+    fn wrap(self) -> Object;
+    fn as_kind(object: &mut Object) -> Option<&mut Self>;
+    fn from_node(node: Node<'_>) -> Option<&mut Self>;
+}
+
+macro_rules! impl_kind {
+    ($($kind:ident => $from_node:ident),*) => {
+        $(
+            impl Kind for $kind {
+                const NAME: &'static str = stringify!($kind);
+
+                fn wrap(self) -> Object {
+                    Object::$kind(self)
+                }
+
+                fn as_kind(object: &mut Object) -> Option<&mut Self> {
+                    match object {
+                        Object::$kind(object) => Some(object),
+                        _ => None,
+                    }
+                }
+
+                fn from_node(node: Node<'_>) -> Option<&mut Self> {
+                    node.$from_node()
+                }
+            }
+        )*
+    };
+}
+
+impl_kind!(
+    Document => into_document,
+    Table => into_table,
+    Item => into_item,
+    Value => into_value,
+    InlineTable => into_inline_table,
+    Array => into_array,
+    ArrayOfTables => into_array_of_tables
+);
+
+// a lock is only held, never looked inside, so there is never a view of one
+impl Kind for FileLock {
+    const NAME: &'static str = "FileLock";
+
+    fn wrap(self) -> Object {
+        Object::FileLock(self)
+    }
+
+    fn as_kind(object: &mut Object) -> Option<&mut Self> {
+        match object {
+            Object::FileLock(lock) => Some(lock),
+            _ => None,
+        }
+    }
+
+    fn from_node(_: Node<'_>) -> Option<&mut Self> {
+        None
+    }
+}
+
+impl Object {
+    // where a view's path starts
+    fn root_node(&mut self) -> Node<'_> {
+        match self {
+            Object::Document(doc) => Node::Item(doc.as_item_mut()),
+            Object::Table(table) => Node::Table(table),
+            Object::Item(item) => Node::Item(item),
+            Object::Value(value) => Node::Value(value),
+            Object::InlineTable(inline_table) => Node::InlineTable(inline_table),
+            Object::Array(array) => Node::Array(array),
+            Object::ArrayOfTables(array) => Node::ArrayOfTables(array),
+            Object::FileLock(_) => unreachable!("a FileLock has nothing to view"),
+        }
+    }
+}
+
+// an object, and how many times the elements of each array in it have been moved along by an
+// insert or a removal, by the path to the array (empty for the object itself)
+struct Owned {
+    object: Object,
+    shifted: HashMap<Vec<Step>, u64>,
+}
+
+impl Owned {
+    // the shift count of each array that `path` takes an element of, in order
+    fn shifts(&self, path: &[Step]) -> Vec<u64> {
+        path.iter()
+            .enumerate()
+            .filter(|(_, step)| matches!(step, Step::Index(_)))
+            .map(|(index, _)| self.shifted.get(&path[..index]).copied().unwrap_or(0))
+            .collect()
+    }
+}
+
+// an object, behind its own lock (shared with the views into it)
+type Shared = Arc<Mutex<Owned>>;
+
+// what a reference refers to
+enum Entry {
+    Object { kind: &'static str, object: Shared },
+    View(View),
+}
+
+impl Entry {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Entry::Object { kind, .. } => kind,
+            Entry::View(view) => view.kind,
+        }
+    }
+}
+
+struct Slot {
+    generation: usize,
+    entry: Option<Entry>,
+}
+
+struct Registry {
+    slots: Vec<Slot>,
+    // indexes of the empty slots, reused before the registry grows
+    free: Vec<usize>,
+}
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+    slots: Vec::new(),
+    free: Vec::new(),
+});
+
+// the object a reference refers to, looked up in the registry, and the path to follow from it
+// (`None` for the object itself, rather than a view into it)
+struct Target {
+    object: Shared,
+    path: Option<Vec<Step>>,
+    // the shift counts the path was made with (see `View::shifts`)
+    shifts: Vec<u64>,
+}
+
+impl Registry {
+    fn insert(&mut self, entry: Entry) -> Result<Handle> {
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                if self.slots.len() >= INDEX_MASK {
+                    return Err(Error::new(
+                        ErrorCode::TooManyReferences,
+                        format!("Too many open references ({})", self.slots.len()),
+                    ));
+                }
+                self.slots.push(Slot {
+                    generation: 1,
+                    entry: None,
+                });
+                self.slots.len() - 1
+            }
+        };
+
+        let slot = &mut self.slots[index];
+        slot.entry = Some(entry);
+        Ok((slot.generation << INDEX_BITS) | (index + 1))
+    }
+
+    // the occupied slot a handle refers to, checking that it hasn't been closed
+    fn slot<T: Kind>(&mut self, handle: Handle) -> Result<(usize, &mut Slot)> {
+        if handle == 0 {
+            return Err(Error::null(T::NAME));
+        }
+
+        let index = (handle & INDEX_MASK).wrapping_sub(1);
+        let generation = handle >> INDEX_BITS;
+
+        match self.slots.get_mut(index) {
+            Some(slot) if slot.generation == generation && slot.entry.is_some() => {
+                Ok((index, slot))
+            }
+            _ => Err(Error::invalid_reference(T::NAME)),
+        }
+    }
+
+    // the entry a handle refers to, checking it is of type `T`
+    fn entry<T: Kind>(&mut self, handle: Handle) -> Result<&Entry> {
+        let (_, slot) = self.slot::<T>(handle)?;
+        let entry = slot.entry.as_ref().expect("slot is occupied");
+        let type_name = entry.type_name();
+
+        if type_name != T::NAME {
+            return Err(Error::wrong_reference_type(T::NAME, type_name));
+        }
+        Ok(entry)
+    }
+
+    fn target<T: Kind>(&mut self, handle: Handle) -> Result<Target> {
+        let (root, path) = match self.entry::<T>(handle)? {
+            Entry::Object { object, .. } => {
+                return Ok(Target {
+                    object: object.clone(),
+                    path: None,
+                    shifts: Vec::new(),
+                })
+            }
+            Entry::View(view) => (view.root, (view.path.clone(), view.shifts.clone())),
+        };
+
+        // views are always of an object, never of another view (see `view`)
+        let (_, slot) = self
+            .slot::<T>(root)
+            .map_err(|_| Error::invalid_reference(T::NAME))?;
+        match slot.entry.as_ref().expect("slot is occupied") {
+            Entry::Object { object, .. } => Ok(Target {
+                object: object.clone(),
+                path: Some(path.0),
+                shifts: path.1,
+            }),
+            Entry::View(_) => unreachable!("views are never the root of another view"),
+        }
+    }
+
+    // the object a view of `parent` should start from, and the path from it to `parent`
+    fn view_root<P: Kind>(&mut self, parent: Handle) -> Result<(Handle, Vec<Step>)> {
+        Ok(match self.entry::<P>(parent)? {
+            Entry::View(view) => (view.root, view.path.clone()),
+            Entry::Object { .. } => (parent, Vec::new()),
+        })
+    }
+
+    fn remove<T: Kind>(&mut self, handle: Handle) -> Result<Entry> {
+        // check the type before emptying the slot, so closing the wrong type doesn't free anything
+        self.entry::<T>(handle)?;
+
+        let (index, slot) = self.slot::<T>(handle)?;
+        let entry = slot.entry.take().expect("slot is occupied");
+
+        // invalidate every outstanding copy of this handle (0 is skipped when the count wraps)
+        slot.generation = match (slot.generation + 1) & GENERATION_MASK {
+            0 => 1,
+            generation => generation,
+        };
+        self.free.push(index);
+
+        Ok(entry)
+    }
+}
+
+// what holds the part of an object a view refers to, which decides how it is written
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Parent {
+    // nothing: the reference is to an object of its own (or a view of a whole object)
+    None,
+    // a Document, whose root table is a view of the whole Document
+    Document,
+    // a Table (or, for a Table, an Array of Tables)
+    Table,
+    Array,
+    InlineTable,
+}
+
+impl Target {
+    // lock the object, follow the path, and run `body` with what it leads to
+    fn with<T: Kind, R>(&self, body: impl FnOnce(&mut T) -> Result<R>) -> Result<R> {
+        self.with_parent(|found, _| body(found))
+    }
+
+    // as `with`, also passing `body` what holds the part the path leads to
+    fn with_parent<T: Kind, R>(&self, body: impl FnOnce(&mut T, Parent) -> Result<R>) -> Result<R> {
+        self.run(None, body)
+    }
+
+    // as `with_parent`, and if `shifting` is given, count a shift of the elements of the array
+    // at that path from the target once `body` has run
+    fn run<T: Kind, R>(
+        &self,
+        shifting: Option<&[Step]>,
+        body: impl FnOnce(&mut T, Parent) -> Result<R>,
+    ) -> Result<R> {
+        let mut owned = lock(&self.object);
+
+        let (found, parent) = match &self.path {
+            None => (
+                T::as_kind(&mut owned.object).expect("type was checked"),
+                Parent::None,
+            ),
+            Some(path) => {
+                // describe where the view points, for error messages
+                let location = match path.is_empty() {
+                    true => owned.object.type_name().to_string(),
+                    false => path::display(path),
+                };
+
+                if owned.shifts(path) != self.shifts {
+                    return Err(Error::new(
+                        ErrorCode::NotFound,
+                        format!(
+                            "{} not found: an element before it has been inserted or removed",
+                            location
+                        ),
+                    ));
+                }
+
+                let in_document = matches!(owned.object, Object::Document(_));
+                let node = path::get(owned.object.root_node(), path)?;
+                // a value in a table is reached through its Item, but a value in an array or an
+                // inline table is reached directly
+                let parent = match (&node, path.last()) {
+                    (_, None) if in_document => Parent::Document,
+                    (_, None) => Parent::None,
+                    (Node::Value(_), Some(Step::Index(_))) => Parent::Array,
+                    (Node::Value(_), Some(Step::Key(_))) => Parent::InlineTable,
+                    _ => Parent::Table,
+                };
+                let found =
+                    T::from_node(node).ok_or_else(|| Error::wrong_type(&location, T::NAME))?;
+                (found, parent)
+            }
+        };
+
+        let result = body(found, parent)?;
+
+        if let Some(array) = shifting {
+            let mut path = self.path.clone().unwrap_or_default();
+            path.extend_from_slice(array);
+            *owned.shifted.entry(path).or_default() += 1;
+        }
+        Ok(result)
+    }
+}
+
+// a lock is never held while calling back into LabVIEW or into another function in this module,
+// but a panic while one is held (caught by `error::call`) would poison it; the registry is still
+// consistent in that case, and an object is no worse than the edit that panicked left it
+fn registry() -> MutexGuard<'static, Registry> {
+    REGISTRY
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn lock(object: &Mutex<Owned>) -> MutexGuard<'_, Owned> {
+    object
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// store an object and return a new reference to it
+pub fn insert<T: Kind>(object: T) -> Result<Handle> {
+    registry().insert(Entry::Object {
+        kind: T::NAME,
+        object: Arc::new(Mutex::new(Owned {
+            object: object.wrap(),
+            shifted: HashMap::new(),
+        })),
+    })
+}
+
+// run `body` with the object a reference refers to
+//
+// the object is locked while `body` runs, and other calls on it wait until it is done, so
+// `body` must not call other functions in this module (clone anything needed from a second
+// reference before calling this)
+pub fn with<T: Kind, R>(handle: Handle, body: impl FnOnce(&mut T) -> Result<R>) -> Result<R> {
+    // look the object up, and let go of the registry before locking it
+    let target = registry().target::<T>(handle)?;
+    target.with(body)
+}
+
+// run `body` with the object a reference refers to, and what holds it (see `with`)
+pub fn with_parent<T: Kind, R>(
+    handle: Handle,
+    body: impl FnOnce(&mut T, Parent) -> Result<R>,
+) -> Result<R> {
+    let target = registry().target::<T>(handle)?;
+    target.with_parent(body)
+}
+
+// run `body` with the object a reference refers to (see `with`), where `body` inserts or removes
+// elements of the array at `array` (a path from the reference, empty for the reference itself),
+// so views of its elements taken before no longer find them
+pub fn with_shifting<T: Kind, R>(
+    handle: Handle,
+    array: &[Step],
+    body: impl FnOnce(&mut T) -> Result<R>,
+) -> Result<R> {
+    let target = registry().target::<T>(handle)?;
+    target.run(Some(array), |found, _| body(found))
+}
+
+// create a live view of the object found by following `steps` from the object `parent` refers to
+// (`parent` can itself be a view, and is checked to be of type `P`)
+pub fn view<P: Kind, T: Kind>(parent: Handle, steps: &[Step]) -> Result<Handle> {
+    let (root, mut path, parent) = {
+        let mut registry = registry();
+        let (root, path) = registry.view_root::<P>(parent)?;
+        (root, path, registry.target::<P>(parent)?)
+    };
+    parent.with(|_: &mut P| Ok(()))?;
+
+    // check the view refers to something before handing it out
+    path.extend_from_slice(steps);
+    let shifts = lock(&parent.object).shifts(&path);
+    let target = Target {
+        object: parent.object,
+        path: Some(path.clone()),
+        shifts: shifts.clone(),
+    };
+    target.with(|_: &mut T| Ok(()))?;
+
+    registry().insert(Entry::View(View {
+        root,
+        path,
+        shifts,
+        kind: T::NAME,
+    }))
+}
+
+// close a reference, and free the object if it isn't a view (once no other call is using it)
+pub fn remove<T: Kind>(handle: Handle) -> Result<()> {
+    let entry = registry().remove::<T>(handle)?;
+
+    // free the object after letting go of the registry, as a large Document takes a while
+    drop(entry);
+    Ok(())
+}
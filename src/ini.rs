@@ -0,0 +1,547 @@
+// Converting between TOML and LabVIEW configuration (INI) files, as used by the Config File VIs.
+//
+// An INI file is a list of `[sections]`, each holding `key = value` lines. Values have no types,
+// so an unquoted value that reads as a boolean or a number becomes one, and anything else is a
+// string. Comment lines (`;` or `#`) and blank lines are kept above the key or section they
+// precede. Anything that can't be carried over exactly (such as a second value for the same
+// key, or an empty TOML array) is listed, so the caller can check the conversion.
+
+use toml_edit::{Document, Item, Table, Value};
+
+use crate::path::{self, Step};
+
+// the section the keys being read go into
+enum Section {
+    // the keys before the first section, which go in the root table
+    Root,
+    Named(String),
+    // a section that couldn't be made into a table
+    Dropped,
+}
+
+// read INI text into a new Document, along with a list of what couldn't be carried over exactly
+pub fn from_ini(ini: &str) -> (Document, Vec<String>) {
+    let mut doc = Document::new();
+    let mut lossy = Vec::new();
+
+    // the comments and blank lines waiting to go above the next key or section
+    let mut prefix = String::new();
+    let mut section = Section::Root;
+
+    let ini = ini.strip_prefix('\u{feff}').unwrap_or(ini);
+    for (number, line) in ini.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() {
+            prefix.push('\n');
+        } else if let Some(comment) = line.strip_prefix(';') {
+            prefix.push_str(&format!("#{}\n", comment));
+        } else if line.starts_with('#') {
+            prefix.push_str(&format!("{}\n", line));
+        } else if let Some((name, suffix)) = parse_section(line) {
+            let table = doc
+                .as_table_mut()
+                .entry(&name)
+                .or_insert_with(|| Item::Table(Table::new()));
+
+            // a repeated section adds its keys to the first one
+            match table.as_table_mut() {
+                Some(table) => {
+                    let decor = table.decor_mut();
+                    if !prefix.is_empty() && decor.prefix().is_none() {
+                        decor.set_prefix(prefix.clone());
+                    }
+                    if let Some(suffix) = suffix {
+                        decor.set_suffix(suffix);
+                    }
+                    section = Section::Named(name);
+                }
+                None => {
+                    lossy.push(format!(
+                        "line {}: section `{}` has the name of a key before the first section, \
+                         dropped its keys",
+                        number + 1,
+                        name
+                    ));
+                    section = Section::Dropped;
+                }
+            }
+            prefix.clear();
+        } else if let Some((key, text)) = line.split_once('=') {
+            let key = key.trim();
+            let table = match &section {
+                Section::Root => doc.as_table_mut(),
+                Section::Named(name) => doc[name.as_str()]
+                    .as_table_mut()
+                    .expect("sections are tables"),
+                Section::Dropped => {
+                    prefix.clear();
+                    continue;
+                }
+            };
+            let mut path = Vec::new();
+            if let Section::Named(name) = &section {
+                path.push(Step::Key(name.clone()));
+            }
+            path.push(Step::Key(key.to_string()));
+            let path = path::display(&path);
+
+            if table.contains_key(key) {
+                lossy.push(format!(
+                    "{}: duplicate key, kept the first value and dropped `{}`",
+                    path,
+                    text.trim()
+                ));
+                prefix.clear();
+                continue;
+            }
+
+            let value = parse_value(text.trim(), &path, &mut lossy);
+            table.insert(key, Item::Value(value));
+            if !prefix.is_empty() {
+                table
+                    .key_decor_mut(key)
+                    .expect("key was just inserted")
+                    .set_prefix(prefix.clone());
+            }
+            prefix.clear();
+        } else {
+            lossy.push(format!(
+                "line {}: not a section, key or comment, dropped `{}`",
+                number + 1,
+                line
+            ));
+        }
+    }
+
+    // comments after the last key stay at the end of the file
+    doc.set_trailing(prefix);
+    (doc, lossy)
+}
+
+// the name of a `[section]` line, and a comment after it (as a TOML comment)
+fn parse_section(line: &str) -> Option<(String, Option<String>)> {
+    let (name, rest) = line.strip_prefix('[')?.split_once(']')?;
+    let suffix = match rest.trim() {
+        "" => None,
+        rest => Some(format!(" {}", comment(rest)?)),
+    };
+    Some((name.trim().to_string(), suffix))
+}
+
+// a `;` or `#` comment, written as a TOML comment
+fn comment(text: &str) -> Option<String> {
+    match text.strip_prefix(';') {
+        Some(comment) => Some(format!("#{}", comment)),
+        None => text.starts_with('#').then(|| text.to_string()),
+    }
+}
+
+// the value of a `key = value` line
+// a quoted value is a string (with any comment after the closing quote kept), and an unquoted
+// value is a boolean or number if it reads as one, or else the rest of the line
+fn parse_value(text: &str, path: &str, lossy: &mut Vec<String>) -> Value {
+    if let Some((string, rest)) = split_quoted(text) {
+        match rest.trim() {
+            "" => return Value::from(unescape(string, path, lossy)),
+            rest => {
+                if let Some(comment) = comment(rest) {
+                    return Value::from(unescape(string, path, lossy))
+                        .decorated(" ", format!(" {}", comment));
+                }
+            }
+        }
+    }
+
+    if text.eq_ignore_ascii_case("true") {
+        return Value::from(true);
+    }
+    if text.eq_ignore_ascii_case("false") {
+        return Value::from(false);
+    }
+    parse_number(text, path, lossy).unwrap_or_else(|| Value::from(text))
+}
+
+// the inside of a quoted value and the text after its closing quote
+fn split_quoted(text: &str) -> Option<(&str, &str)> {
+    let inside = text.strip_prefix('"')?;
+    let mut escaped = false;
+
+    for (index, c) in inside.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return Some((&inside[..index], &inside[index + 1..])),
+            _ => {}
+        }
+    }
+    None
+}
+
+// the string a quoted value stands for, with its backslash codes replaced
+// these are the codes LabVIEW writes: `\n`, `\r`, `\t`, `\b`, `\f`, `\s` (a space), `\\`,
+// `\"` and `\XX`, the byte with the hexadecimal code XX
+fn unescape(string: &str, path: &str, lossy: &mut Vec<String>) -> String {
+    let mut bytes = Vec::new();
+    let mut rest = string;
+
+    while let Some(index) = rest.find('\\') {
+        bytes.extend_from_slice(&rest.as_bytes()[..index]);
+        let code = &rest[index + 1..];
+
+        let (byte, length) = match code.chars().next() {
+            Some('n') => (b'\n', 1),
+            Some('r') => (b'\r', 1),
+            Some('t') => (b'\t', 1),
+            Some('b') => (0x08, 1),
+            Some('f') => (0x0c, 1),
+            Some('s') => (b' ', 1),
+            Some('\\') => (b'\\', 1),
+            Some('"') => (b'"', 1),
+            _ => match code
+                .get(..2)
+                .filter(|hex| hex.bytes().all(|c| c.is_ascii_hexdigit()))
+            {
+                Some(hex) => (u8::from_str_radix(hex, 16).expect("hex digits"), 2),
+                // not a code, so the backslash is kept as it is
+                None => (b'\\', 0),
+            },
+        };
+        bytes.push(byte);
+        rest = &code[length..];
+    }
+    bytes.extend_from_slice(rest.as_bytes());
+
+    match String::from_utf8(bytes) {
+        Ok(string) => string,
+        Err(error) => {
+            let string = String::from_utf8_lossy(error.as_bytes()).into_owned();
+            lossy.push(format!(
+                "{}: not UTF-8 text, the bytes that aren't were replaced in `{}`",
+                path, string
+            ));
+            string
+        }
+    }
+}
+
+// an unquoted value that reads as an integer or a float
+// (an integer is only taken as one if it is written as TOML would write it, so `007` stays a
+// string rather than losing its zeros)
+fn parse_number(text: &str, path: &str, lossy: &mut Vec<String>) -> Option<Value> {
+    let digits = text.strip_prefix(['+', '-']).unwrap_or(text);
+
+    if !digits.is_empty() && digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        if digits.len() > 1 && digits.starts_with('0') {
+            return None;
+        }
+        return match text.parse::<i64>() {
+            Ok(integer) => Some(Value::from(integer)),
+            Err(_) => {
+                lossy.push(format!(
+                    "{}: {} is too big for an integer, kept as a string",
+                    path, text
+                ));
+                None
+            }
+        };
+    }
+
+    match digits.to_ascii_lowercase().as_str() {
+        "nan" => return Some(Value::from(f64::NAN)),
+        "inf" | "infinity" => {
+            let negative = text.starts_with('-');
+            return Some(Value::from(if negative {
+                f64::NEG_INFINITY
+            } else {
+                f64::INFINITY
+            }));
+        }
+        _ => {}
+    }
+
+    // a float has a decimal point or an exponent, and only digits otherwise
+    let (mantissa, exponent) = match digits.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, Some(exponent)),
+        None => (digits, None),
+    };
+    let is_digits = |text: &str| text.bytes().all(|byte| byte.is_ascii_digit());
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let exponent_ok = exponent.is_none_or(|exponent| {
+        let exponent = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+        !exponent.is_empty() && is_digits(exponent)
+    });
+    if (!mantissa.contains('.') && exponent.is_none())
+        || whole.len() + fraction.len() == 0
+        || !is_digits(whole)
+        || !is_digits(fraction)
+        || !exponent_ok
+    {
+        return None;
+    }
+
+    let float = text.parse::<f64>().ok()?;
+    // a double holds any decimal of up to 15 significant digits exactly
+    let significant = format!("{}{}", whole, fraction);
+    let significant = significant.trim_start_matches('0').trim_end_matches('0');
+    if significant.len() > 15 || !float.is_finite() {
+        lossy.push(format!("{}: {} was rounded to {}", path, text, float));
+    }
+    Some(Value::from(float))
+}
+
+// write a Document as the text of an INI file, along with a list of the values that couldn't be
+// written
+// each table with values becomes a `[section]` named by its path (`[a.b]`, or `[steps.0]` for
+// a table in an array of tables), and each value inside an inline table or an array becomes its
+// own key named by its path inside the value (`point.x`, `list.0`)
+pub fn to_ini(doc: &Document) -> (String, Vec<String>) {
+    let mut writer = Writer::default();
+    writer.values(doc.as_table(), &[]);
+
+    let mut sections = Vec::new();
+    let mut last = doc.as_table().position().unwrap_or(0);
+    find_sections(doc.as_table(), &mut Vec::new(), &mut last, &mut sections);
+    // sections are written in the order of their tables in the document, as `toml_edit` does
+    sections.sort_by_key(|(position, _, _)| *position);
+
+    for (_, path, table) in sections {
+        writer.section(&path, table);
+    }
+
+    writer.comments(doc.trailing().as_str().unwrap_or(""));
+    (writer.ini, writer.lossy)
+}
+
+// the tables inside a table that are written with a `[header]`, depth first, each with the
+// position it is written at (a table without one is written where the table before it is)
+fn find_sections<'a>(
+    table: &'a Table,
+    path: &mut Vec<Step>,
+    last: &mut usize,
+    sections: &mut Vec<(usize, Vec<Step>, &'a Table)>,
+) {
+    for (key, item) in table.iter() {
+        let tables: Vec<(Option<usize>, &Table)> = match item {
+            Item::Table(table) => vec![(None, table)],
+            Item::ArrayOfTables(array) => array
+                .iter()
+                .enumerate()
+                .map(|(index, table)| (Some(index), table))
+                .collect(),
+            _ => vec![],
+        };
+
+        for (index, table) in tables {
+            path.push(Step::Key(key.to_string()));
+            if let Some(index) = index {
+                path.push(Step::Index(index));
+            }
+
+            *last = table.position().unwrap_or(*last);
+            // dotted keys are written in their parent's section
+            let has_values = table.iter().any(|(_, item)| item.is_value());
+            if !table.is_dotted() && (has_values || !table.is_implicit()) {
+                sections.push((*last, path.clone(), table));
+            }
+            find_sections(table, path, last, sections);
+
+            path.truncate(path.len() - 1 - index.map_or(0, |_| 1));
+        }
+    }
+}
+
+// the name of a section in an INI file, from its path (keys aren't quoted, and an index into an
+// array of tables is written as a key, as `[` and `]` would end the section name)
+fn ini_name(path: &[Step]) -> String {
+    let mut name = String::new();
+    for step in path {
+        if !name.is_empty() {
+            name.push('.');
+        }
+        match step {
+            Step::Key(key) => name.push_str(key),
+            Step::Index(index) => name.push_str(&index.to_string()),
+        }
+    }
+    name
+}
+
+#[derive(Default)]
+struct Writer {
+    ini: String,
+    lossy: Vec<String>,
+}
+
+impl Writer {
+    // write the comment and blank lines of a prefix, with `#` comments written as `;` ones
+    fn comments(&mut self, prefix: &str) {
+        let mut lines: Vec<&str> = prefix.split('\n').collect();
+        // the last line is the indent before whatever the prefix is for
+        lines.pop();
+
+        for line in lines {
+            let line = line.trim();
+            match line.strip_prefix('#') {
+                Some(comment) => self.ini.push_str(&format!(";{}\n", comment)),
+                None => self.ini.push('\n'),
+            }
+        }
+    }
+
+    fn section(&mut self, path: &[Step], table: &Table) {
+        let name = ini_name(path);
+        if name.contains([']', '\n', '\r']) {
+            self.lossy.push(format!(
+                "{}: the section name can't be written in INI, its values were left out",
+                path::display(path)
+            ));
+            return;
+        }
+
+        match table.decor().prefix() {
+            Some(prefix) => self.comments(prefix.as_str().unwrap_or("")),
+            None if !self.ini.is_empty() => self.ini.push('\n'),
+            None => {}
+        }
+        self.ini.push_str(&format!("[{}]", name));
+        if let Some(comment) = end_comment(table.decor().suffix()) {
+            self.ini.push_str(&format!(" {}", comment));
+        }
+        self.ini.push('\n');
+
+        self.values(table, path);
+    }
+
+    // write the values of a table (including its dotted keys), with the comments above them
+    // (the values of the root table are written before the first section, where the Config File
+    // VIs don't look for them, so they are listed too)
+    fn values(&mut self, table: &Table, path: &[Step]) {
+        let root = path.is_empty();
+        for (keys, value) in table.get_values() {
+            let mut path = path.to_vec();
+            path.extend(keys.iter().map(|key| Step::Key(key.get().to_string())));
+            let name = keys
+                .iter()
+                .map(|key| key.get())
+                .collect::<Vec<_>>()
+                .join(".");
+
+            let prefix = keys[0].decor().prefix().and_then(|prefix| prefix.as_str());
+            self.comments(prefix.unwrap_or(""));
+
+            let mut lines = Vec::new();
+            self.flatten(name, &path, value, &mut lines);
+            if root && !lines.is_empty() {
+                self.lossy.push(format!(
+                    "{}: it was written before the first section, where the Config File VIs \
+                     can't read it",
+                    path::display(&path)
+                ));
+            }
+
+            // a comment can only follow a quoted value (anything else is read to the end of
+            // the line), so otherwise it goes on the line above
+            let comment = end_comment(value.decor().suffix());
+            let quoted = lines.len() == 1 && lines[0].ends_with('"');
+            if let (Some(comment), false) = (&comment, quoted) {
+                self.ini.push_str(&format!("{}\n", comment));
+            }
+            for line in &lines {
+                self.ini.push_str(line);
+                if let (Some(comment), true) = (&comment, quoted) {
+                    self.ini.push_str(&format!(" {}", comment));
+                }
+                self.ini.push('\n');
+            }
+        }
+    }
+
+    // the `name = value` lines for a value, one for each value inside an array or inline table
+    fn flatten(&mut self, name: String, path: &[Step], value: &Value, lines: &mut Vec<String>) {
+        let mut cant = |reason: &str| {
+            self.lossy.push(format!(
+                "{}: {}, it was left out",
+                path::display(path),
+                reason
+            ))
+        };
+        if let Some(Step::Key(key)) = path.last() {
+            if key.is_empty()
+                || key.starts_with(['[', ';', '#'])
+                || key.contains(['=', '\n', '\r'])
+                || key.trim() != key
+            {
+                return cant("the key can't be written in INI");
+            }
+        }
+
+        let text = match value {
+            Value::String(string) => quote(string.value()),
+            Value::Integer(integer) => integer.value().to_string(),
+            Value::Float(float) => match *float.value() {
+                float if float.is_nan() => "NaN".to_string(),
+                float if float.is_infinite() && float > 0.0 => "Inf".to_string(),
+                float if float.is_infinite() => "-Inf".to_string(),
+                float => format!("{:?}", float),
+            },
+            Value::Boolean(boolean) => match boolean.value() {
+                true => "TRUE".to_string(),
+                false => "FALSE".to_string(),
+            },
+            Value::Datetime(datetime) => quote(&datetime.value().to_string()),
+            Value::Array(array) if array.is_empty() => return cant("an empty array"),
+            Value::Array(array) => {
+                for (index, value) in array.iter().enumerate() {
+                    let mut path = path.to_vec();
+                    path.push(Step::Index(index));
+                    self.flatten(format!("{}.{}", name, index), &path, value, lines);
+                }
+                return;
+            }
+            Value::InlineTable(inline_table) if inline_table.is_empty() => {
+                return cant("an empty inline table")
+            }
+            Value::InlineTable(inline_table) => {
+                for (key, value) in inline_table.iter() {
+                    let mut path = path.to_vec();
+                    path.push(Step::Key(key.to_string()));
+                    self.flatten(format!("{}.{}", name, key), &path, value, lines);
+                }
+                return;
+            }
+        };
+        lines.push(format!("{} = {}", name, text));
+    }
+}
+
+// the comment at the end of a line, written as an INI comment
+fn end_comment(suffix: Option<&toml_edit::RawString>) -> Option<String> {
+    let suffix = suffix?.as_str()?.trim();
+    suffix
+        .strip_prefix('#')
+        .map(|comment| format!(";{}", comment))
+}
+
+// a quoted string, with LabVIEW's backslash codes for a backslash, a quote and control
+// characters (`from_ini` reads these back)
+fn quote(string: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in string.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\22"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            '\u{8}' => quoted.push_str("\\b"),
+            '\u{c}' => quoted.push_str("\\f"),
+            c if c.is_control() && (c as u32) < 0x80 => {
+                quoted.push_str(&format!("\\{:02X}", c as u32))
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
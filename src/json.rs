@@ -0,0 +1,168 @@
+// Converting Documents to and from JSON, for web services that don't speak TOML.
+//
+// JSON has no datetimes, no NaN or infinity, and many JSON readers (such as JavaScript) store
+// every number as a double, which can't hold an integer beyond 2^53 exactly. So these are
+// written as JSON strings: a datetime as its TOML text (`"1979-05-27T07:32:00-07:00"`), a
+// special float as `"nan"`, `"inf"` or `"-inf"`, and a large integer as its digits. JSON read
+// back in is taken as it is written, so those strings stay strings.
+
+use serde_json::{Map, Number, Value as Json};
+use toml_edit::{Array, ArrayOfTables, Document, InlineTable, Item, Table, Value};
+
+use crate::error::{Error, ErrorCode, Result};
+
+// the largest integer a double holds exactly, as does every integer between it and its negative
+const SAFE_INTEGER: i64 = (1 << 53) - 1;
+
+// write a Document as JSON, on one line or indented
+pub fn to_json(doc: &Document, pretty: bool) -> String {
+    let json = table_to_json(doc.as_table());
+
+    match pretty {
+        true => serde_json::to_string_pretty(&json),
+        false => serde_json::to_string(&json),
+    }
+    .expect("a JSON value can always be written")
+}
+
+fn table_to_json(table: &Table) -> Json {
+    Json::Object(
+        table
+            .iter()
+            .filter_map(|(key, item)| item_to_json(item).map(|json| (key.to_string(), json)))
+            .collect(),
+    )
+}
+
+fn item_to_json(item: &Item) -> Option<Json> {
+    match item {
+        Item::None => None,
+        Item::Value(value) => Some(value_to_json(value)),
+        Item::Table(table) => Some(table_to_json(table)),
+        Item::ArrayOfTables(array) => Some(Json::Array(array.iter().map(table_to_json).collect())),
+    }
+}
+
+fn value_to_json(value: &Value) -> Json {
+    match value {
+        Value::String(string) => Json::String(string.value().clone()),
+        Value::Integer(integer) => match *integer.value() {
+            integer if (-SAFE_INTEGER..=SAFE_INTEGER).contains(&integer) => Json::from(integer),
+            integer => Json::String(integer.to_string()),
+        },
+        Value::Float(float) => match Number::from_f64(*float.value()) {
+            Some(number) => Json::Number(number),
+            None if float.value().is_nan() => Json::String("nan".to_string()),
+            None if *float.value() > 0.0 => Json::String("inf".to_string()),
+            None => Json::String("-inf".to_string()),
+        },
+        Value::Boolean(boolean) => Json::Bool(*boolean.value()),
+        Value::Datetime(datetime) => Json::String(datetime.value().to_string()),
+        Value::Array(array) => Json::Array(array.iter().map(value_to_json).collect()),
+        Value::InlineTable(inline_table) => Json::Object(
+            inline_table
+                .iter()
+                .map(|(key, value)| (key.to_string(), value_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+// read a Document from JSON, which must be an object
+// objects become `[header]` tables (or inline tables, inside an array), and arrays of only
+// objects become arrays of tables
+pub fn from_json(json: &str) -> Result<Document> {
+    let json: Json = serde_json::from_str(json).map_err(|error| {
+        Error::new(
+            ErrorCode::ParseError,
+            format!("Could not parse JSON: {}", error),
+        )
+    })?;
+
+    let object = match json {
+        Json::Object(object) => object,
+        _ => return Err(Error::wrong_type("JSON", "object")),
+    };
+
+    let mut doc = Document::new();
+    *doc.as_table_mut() = object_to_table(object, "")?;
+    Ok(doc)
+}
+
+// the path to a key, for error messages
+fn child_path(path: &str, key: &str) -> String {
+    match path.is_empty() {
+        true => key.to_string(),
+        false => format!("{}.{}", path, key),
+    }
+}
+
+fn object_to_table(object: Map<String, Json>, path: &str) -> Result<Table> {
+    let mut table = Table::new();
+
+    for (key, json) in object {
+        let path = child_path(path, &key);
+        let item = match json {
+            Json::Object(object) => Item::Table(object_to_table(object, &path)?),
+            Json::Array(array) if !array.is_empty() && array.iter().all(Json::is_object) => {
+                let mut tables = ArrayOfTables::new();
+                for (index, json) in array.into_iter().enumerate() {
+                    let object = match json {
+                        Json::Object(object) => object,
+                        _ => unreachable!("every element is an object"),
+                    };
+                    tables.push(object_to_table(object, &format!("{}[{}]", path, index))?);
+                }
+                Item::ArrayOfTables(tables)
+            }
+            json => Item::Value(json_to_value(json, &path)?),
+        };
+        table.insert(&key, item);
+    }
+
+    // a table holding only tables needs no `[header]` of its own (but an empty one does, or
+    // it would be left out altogether)
+    let only_tables = table
+        .iter()
+        .all(|(_, item)| item.is_table() || item.is_array_of_tables());
+    table.set_implicit(!table.is_empty() && only_tables);
+
+    Ok(table)
+}
+
+fn json_to_value(json: Json, path: &str) -> Result<Value> {
+    Ok(match json {
+        Json::Null => {
+            return Err(Error::new(
+                ErrorCode::InvalidValue,
+                format!("JSON null has no TOML equivalent: {}", path),
+            ))
+        }
+        Json::Bool(boolean) => Value::from(boolean),
+        // an integer too big for TOML (beyond an i64) becomes a float, as JSON allows
+        Json::Number(number) => match number.as_i64() {
+            Some(integer) => Value::from(integer),
+            None => Value::from(
+                number
+                    .as_f64()
+                    .expect("a JSON number is an i64, u64 or f64"),
+            ),
+        },
+        Json::String(string) => Value::from(string),
+        Json::Array(array) => {
+            let mut values = Array::new();
+            for (index, json) in array.into_iter().enumerate() {
+                values.push(json_to_value(json, &format!("{}[{}]", path, index))?);
+            }
+            Value::Array(values)
+        }
+        Json::Object(object) => {
+            let mut inline_table = InlineTable::new();
+            for (key, json) in object {
+                let value = json_to_value(json, &child_path(path, &key))?;
+                inline_table.insert(key, value);
+            }
+            Value::InlineTable(inline_table)
+        }
+    })
+}
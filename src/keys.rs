@@ -0,0 +1,433 @@
+// Changing the keys of Tables and InlineTables without losing their place or their comments.
+//
+// `toml_edit` keeps keys in the order they were written, but only ever adds a key at the end,
+// so renaming a key, adding one anywhere else or sorting them means taking every entry out
+// (with its key's formatting and comments) and putting them all back in order.
+//
+// The `[header]` tables inside a Table are written out in order of their position in the
+// document rather than their key order, so a table added between two keys is also moved to sit
+// between the tables around it, and sorting the sections of a document renumbers them all.
+
+use toml_edit::{InlineTable, Item, Key, Table, Value};
+
+use crate::array;
+use crate::decor::{self, Place};
+use crate::error::{Error, Result};
+
+// the key/entry pairs of a Table or InlineTable, taken out and put back in order
+pub trait Entries {
+    type Entry;
+
+    fn keys(&self) -> Vec<String>;
+    fn take(&mut self, key: &str) -> Option<(Key, Self::Entry)>;
+    fn put(&mut self, key: &Key, entry: Self::Entry);
+
+    // get a new entry ready to go in before the entry at `index`
+    fn fit(&self, _index: usize, _entry: &mut Self::Entry) {}
+
+    // sort the keys of any tables inside an entry
+    fn sort_inside(entry: &mut Self::Entry, order: &Order);
+
+    // fix up the entries that have moved to or from the start or end, after reordering
+    fn tidy_ends(_entries: &mut [(Key, Self::Entry)], _old_first: &str, _old_last: &str) {}
+
+    fn take_all(&mut self) -> Vec<(Key, Self::Entry)> {
+        self.keys()
+            .iter()
+            .map(|key| self.take(key).expect("key was just listed"))
+            .collect()
+    }
+}
+
+impl Entries for Table {
+    type Entry = Item;
+
+    fn keys(&self) -> Vec<String> {
+        self.iter().map(|(key, _)| key.to_string()).collect()
+    }
+
+    fn take(&mut self, key: &str) -> Option<(Key, Item)> {
+        self.remove_entry(key)
+    }
+
+    fn put(&mut self, key: &Key, item: Item) {
+        self.insert_formatted(key, item);
+    }
+
+    fn sort_inside(item: &mut Item, order: &Order) {
+        match item {
+            Item::Table(table) => sort(table, order, true),
+            Item::ArrayOfTables(array) => {
+                for table in array.iter_mut() {
+                    sort(table, order, true)
+                }
+            }
+            Item::Value(value) => InlineTable::sort_inside(value, order),
+            Item::None => {}
+        }
+    }
+
+    // a new table goes after the tables before it in the document, or else before the tables
+    // after it, or else straight after this table's own header
+    fn fit(&self, index: usize, item: &mut Item) {
+        let items: Vec<&Item> = self.iter().map(|(_, item)| item).collect();
+        let positions = |items: &[&Item], position: fn(&Table) -> Option<usize>| {
+            items
+                .iter()
+                .flat_map(|item| match item {
+                    Item::Table(table) => vec![table],
+                    Item::ArrayOfTables(array) => array.iter().collect(),
+                    _ => vec![],
+                })
+                .filter_map(position)
+                .collect::<Vec<usize>>()
+        };
+
+        let position = positions(&items[..index], array::last_position)
+            .into_iter()
+            .max()
+            .or_else(|| {
+                positions(&items[index..], array::first_position)
+                    .into_iter()
+                    .min()
+            })
+            .or(self.position());
+
+        match (item, position) {
+            (Item::Table(table), Some(position)) => array::set_position(table, position),
+            (Item::ArrayOfTables(array), Some(position)) => {
+                for table in array.iter_mut() {
+                    array::set_position(table, position)
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Entries for InlineTable {
+    type Entry = Value;
+
+    fn keys(&self) -> Vec<String> {
+        self.iter().map(|(key, _)| key.to_string()).collect()
+    }
+
+    fn take(&mut self, key: &str) -> Option<(Key, Value)> {
+        self.remove_entry(key)
+    }
+
+    fn put(&mut self, key: &Key, value: Value) {
+        self.insert_formatted(key, value);
+    }
+
+    fn fit(&self, _index: usize, value: &mut Value) {
+        decor::fit(value.decor_mut(), Place::Inline);
+    }
+
+    fn sort_inside(value: &mut Value, order: &Order) {
+        match value {
+            Value::InlineTable(inline_table) => sort(inline_table, order, true),
+            Value::Array(array) => {
+                for value in array.iter_mut() {
+                    InlineTable::sort_inside(value, order)
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // the whitespace after the `{` and before the `}` is kept where it was, as the prefix of
+    // the first key and the suffix of the last value, so `{x = 1}` doesn't become `{ w = 0,x = 1}`
+    fn tidy_ends(entries: &mut [(Key, Value)], old_first: &str, old_last: &str) {
+        let find = |entries: &[(Key, Value)], key: &str| {
+            entries
+                .iter()
+                .position(|(existing, _)| existing.get() == key)
+        };
+
+        if let (Some(old), Some((first, _))) = (find(entries, old_first), entries.first()) {
+            if first.get() != old_first {
+                let (key, _) = &mut entries[old];
+                let prefix = key.decor().prefix().cloned();
+                key.decor_mut().set_prefix(" ");
+
+                if let Some(prefix) = prefix {
+                    entries[0].0.decor_mut().set_prefix(prefix);
+                }
+            }
+        }
+
+        if let (Some(old), Some((last, _))) = (find(entries, old_last), entries.last()) {
+            if last.get() != old_last {
+                let (_, value) = &mut entries[old];
+                let suffix = value.decor().suffix().cloned();
+                value.decor_mut().set_suffix("");
+
+                if let Some(suffix) = suffix {
+                    let end = entries.len() - 1;
+                    entries[end].1.decor_mut().set_suffix(suffix);
+                }
+            }
+        }
+    }
+}
+
+// take every entry out of a table, change them, and put them back in their new order
+fn reorder<T: Entries>(table: &mut T, change: impl FnOnce(&mut Vec<(Key, T::Entry)>)) {
+    let mut entries = table.take_all();
+    let name = |entry: Option<&(Key, T::Entry)>| {
+        entry.map_or(String::new(), |(key, _)| key.get().to_string())
+    };
+    let (old_first, old_last) = (name(entries.first()), name(entries.last()));
+
+    change(&mut entries);
+    T::tidy_ends(&mut entries, &old_first, &old_last);
+
+    for (key, entry) in entries {
+        table.put(&key, entry);
+    }
+}
+
+// the zero-based place of a key among the keys of a table
+pub fn index<T: Entries>(table: &T, key: &str) -> Result<usize> {
+    table
+        .keys()
+        .iter()
+        .position(|existing| existing == key)
+        .ok_or_else(|| Error::not_found("Key", key))
+}
+
+// add a new key before `index` (which must be no more than the number of keys)
+// (fails if the key is already taken, rather than replacing it and moving it)
+pub fn insert<T: Entries>(
+    table: &mut T,
+    index: usize,
+    key: &str,
+    mut entry: T::Entry,
+) -> Result<()> {
+    if self::index(table, key).is_ok() {
+        return Err(Error::key_exists(key));
+    }
+
+    table.fit(index, &mut entry);
+    reorder(table, |entries| {
+        entries.insert(index, (Key::new(key), entry))
+    });
+    Ok(())
+}
+
+// rename a key, keeping its place, the comments around it and its value as they were
+// (fails if there is no such key, or if the new key is already taken)
+pub fn rename<T: Entries>(table: &mut T, old: &str, new: &str) -> Result<()> {
+    index(table, old)?;
+    if old == new {
+        return Ok(());
+    }
+    if index(table, new).is_ok() {
+        return Err(Error::key_exists(new));
+    }
+
+    reorder(table, |entries| {
+        for (key, _) in entries.iter_mut().filter(|(key, _)| key.get() == old) {
+            // the new key is written in its own default style (bare, or quoted if it must be)
+            *key = Key::new(new).with_decor(key.decor().clone());
+        }
+    });
+
+    Ok(())
+}
+
+// the order to sort keys into
+pub enum Order<'a> {
+    Alphabetical,
+    // these keys first, in this order, then any others in the order they were already in
+    Listed(&'a [String]),
+}
+
+// sort the keys of a table, and optionally of every table inside it
+// (each key keeps its comments, and the sort is stable, so keys that compare equal stay put)
+pub fn sort<T: Entries>(table: &mut T, order: &Order, recursive: bool) {
+    reorder(table, |entries| {
+        match order {
+            Order::Alphabetical => entries.sort_by(|(a, _), (b, _)| a.get().cmp(b.get())),
+            Order::Listed(keys) => entries.sort_by_key(|(key, _)| {
+                keys.iter()
+                    .position(|listed| listed == key.get())
+                    .unwrap_or(keys.len())
+            }),
+        }
+
+        if recursive {
+            for (_, entry) in entries.iter_mut() {
+                T::sort_inside(entry, order);
+            }
+        }
+    });
+}
+
+// sort the keys of a document's root table as `sort` does, keeping the comments at the top of
+// the file at the top (they are written as the prefix of the first `key = value` line, so they
+// move from the old first key to the new one, apart from any comment lines straight above the
+// old first key that a blank line separates from the ones above)
+pub fn sort_root(root: &mut Table, order: &Order, recursive: bool) {
+    let first_value = |root: &Table| {
+        root.iter()
+            .find(|(_, item)| item.is_value())
+            .map(|(key, _)| key.to_string())
+    };
+    let old_first = first_value(root);
+    sort(root, order, recursive);
+    let new_first = first_value(root);
+
+    let (Some(old), Some(new)) = (old_first, new_first) else {
+        return;
+    };
+    if old == new {
+        return;
+    }
+
+    let prefix = |root: &Table, key: &str| {
+        root.key_decor(key)
+            .and_then(|decor| decor.prefix())
+            .and_then(|prefix| prefix.as_str())
+            .unwrap_or("")
+            .to_string()
+    };
+    let (old_prefix, new_prefix) = (prefix(root, &old), prefix(root, &new));
+
+    // the top is everything up to the last blank line, or the whole prefix if it has none
+    let lines: Vec<&str> = old_prefix.split_inclusive('\n').collect();
+    let top = lines
+        .iter()
+        .rposition(|line| line.trim().is_empty())
+        .map_or(lines.len(), |blank| blank + 1);
+    let (top, rest) = (lines[..top].concat(), lines[top..].concat());
+
+    if let Some(decor) = root.key_decor_mut(&old) {
+        decor.set_prefix(rest);
+    }
+    if let Some(decor) = root.key_decor_mut(&new) {
+        decor.set_prefix(top + &new_prefix);
+    }
+}
+
+// write every `[header]` table in a document in key order, each straight after its parent
+// (the new first header takes the blank lines of the old one, normally none, and the old one
+// takes its blank lines in exchange, so the file doesn't start with a blank line)
+pub fn sort_tables(root: &mut Table) {
+    let old_first = first_header(root);
+
+    let mut next = root.position().map_or(0, |position| position + 1);
+    number_headers(root, &mut next);
+
+    let new_first = first_header(root);
+    if let (Some(old), Some(new)) = (old_first, new_first) {
+        if old != new {
+            let (old_blank, old_rest) = split_prefix(nth_header(root, old).expect("counted"));
+            let (new_blank, new_rest) = split_prefix(nth_header(root, new).expect("counted"));
+
+            let old_blank = old_blank.unwrap_or_default();
+            let new_blank = new_blank.unwrap_or_else(|| "\n".to_string());
+            nth_header(root, old)
+                .expect("counted")
+                .decor_mut()
+                .set_prefix(new_blank + &old_rest);
+            nth_header(root, new)
+                .expect("counted")
+                .decor_mut()
+                .set_prefix(old_blank + &new_rest);
+        }
+    }
+}
+
+// the tables inside a table that could have a `[header]`, depth first
+fn headers(table: &mut Table) -> Vec<&mut Table> {
+    table
+        .iter_mut()
+        .flat_map(|(_, item)| match item {
+            Item::Table(table) => vec![table],
+            Item::ArrayOfTables(array) => array.iter_mut().collect(),
+            _ => vec![],
+        })
+        .collect()
+}
+
+// number the positions of the tables inside a table in key order, from `next` on
+fn number_headers(table: &mut Table, next: &mut usize) {
+    for table in headers(table) {
+        table.set_position(*next);
+        *next += 1;
+        number_headers(table, next);
+    }
+}
+
+// the `n`th table inside a table, depth first (counting as `number_headers` does)
+fn nth_header(table: &mut Table, n: usize) -> Option<&mut Table> {
+    fn find<'a>(table: &'a mut Table, n: usize, seen: &mut usize) -> Option<&'a mut Table> {
+        for table in headers(table) {
+            if *seen == n {
+                return Some(table);
+            }
+            *seen += 1;
+            if let Some(found) = find(table, n, seen) {
+                return Some(found);
+            }
+        }
+        None
+    }
+
+    find(table, n, &mut 0)
+}
+
+// which table inside a document's root has the first `[header]` written out (tables are
+// written by position, a table without one taking the position of the table before it, and
+// in key order at the same position)
+fn first_header(root: &mut Table) -> Option<usize> {
+    // (position, index) of each table that is written with a header
+    fn visit(
+        table: &mut Table,
+        last: &mut usize,
+        count: &mut usize,
+        found: &mut Vec<(usize, usize)>,
+    ) {
+        for table in headers(table) {
+            *last = table.position().unwrap_or(*last);
+            // an implicit table is only written if it has values of its own
+            let hidden = table.is_implicit() && table.get_values().is_empty();
+            if !table.is_dotted() && !hidden {
+                found.push((*last, *count));
+            }
+            *count += 1;
+            visit(table, last, count, found);
+        }
+    }
+
+    let mut found = Vec::new();
+    let mut last = root.position().unwrap_or(0);
+    visit(root, &mut last, &mut 0, &mut found);
+    found.into_iter().min().map(|(_, index)| index)
+}
+
+// a `[header]`'s prefix split into the blank lines at its start (`None` if it has the default
+// prefix) and the rest (such as comment lines)
+fn split_prefix(table: &Table) -> (Option<String>, String) {
+    let prefix = match table.decor().prefix() {
+        Some(prefix) => prefix.as_str().unwrap_or("").to_string(),
+        None => return (None, String::new()),
+    };
+
+    let spaces = prefix
+        .find(|c: char| !c.is_whitespace())
+        .unwrap_or(prefix.len());
+    let blank = match spaces == prefix.len() {
+        true => spaces,
+        false => prefix[..spaces]
+            .rfind('\n')
+            .map_or(0, |newline| newline + 1),
+    };
+
+    (
+        Some(prefix[..blank].to_string()),
+        prefix[blank..].to_string(),
+    )
+}
@@ -1,339 +1,1120 @@
-use labview_interop::types::LStrHandle;
-
-use std::{
-    ffi::{c_void, CString},
-    ptr,
-    str::FromStr,
+// the exported functions take output parameters as raw pointers by design; they are checked
+// for null before use, but can't be marked `unsafe` without changing the C calling convention
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use labview_interop::types::{LStrHandle, LVArrayHandle, LVBool, LVTime};
+
+use std::path::Path;
+use std::str::FromStr;
+use toml_edit::{Array, ArrayOfTables, Document, InlineTable, Item, Table, Value};
+
+mod array;
+mod datetime;
+mod decor;
+mod error;
+mod ffi;
+mod file;
+mod flattened;
+mod handle;
+mod ini;
+mod json;
+mod keys;
+mod path;
+mod style;
+
+use datetime::{DatetimeKind, DatetimeParts};
+use decor::Place;
+use error::{call, Error, ErrorCode, ParseErrorDetails, Result};
+use ffi::{
+    out, read_array, read_bytes, read_str, read_str_array, read_time, write_array, write_str,
+    write_str_array, write_time,
 };
-use toml_edit::{table, Document, InlineTable, Item, Table, Value};
+use handle::{Handle, Parent};
+use keys::Order;
+use path::{Node, Step};
+
+// every exported function returns a status code (0 on success, see `error::ErrorCode`)
+// and records any failure so it can be retrieved with `toml_edit_last_error`
+//
+// objects are passed to and from LabVIEW as references (see `handle::Handle`), which are
+// checked before use, so a closed or wrong-typed reference is an error rather than a crash
 
 // return any TOML parse error as a string using toml_edit::TomlError
-#[allow(dead_code)]
 #[no_mangle]
-pub extern "C" fn toml_edit_doc_get_error(toml_str: LStrHandle, mut error_str: LStrHandle) {
-    let toml_str = toml_str.to_rust_string();
+pub extern "C" fn toml_edit_doc_get_error(toml_str: LStrHandle, error_str: LStrHandle) -> i32 {
+    call("toml_edit_doc_get_error", || {
+        let toml_str = read_str(toml_str)?;
 
-    // try to parse the TOML string
-    return match Document::from_str(&toml_str) {
-        Ok(_) => {
+        // try to parse the TOML string
+        match Document::from_str(&toml_str) {
             // return an empty string if no error
-            error_str.set_str("").unwrap();
-        }
-        Err(error) => {
+            Ok(_) => write_str(error_str, ""),
             // return the error string (if any)
-            error_str.set_str(&error.to_string()).unwrap();
+            Err(error) => write_str(error_str, &error.to_string()),
         }
-    };
+    })
+}
+
+// parse a TOML string and return the details of any parse error, so the location can be highlighted:
+// the message, 1-based line and column (in characters), the byte span, and the text of the offending line
+// `has_error` is false (and the other outputs are empty/zero) if the string parses successfully
+#[no_mangle]
+pub extern "C" fn toml_edit_doc_get_error_details(
+    toml_str: LStrHandle,
+    has_error: *mut LVBool,
+    message: LStrHandle,
+    line: *mut i32,
+    column: *mut i32,
+    span_start: *mut i32,
+    span_end: *mut i32,
+    source_line: LStrHandle,
+) -> i32 {
+    call("toml_edit_doc_get_error_details", || {
+        let has_error = out(has_error, "Has error output")?;
+        let line = out(line, "Line output")?;
+        let column = out(column, "Column output")?;
+        let span_start = out(span_start, "Span start output")?;
+        let span_end = out(span_end, "Span end output")?;
+        let toml_str = read_str(toml_str)?;
+
+        let details = match Document::from_str(&toml_str) {
+            Ok(_) => None,
+            Err(error) => Some(ParseErrorDetails::new(&toml_str, &error)),
+        };
+
+        *has_error = details.is_some().into();
+
+        let details = details.unwrap_or(ParseErrorDetails {
+            message: String::new(),
+            line: 0,
+            column: 0,
+            span: 0..0,
+            source_line: String::new(),
+        });
+
+        *line = details.line as i32;
+        *column = details.column as i32;
+        *span_start = details.span.start as i32;
+        *span_end = details.span.end as i32;
+        write_str(message, &details.message)?;
+        write_str(source_line, &details.source_line)
+    })
 }
 
-// return a pointer to a Document, which can be used in other .dll functions
+// return a reference to a Document, which can be used in other .dll functions
 // takes a TOML string as an input
-#[allow(dead_code)]
 #[no_mangle]
-pub extern "C" fn toml_edit_doc_from_string(toml_str: LStrHandle) -> *mut c_void {
-    let toml_str = unsafe { toml_str.as_ref().unwrap().to_rust_string() };
+pub extern "C" fn toml_edit_doc_from_string(toml_str: LStrHandle, doc: *mut Handle) -> i32 {
+    call("toml_edit_doc_from_string", || {
+        let doc = out(doc, "Document output")?;
+        let toml_str = read_str(toml_str)?;
+
+        *doc = handle::insert(Document::from_str(&toml_str)?)?;
+        Ok(())
+    })
+}
 
-    let doc = match Document::from_str(&toml_str) {
-        Ok(doc) => doc,
-        Err(_) => {
-            println!("Unable to parse TOML string: {}", toml_str);
-            return ptr::null_mut();
-        }
-    };
+// open a TOML file and return a reference to its Document
+// a UTF-8 byte order mark is skipped, and a missing file, a permission problem and a parse
+// failure are each reported with their own error code
+#[no_mangle]
+pub extern "C" fn toml_edit_doc_open_file(path: LStrHandle, doc: *mut Handle) -> i32 {
+    call("toml_edit_doc_open_file", || {
+        let doc = out(doc, "Document output")?;
+        let path = read_str(path)?;
+
+        let (opened, format) = file::open(Path::new(&path))?;
+        *doc = insert_opened(opened, Some(format))?;
+        Ok(())
+    })
+}
 
-    let doc: Box<Document> = Box::new(doc);
+// store a Document read from a file, remembering the file's format to save it the same way
+fn insert_opened(doc: Document, format: Option<file::Format>) -> Result<Handle> {
+    let doc = handle::insert(doc)?;
+    if let Some(format) = format {
+        file::remember(doc, format);
+    }
+    Ok(doc)
+}
 
-    Box::into_raw(doc) as *mut c_void
+// save a Document to a TOML file, replacing the file if it exists
+// a Document opened from a file keeps that file's line endings (`\r\n` or `\n`) and any byte
+// order mark, even when saved to another path; any other Document takes those of the file it
+// replaces; the file is replaced in one step, so it is never left half-written
+#[no_mangle]
+pub extern "C" fn toml_edit_doc_save_file(doc: Handle, path: LStrHandle) -> i32 {
+    call("toml_edit_doc_save_file", || save_file(doc, path, 0))
 }
 
-// return a toml string from a Document
-#[allow(dead_code)]
+// save a Document to a TOML file as `toml_edit_doc_save_file` does, first copying any existing
+// file to `<path>.bak` and keeping up to `backups` older copies (`<path>.bak.1` is the next oldest)
 #[no_mangle]
-pub extern "C" fn toml_edit_doc_to_string(doc: *mut c_void, mut toml_string: LStrHandle) {
-    // todo: add better error return
-    if doc.is_null() {
-        println!("Document pointer is null");
-        toml_string.set_str("").expect("String should be valid.");
-    }
-    let doc = unsafe { &mut *(doc as *mut Document) };
+pub extern "C" fn toml_edit_doc_save_file_with_backups(
+    doc: Handle,
+    path: LStrHandle,
+    backups: i32,
+) -> i32 {
+    call("toml_edit_doc_save_file_with_backups", || {
+        let backups = u32::try_from(backups).map_err(|_| {
+            Error::new(
+                ErrorCode::InvalidValue,
+                format!("Number of backups {} is negative", backups),
+            )
+        })?;
+        save_file(doc, path, backups)
+    })
+}
 
-    let toml_str = Document::to_string(doc);
+fn save_file(doc: Handle, path: LStrHandle, backups: u32) -> Result<()> {
+    let path = read_str(path)?;
+    let toml = handle::with(doc, |doc: &mut Document| Ok(doc.to_string()))?;
 
-    toml_string
-        .set_str(&toml_str)
-        .expect("String should be valid.");
+    file::save(Path::new(&path), &toml, file::format_of(doc), backups)
 }
 
-// return a pointer to the root Table of a Document
-#[allow(dead_code)]
+// take an advisory lock on a TOML file, shared (to read it) or exclusive (to change it), and
+// return a reference that holds the lock until it is closed with `toml_edit_file_unlock`
+// waits up to `timeout_ms` for other programs to release the file (-1 waits forever)
+// the lock is on `<path>.lock`, so use `toml_edit_doc_open_file` and `toml_edit_doc_save_file`
+// (not the `_locked` functions, which would wait for this lock) while holding it
 #[no_mangle]
-pub extern "C" fn toml_edit_doc_get_root_table(doc: *mut c_void) -> *mut c_void {
-    if doc.is_null() {
-        println!("Document pointer is null");
-        return ptr::null_mut();
-    }
-    let doc = unsafe { &mut *(doc as *mut Document) };
-
-    let table = match doc.as_table() {
-        table => table,
-    };
+pub extern "C" fn toml_edit_file_lock(
+    path: LStrHandle,
+    exclusive: LVBool,
+    timeout_ms: i32,
+    lock: *mut Handle,
+) -> i32 {
+    call("toml_edit_file_lock", || {
+        let lock = out(lock, "FileLock output")?;
+        let path = read_str(path)?;
+
+        *lock = handle::insert(file::lock(
+            Path::new(&path),
+            exclusive.into(),
+            lock_timeout(timeout_ms),
+        )?)?;
+        Ok(())
+    })
+}
 
-    let table = Box::new(table.clone());
+// release a lock taken with `toml_edit_file_lock`
+#[no_mangle]
+pub extern "C" fn toml_edit_file_unlock(lock: Handle) -> i32 {
+    call("toml_edit_file_unlock", || {
+        handle::remove::<file::FileLock>(lock)?;
+        Ok(())
+    })
+}
 
-    Box::into_raw(table) as *mut c_void
+// open a TOML file as `toml_edit_doc_open_file` does, holding a shared lock while it is read
+// so that it can't be read part way through a locked save
+#[no_mangle]
+pub extern "C" fn toml_edit_doc_open_file_locked(
+    path: LStrHandle,
+    timeout_ms: i32,
+    doc: *mut Handle,
+) -> i32 {
+    call("toml_edit_doc_open_file_locked", || {
+        let doc = out(doc, "Document output")?;
+        let path = read_str(path)?;
+        let path = Path::new(&path);
+
+        let _lock = file::lock(path, false, lock_timeout(timeout_ms))?;
+        let (opened, format) = file::open(path)?;
+        *doc = insert_opened(opened, Some(format))?;
+        Ok(())
+    })
 }
 
-// Close a Document and free the memory
-#[allow(dead_code)]
+// save a Document as `toml_edit_doc_save_file` does, holding an exclusive lock while it is written
 #[no_mangle]
-pub extern "C" fn toml_edit_doc_close(doc: *mut c_void) {
-    if doc.is_null() {
-        println!("Document pointer is null");
-        return;
-    }
-    let doc = unsafe { Box::from_raw(doc as *mut Document) };
-    drop(doc);
+pub extern "C" fn toml_edit_doc_save_file_locked(
+    doc: Handle,
+    path: LStrHandle,
+    timeout_ms: i32,
+) -> i32 {
+    call("toml_edit_doc_save_file_locked", || {
+        let path = read_str(path)?;
+        let path = Path::new(&path);
+        let toml = handle::with(doc, |doc: &mut Document| Ok(doc.to_string()))?;
+
+        let _lock = file::lock(path, true, lock_timeout(timeout_ms))?;
+        file::save(path, &toml, file::format_of(doc), 0)
+    })
 }
 
-// convert from a Table to a toml string
-#[allow(dead_code)]
+// take an exclusive lock on a TOML file and open it (or start an empty Document if it doesn't
+// exist yet), to change it with the usual functions and save it with `toml_edit_file_unlock_save`
+// without another program changing it in between
+// waits up to `timeout_ms` for other programs to release the file (-1 waits forever)
 #[no_mangle]
-pub extern "C" fn toml_edit_table_to_string(table: *mut c_void, mut toml_string: LStrHandle) {
-    if table.is_null() {
-        println!("Table pointer is null");
-        toml_string.set_str("").expect("String should be valid.");
-    }
-    let table = unsafe { &mut *(table as *mut Table) };
+pub extern "C" fn toml_edit_file_lock_open(
+    path: LStrHandle,
+    timeout_ms: i32,
+    lock: *mut Handle,
+    doc: *mut Handle,
+) -> i32 {
+    call("toml_edit_file_lock_open", || {
+        let lock = out(lock, "FileLock output")?;
+        let doc = out(doc, "Document output")?;
+        let path = read_str(path)?;
+
+        let (file_lock, document, format) =
+            file::lock_open(Path::new(&path), lock_timeout(timeout_ms))?;
+        let document = insert_opened(document, format)?;
+        match handle::insert(file_lock) {
+            Ok(file_lock) => {
+                *lock = file_lock;
+                *doc = document;
+                Ok(())
+            }
+            Err(error) => {
+                let _ = handle::remove::<Document>(document);
+                file::forget(document);
+                Err(error)
+            }
+        }
+    })
+}
 
-    let toml_str = Table::to_string(table);
-    toml_string
-        .set_str(&toml_str)
-        .expect("String should be valid.");
+// save a Document to the file locked by `toml_edit_file_lock_open` (as `toml_edit_doc_save_file`
+// does), then release the lock
+// the lock is released even if the save fails, and the Document is left open either way
+#[no_mangle]
+pub extern "C" fn toml_edit_file_unlock_save(lock: Handle, doc: Handle) -> i32 {
+    call("toml_edit_file_unlock_save", || {
+        let saved = handle::with(doc, |doc: &mut Document| Ok(doc.to_string())).and_then(|toml| {
+            handle::with(lock, |lock: &mut file::FileLock| {
+                file::save_locked(lock, &toml, file::format_of(doc))
+            })
+        });
+        saved.and(handle::remove::<file::FileLock>(lock))
+    })
 }
 
-// convert a Table to an Item
-#[allow(dead_code)]
+// change a TOML file that other programs also change: under an exclusive lock, reload the file,
+// merge `edits` into it, save it, and return the updated Document
+// every key in `edits` is set in the file (tables in both are merged, key by key), so build
+// `edits` with only the keys to change, e.g. with `toml_edit_doc_set_by_path`
+// this can only add or replace keys, never remove or rename one; for that, use
+// `toml_edit_file_lock_open` and `toml_edit_file_unlock_save`
 #[no_mangle]
-pub extern "C" fn toml_edit_table_to_item(table: *mut c_void) -> *mut c_void {
-    if table.is_null() {
-        println!("Table pointer is null");
-        return ptr::null_mut();
-    }
-    let table = unsafe { &mut *(table as *mut Table) };
+pub extern "C" fn toml_edit_doc_update_file(
+    path: LStrHandle,
+    edits: Handle,
+    timeout_ms: i32,
+    doc: *mut Handle,
+) -> i32 {
+    call("toml_edit_doc_update_file", || {
+        let doc = out(doc, "Document output")?;
+        let path = read_str(path)?;
+        let edits = handle::with(edits, |edits: &mut Document| Ok(edits.clone()))?;
+
+        let (updated, format) = file::update(Path::new(&path), &edits, lock_timeout(timeout_ms))?;
+        *doc = insert_opened(updated, format)?;
+        Ok(())
+    })
+}
 
-    let item = match Item::Table(table.clone()) {
-        item => item,
-    };
+// a LabVIEW timeout in milliseconds, where a negative timeout waits forever
+fn lock_timeout(timeout_ms: i32) -> Option<std::time::Duration> {
+    u64::try_from(timeout_ms)
+        .ok()
+        .map(std::time::Duration::from_millis)
+}
 
-    let item = Box::new(item);
+// return a toml string from a Document
+#[no_mangle]
+pub extern "C" fn toml_edit_doc_to_string(doc: Handle, toml_string: LStrHandle) -> i32 {
+    call("toml_edit_doc_to_string", || {
+        let string = handle::with(doc, |doc: &mut Document| Ok(doc.to_string()))?;
 
-    Box::into_raw(item) as *mut c_void
+        write_str(toml_string, &string)
+    })
 }
 
-// convert a InlineTable to an Item
-#[allow(dead_code)]
+// return a JSON string from a Document, on one line or indented if `pretty`
+// datetimes, NaN and infinite floats, and integers beyond +/-(2^53 - 1) are written as strings
 #[no_mangle]
-pub extern "C" fn toml_edit_inline_table_to_item(inline_table: *mut c_void) -> *mut c_void {
-    if inline_table.is_null() {
-        println!("InlineTable pointer is null");
-        return ptr::null_mut();
-    }
-
-    let inline_table = unsafe { &mut *(inline_table as *mut InlineTable) };
+pub extern "C" fn toml_edit_doc_to_json(
+    doc: Handle,
+    pretty: LVBool,
+    json_string: LStrHandle,
+) -> i32 {
+    call("toml_edit_doc_to_json", || {
+        let string = handle::with(doc, |doc: &mut Document| {
+            Ok(json::to_json(doc, pretty.into()))
+        })?;
+
+        write_str(json_string, &string)
+    })
+}
 
-    let item = match toml_edit::value(inline_table.clone()) {
-        item => item,
-    };
+// return a reference to a Document made from a JSON object
+// objects become tables, arrays of only objects become arrays of tables, and null is an error
+#[no_mangle]
+pub extern "C" fn toml_edit_doc_from_json(json_string: LStrHandle, doc: *mut Handle) -> i32 {
+    call("toml_edit_doc_from_json", || {
+        let doc = out(doc, "Document output")?;
+        let json_string = read_str(json_string)?;
+
+        *doc = handle::insert(json::from_json(&json_string)?)?;
+        Ok(())
+    })
+}
 
-    let item = Box::new(item);
+// return a reference to a Document made from the text of a LabVIEW configuration (INI) file
+// unquoted values that read as booleans or numbers become them, comments are kept, and
+// anything that couldn't be carried over exactly (such as a repeated key) is listed in `lossy`
+#[no_mangle]
+pub extern "C" fn toml_edit_doc_from_ini(
+    ini_string: LStrHandle,
+    doc: *mut Handle,
+    lossy: *mut LVArrayHandle<1, LStrHandle>,
+) -> i32 {
+    call("toml_edit_doc_from_ini", || {
+        let doc = out(doc, "Document output")?;
+        let lossy = out(lossy, "Lossy output")?;
+        let ini_string = read_str(ini_string)?;
+
+        let (new_doc, conversions) = ini::from_ini(&ini_string);
+        write_str_array(lossy, &conversions)?;
+        *doc = handle::insert(new_doc)?;
+        Ok(())
+    })
+}
 
-    Box::into_raw(item) as *mut c_void
+// return the text of a LabVIEW configuration (INI) file from a Document
+// nested tables become `[a.b]` sections, arrays and inline tables are written one key per
+// value (`list.0`, `point.x`), comments are kept, and any value that couldn't be written (or
+// that is written before the first section, where the Config File VIs can't read it) is listed
+// in `lossy`
+#[no_mangle]
+pub extern "C" fn toml_edit_doc_to_ini(
+    doc: Handle,
+    ini_string: LStrHandle,
+    lossy: *mut LVArrayHandle<1, LStrHandle>,
+) -> i32 {
+    call("toml_edit_doc_to_ini", || {
+        let lossy = out(lossy, "Lossy output")?;
+        let (ini, conversions) = handle::with(doc, |doc: &mut Document| Ok(ini::to_ini(doc)))?;
+
+        write_str(ini_string, &ini)?;
+        write_str_array(lossy, &conversions)
+    })
 }
 
-// list the tables in a Document as a multi-line string
-#[allow(dead_code)]
+// return a reference to the root Table of a Document
+// the Table is a live view, so changes made through it edit the Document
 #[no_mangle]
-pub extern "C" fn toml_edit_doc_list_tables(doc: *mut c_void, mut tables: LStrHandle) {
-    if doc.is_null() {
-        println!("Document pointer is null");
-        let _ = tables.set_str("");
-        return;
-    }
+pub extern "C" fn toml_edit_doc_get_root_table(doc: Handle, table: *mut Handle) -> i32 {
+    call("toml_edit_doc_get_root_table", || {
+        let table = out(table, "Table output")?;
+
+        *table = handle::view::<Document, Table>(doc, &[])?;
+        Ok(())
+    })
+}
 
-    let doc = unsafe { &mut *(doc as *mut Document) };
+// Close a Document and free the memory
+#[no_mangle]
+pub extern "C" fn toml_edit_doc_close(doc: Handle) -> i32 {
+    call("toml_edit_doc_close", || {
+        handle::remove::<Document>(doc)?;
+        file::forget(doc);
+        Ok(())
+    })
+}
 
-    let mut table_list = String::new();
+// convert from a Table to a toml string
+#[no_mangle]
+pub extern "C" fn toml_edit_table_to_string(table: Handle, toml_string: LStrHandle) -> i32 {
+    call("toml_edit_table_to_string", || {
+        let string = handle::with(table, |table: &mut Table| Ok(table.to_string()))?;
 
-    for table in doc.as_table() {
-        table_list.push_str(&format!("{}\n", table.0));
-    }
+        write_str(toml_string, &string)
+    })
+}
 
-    let _ = tables.set_str(&table_list);
+// convert a Table to an Item
+#[no_mangle]
+pub extern "C" fn toml_edit_table_to_item(table: Handle, item: *mut Handle) -> i32 {
+    call("toml_edit_table_to_item", || {
+        let item = out(item, "Item output")?;
+        let found = handle::with(table, |table: &mut Table| Ok(table.clone()))?;
+
+        *item = handle::insert(Item::Table(found))?;
+        Ok(())
+    })
 }
 
-// return a pointer to a Table, which can be used in other .dll functions
-// takes a Document and a table name as inputs
-#[allow(dead_code)]
+// convert a InlineTable to an Item
 #[no_mangle]
-pub extern "C" fn toml_edit_doc_get_table(doc: *mut c_void, table_name: LStrHandle) -> *mut c_void {
-    if doc.is_null() {
-        println!("Document pointer is null");
-        return ptr::null_mut();
-    }
-    let doc = unsafe { &mut *(doc as *mut Document) };
-    let table_name = table_name.to_rust_string();
+pub extern "C" fn toml_edit_inline_table_to_item(inline_table: Handle, item: *mut Handle) -> i32 {
+    call("toml_edit_inline_table_to_item", || {
+        let item = out(item, "Item output")?;
+        let found = handle::with(inline_table, |inline_table: &mut InlineTable| {
+            Ok(inline_table.clone())
+        })?;
+
+        *item = handle::insert(toml_edit::value(found))?;
+        Ok(())
+    })
+}
 
-    let table = match doc[&table_name].as_table() {
-        Some(table) => table,
-        None => {
-            println!("Unable to find table: {}", table_name);
-            return ptr::null_mut();
-        }
-    };
+// convert an Array to an Item
+#[no_mangle]
+pub extern "C" fn toml_edit_array_to_item(array: Handle, item: *mut Handle) -> i32 {
+    call("toml_edit_array_to_item", || {
+        let item = out(item, "Item output")?;
+        let found = handle::with(array, |array: &mut Array| Ok(array.clone()))?;
+
+        *item = handle::insert(toml_edit::value(found))?;
+        Ok(())
+    })
+}
 
-    let table = Box::new(table.clone());
+// list the keys in the root table of a Document as a LabVIEW array of strings
+#[no_mangle]
+pub extern "C" fn toml_edit_doc_list_tables(
+    doc: Handle,
+    tables: *mut LVArrayHandle<1, LStrHandle>,
+) -> i32 {
+    call("toml_edit_doc_list_tables", || {
+        let tables = out(tables, "Tables output")?;
+        let keys = handle::with(doc, |doc: &mut Document| {
+            Ok(doc
+                .as_table()
+                .iter()
+                .map(|(key, _)| key.to_string())
+                .collect::<Vec<_>>())
+        })?;
+
+        write_str_array(tables, &keys)
+    })
+}
 
-    Box::into_raw(table) as *mut c_void
+// return a reference to a Table, which can be used in other .dll functions
+// takes a Document and a table name as inputs
+// the Table is a live view, so changes made through it edit the Document
+#[no_mangle]
+pub extern "C" fn toml_edit_doc_get_table(
+    doc: Handle,
+    table_name: LStrHandle,
+    table: *mut Handle,
+) -> i32 {
+    call("toml_edit_doc_get_table", || {
+        let table = out(table, "Table output")?;
+        let table_name = read_str(table_name)?;
+
+        *table = handle::view::<Document, Table>(doc, &[Step::Key(table_name)])?;
+        Ok(())
+    })
 }
 
 // set an item in the root table of a Document
 // takes a Document, a key, and a Item as inputs
-#[allow(dead_code)]
 #[no_mangle]
-pub extern "C" fn toml_edit_doc_set_item(
-    doc: *mut c_void,
-    key: LStrHandle,
-    item: *mut c_void,
-) -> *mut c_void {
-    if doc.is_null() {
-        println!("Document is null");
-        return ptr::null_mut();
-    }
-    if item.is_null() {
-        println!("Item is null");
-        return ptr::null_mut();
-    }
+pub extern "C" fn toml_edit_doc_set_item(doc: Handle, key: LStrHandle, item: Handle) -> i32 {
+    call("toml_edit_doc_set_item", || {
+        let item = handle::with(item, |item: &mut Item| Ok(item.clone()))?;
+        let key = read_str(key)?;
+
+        handle::with(doc, |doc: &mut Document| {
+            doc[&key] = item;
+            Ok(())
+        })
+    })
+}
 
-    let doc = unsafe { &mut *(doc as *mut Document) };
-    let key = key.to_rust_string();
-    let item = unsafe { &mut *(item as *mut Item) };
+// return a copy of the item at a key path in a Document, e.g. `nipm.dependencies.my_package2.version`
+// keys can be quoted (`tool."my.package"`) and array elements selected by index (`servers[2].host`)
+#[no_mangle]
+pub extern "C" fn toml_edit_doc_get_by_path(
+    doc: Handle,
+    path: LStrHandle,
+    item: *mut Handle,
+) -> i32 {
+    call("toml_edit_doc_get_by_path", || {
+        let item = out(item, "Item output")?;
+        let path = path::parse(&read_str(path)?)?;
+
+        let found = handle::with(doc, |doc: &mut Document| {
+            Ok(path::get(Node::Item(doc.as_item_mut()), &path)?.to_item())
+        })?;
+
+        *item = handle::insert(found)?;
+        Ok(())
+    })
+}
 
-    doc[&key] = item.clone();
+// set the item at a key path in a Document, replacing any existing item in place
+// if `create_missing` is true, any missing tables along the path are created
+#[no_mangle]
+pub extern "C" fn toml_edit_doc_set_by_path(
+    doc: Handle,
+    path: LStrHandle,
+    item: Handle,
+    create_missing: LVBool,
+) -> i32 {
+    call("toml_edit_doc_set_by_path", || {
+        let item = handle::with(item, |item: &mut Item| Ok(item.clone()))?;
+        let path = path::parse(&read_str(path)?)?;
+
+        handle::with(doc, |doc: &mut Document| {
+            path::set(
+                Node::Item(doc.as_item_mut()),
+                &path,
+                item,
+                create_missing.into(),
+            )
+        })
+    })
+}
 
-    doc as *mut Document as *mut c_void
+// remove the item at a key path in a Document, and return whether it existed
+#[no_mangle]
+pub extern "C" fn toml_edit_doc_remove_by_path(
+    doc: Handle,
+    path: LStrHandle,
+    removed: *mut LVBool,
+) -> i32 {
+    call("toml_edit_doc_remove_by_path", || {
+        let removed = out(removed, "Removed output")?;
+        let path = path::parse(&read_str(path)?)?;
+
+        let remove = |doc: &mut Document| path::remove(Node::Item(doc.as_item_mut()), &path);
+        // removing an element moves along the ones after it
+        *removed = match path.split_last() {
+            Some((Step::Index(_), array)) => handle::with_shifting(doc, array, remove),
+            _ => handle::with(doc, remove),
+        }?
+        .into();
+        Ok(())
+    })
 }
 
-#[allow(dead_code)]
+// rename the key at a key path in a Document, e.g. `nipm.dependencies.my_package2` to
+// `my_package3`, keeping its place in its table, the comments around it and its value
+// fails if another item already has the new key
 #[no_mangle]
-pub extern "C" fn toml_edit_table_list_items(table: *mut c_void, mut item_list_output: LStrHandle) {
-    if table.is_null() {
-        println!("Table is null");
-        let _ = item_list_output.set_str("");
-        return;
-    }
+pub extern "C" fn toml_edit_doc_rename_by_path(
+    doc: Handle,
+    path: LStrHandle,
+    new_key: LStrHandle,
+) -> i32 {
+    call("toml_edit_doc_rename_by_path", || {
+        let path = path::parse(&read_str(path)?)?;
+        let new_key = read_str(new_key)?;
+
+        handle::with(doc, |doc: &mut Document| {
+            path::rename(Node::Item(doc.as_item_mut()), &path, &new_key)
+        })
+    })
+}
 
-    let table = unsafe { &mut *(table as *mut Table) };
+// write every `[table]` section in a Document in key order, each straight after its parent
+// (so after sorting keys with `toml_edit_table_sort_items`, the sections are sorted too)
+#[no_mangle]
+pub extern "C" fn toml_edit_doc_sort_tables(doc: Handle) -> i32 {
+    call("toml_edit_doc_sort_tables", || {
+        handle::with(doc, |doc: &mut Document| {
+            keys::sort_tables(doc.as_table_mut());
+            Ok(())
+        })
+    })
+}
 
-    let mut item_list = String::new();
+// list the keys in a Table as a LabVIEW array of strings
+#[no_mangle]
+pub extern "C" fn toml_edit_table_list_items(
+    table: Handle,
+    keys: *mut LVArrayHandle<1, LStrHandle>,
+) -> i32 {
+    call("toml_edit_table_list_items", || {
+        let keys = out(keys, "Keys output")?;
+        let (key_list, _) = handle::with(table, |table: &mut Table| Ok(table_entries(table)))?;
+
+        write_str_array(keys, &key_list)
+    })
+}
 
-    for item in table.iter() {
-        item_list.push_str(&format!("{}\n", item.0));
-    }
+// list the keys in a Table, and the type of each item, as two LabVIEW arrays of strings
+// (a type is `Table`, `ArrayOfTables`, or the type of a value, as `toml_edit_get_value_type` names it)
+#[no_mangle]
+pub extern "C" fn toml_edit_table_list_items_with_types(
+    table: Handle,
+    keys: *mut LVArrayHandle<1, LStrHandle>,
+    types: *mut LVArrayHandle<1, LStrHandle>,
+) -> i32 {
+    call("toml_edit_table_list_items_with_types", || {
+        let keys = out(keys, "Keys output")?;
+        let types = out(types, "Types output")?;
+        let (key_list, type_list) =
+            handle::with(table, |table: &mut Table| Ok(table_entries(table)))?;
+
+        write_str_array(keys, &key_list)?;
+        write_str_array(types, &type_list)
+    })
+}
 
-    let _ = item_list_output.set_str(&item_list);
+// the keys of a Table, and the type names of their items
+fn table_entries(table: &Table) -> (Vec<String>, Vec<&'static str>) {
+    table
+        .iter()
+        .map(|(key, item)| (key.to_string(), item_type_name(item)))
+        .unzip()
 }
 
 // remove an item from a Table
-// takes a Table and a item name as inputs
-#[allow(dead_code)]
+// takes a Table and a item name as inputs, and returns whether the item existed
 #[no_mangle]
-pub extern "C" fn toml_edit_table_remove_item(table: *mut c_void, key: LStrHandle) -> u64 {
-    // todo: return -1 on error? need better error return.
-    if table.is_null() {
-        println!("Table is null");
-        return 0;
-    }
-    let table = unsafe { &mut *(table as *mut Table) };
-    let key = key.to_rust_string();
+pub extern "C" fn toml_edit_table_remove_item(
+    table: Handle,
+    key: LStrHandle,
+    removed: *mut LVBool,
+) -> i32 {
+    call("toml_edit_table_remove_item", || {
+        let removed = out(removed, "Removed output")?;
+        let key = read_str(key)?;
+
+        *removed =
+            handle::with(table, |table: &mut Table| Ok(table.remove(&key).is_some()))?.into();
+        Ok(())
+    })
+}
+
+// rename a key in a Table, keeping its place, the comments around it and its item
+// fails if the Table already has the new key
+#[no_mangle]
+pub extern "C" fn toml_edit_table_rename_key(
+    table: Handle,
+    old_key: LStrHandle,
+    new_key: LStrHandle,
+) -> i32 {
+    call("toml_edit_table_rename_key", || {
+        let old_key = read_str(old_key)?;
+        let new_key = read_str(new_key)?;
+
+        handle::with(table, |table: &mut Table| {
+            keys::rename(table, &old_key, &new_key)
+        })
+    })
+}
+
+// get the comments and whitespace around a key in a Table
+// the prefix is everything before the key on its line and the lines above it (e.g. `# note\n`),
+// and the suffix is the whitespace between the key and the `=`
+// (the comment at the end of a `key = value` line is the suffix of the value, see
+// `toml_edit_value_get_decor`)
+#[no_mangle]
+pub extern "C" fn toml_edit_table_get_key_decor(
+    table: Handle,
+    key: LStrHandle,
+    prefix: LStrHandle,
+    suffix: LStrHandle,
+) -> i32 {
+    call("toml_edit_table_get_key_decor", || {
+        let key = read_str(key)?;
+        let (prefix_text, suffix_text) = handle::with(table, |table: &mut Table| {
+            let decor = table
+                .key_decor(&key)
+                .ok_or_else(|| Error::not_found("Key", &key))?;
+            Ok(decor::get(decor))
+        })?;
+
+        write_str(prefix, &prefix_text)?;
+        write_str(suffix, &suffix_text)
+    })
+}
 
-    return if table.contains_key(&key) {
-        table.remove(&key);
-        1
-    } else {
-        0
-    };
+// set the comments and whitespace around a key in a Table (see `toml_edit_table_get_key_decor`)
+// e.g. a prefix of `# set by calibration on 2024-01-31\n` puts a comment line above the key
+// (the suffix sits between the key and the `=`, so only spaces and tabs are allowed there)
+#[no_mangle]
+pub extern "C" fn toml_edit_table_set_key_decor(
+    table: Handle,
+    key: LStrHandle,
+    prefix: LStrHandle,
+    suffix: LStrHandle,
+) -> i32 {
+    call("toml_edit_table_set_key_decor", || {
+        let key = read_str(key)?;
+        let prefix = read_str(prefix)?;
+        let suffix = read_str(suffix)?;
+
+        handle::with(table, |table: &mut Table| {
+            let decor = table
+                .key_decor_mut(&key)
+                .ok_or_else(|| Error::not_found("Key", &key))?;
+            decor::set(decor, Place::Key, &prefix, &suffix)
+        })
+    })
+}
+
+// get the comments and whitespace around a Table's `[header]`
+// the prefix is the lines above the header, and the suffix is the rest of the header's line
+#[no_mangle]
+pub extern "C" fn toml_edit_table_get_decor(
+    table: Handle,
+    prefix: LStrHandle,
+    suffix: LStrHandle,
+) -> i32 {
+    call("toml_edit_table_get_decor", || {
+        let (prefix_text, suffix_text) =
+            handle::with(table, |table: &mut Table| Ok(decor::get(table.decor())))?;
+
+        write_str(prefix, &prefix_text)?;
+        write_str(suffix, &suffix_text)
+    })
+}
+
+// set the comments and whitespace around a Table's `[header]` (see `toml_edit_table_get_decor`)
+// (the suffix may end in a comment, but not go on to another line)
+// fails for a table that is written without a header, as they would never be written: the
+// root table, a dotted table, and an implicit table with no values of its own
+#[no_mangle]
+pub extern "C" fn toml_edit_table_set_decor(
+    table: Handle,
+    prefix: LStrHandle,
+    suffix: LStrHandle,
+) -> i32 {
+    call("toml_edit_table_set_decor", || {
+        let prefix = read_str(prefix)?;
+        let suffix = read_str(suffix)?;
+
+        handle::with_parent(table, |table: &mut Table, parent| {
+            let headerless = match parent {
+                Parent::Document => Some("the root table"),
+                _ if table.is_dotted() => Some("a dotted table"),
+                _ if table.is_implicit() && table.get_values().is_empty() => {
+                    Some("an implicit table with no values")
+                }
+                _ => None,
+            };
+            if let Some(headerless) = headerless {
+                return Err(Error::new(
+                    ErrorCode::InvalidValue,
+                    format!(
+                        "Table has no [header] for comments to go around ({})",
+                        headerless
+                    ),
+                ));
+            }
+            decor::set(table.decor_mut(), Place::Header, &prefix, &suffix)
+        })
+    })
 }
 
 // Close a Table and free the memory
-#[allow(dead_code)]
 #[no_mangle]
-pub extern "C" fn toml_edit_table_close(table: *mut c_void) {
-    if table.is_null() {
-        println!("Table is null");
-        return;
-    }
-    let table = unsafe { Box::from_raw(table as *mut Table) };
-    drop(table);
+pub extern "C" fn toml_edit_table_close(table: Handle) -> i32 {
+    call("toml_edit_table_close", || {
+        handle::remove::<Table>(table)?;
+        Ok(())
+    })
 }
 
-// return a pointer to a Item, which can be used in other .dll functions
+// return a reference to a Item, which can be used in other .dll functions
 // takes a Table and a item name as inputs
-#[allow(dead_code)]
+// the Item is a live view, so changes made through it edit the Table
 #[no_mangle]
 pub extern "C" fn toml_edit_table_get_item(
-    table: *mut c_void,
+    table: Handle,
     item_name: LStrHandle,
-) -> *mut c_void {
-    if table.is_null() {
-        println!("Table is null");
-        return ptr::null_mut();
-    }
+    item: *mut Handle,
+) -> i32 {
+    call("toml_edit_table_get_item", || {
+        let item = out(item, "Item output")?;
+        let item_name = read_str(item_name)?;
+
+        *item = handle::view::<Table, Item>(table, &[Step::Key(item_name)])?;
+        Ok(())
+    })
+}
+
+// set a Item in a Table
+#[no_mangle]
+pub extern "C" fn toml_edit_table_set_item(table: Handle, key: LStrHandle, item: Handle) -> i32 {
+    call("toml_edit_table_set_item", || {
+        let item = handle::with(item, |item: &mut Item| Ok(item.clone()))?;
+        let key = read_str(key)?;
+
+        handle::with(table, |table: &mut Table| {
+            table.insert(&key, item);
+            Ok(())
+        })
+    })
+}
+
+// sort the keys of a Table alphabetically, and optionally those of every table inside it
+// (the comments at the top of a document's root table stay at the top of the file)
+#[no_mangle]
+pub extern "C" fn toml_edit_table_sort_items(table: Handle, recursive: LVBool) -> i32 {
+    call("toml_edit_table_sort_items", || {
+        handle::with_parent(table, |table: &mut Table, parent| {
+            match parent {
+                // the root table keeps the file's leading comments at the top
+                Parent::Document => keys::sort_root(table, &Order::Alphabetical, recursive.into()),
+                _ => keys::sort(table, &Order::Alphabetical, recursive.into()),
+            }
+            Ok(())
+        })
+    })
+}
+
+// sort the keys of a Table into the order given by a LabVIEW array of keys, and optionally
+// those of every table inside it (keys that aren't in the array go after, in their own order)
+#[no_mangle]
+pub extern "C" fn toml_edit_table_sort_items_by_order(
+    table: Handle,
+    order: LVArrayHandle<1, LStrHandle>,
+    recursive: LVBool,
+) -> i32 {
+    call("toml_edit_table_sort_items_by_order", || {
+        let order = read_str_array(&order)?;
+
+        handle::with_parent(table, |table: &mut Table, parent| {
+            match parent {
+                // the root table keeps the file's leading comments at the top
+                Parent::Document => {
+                    keys::sort_root(table, &Order::Listed(&order), recursive.into())
+                }
+                _ => keys::sort(table, &Order::Listed(&order), recursive.into()),
+            }
+            Ok(())
+        })
+    })
+}
 
-    let table = unsafe { &mut *(table as *mut Table) };
-    let item_name = item_name.to_rust_string();
+// get the position of a Table's `[header]` in its Document, which sets the order that
+// `[table]` sections are written in (-1 for a table that has no position yet)
+#[no_mangle]
+pub extern "C" fn toml_edit_table_get_position(table: Handle, position: *mut i32) -> i32 {
+    call("toml_edit_table_get_position", || {
+        let position = out(position, "Position output")?;
+
+        let found = handle::with(table, |table: &mut Table| Ok(table.position()))?;
+        *position = found.map_or(-1, |found| found as i32);
+        Ok(())
+    })
+}
 
-    let item = match table[&item_name].clone() {
-        item => item,
-    };
+// move a Table's `[header]`, and those of the tables inside it, to a position in its Document
+// (sections are written from the lowest position to the highest, and sections at the same
+// position in key order)
+#[no_mangle]
+pub extern "C" fn toml_edit_table_set_position(table: Handle, position: i32) -> i32 {
+    call("toml_edit_table_set_position", || {
+        let position = usize::try_from(position).map_err(|_| {
+            Error::new(
+                ErrorCode::InvalidValue,
+                format!("Position can't be negative: {}", position),
+            )
+        })?;
+
+        handle::with(table, |table: &mut Table| {
+            array::set_position(table, position);
+            Ok(())
+        })
+    })
+}
 
-    let item = Box::new(item);
+// write the table (or array of tables) at a key in a Table as an inline table (or an array of
+// inline tables), e.g. `[a]` and `b = 1` as `a = { b = 1 }`, along with every table inside it
+#[no_mangle]
+pub extern "C" fn toml_edit_table_convert_item_to_inline(table: Handle, key: LStrHandle) -> i32 {
+    call("toml_edit_table_convert_item_to_inline", || {
+        let key = read_str(key)?;
 
-    Box::into_raw(item) as *mut c_void
+        handle::with(table, |table: &mut Table| style::make_inline(table, &key))
+    })
 }
 
-// set a Item in a Table
-#[allow(dead_code)]
+// write the inline table (or array of inline tables) at a key in a Table as a `[header]` table
+// (or an array of tables), optionally along with every inline table inside it
 #[no_mangle]
-pub extern "C" fn toml_edit_table_set_item(table: *mut c_void, key: LStrHandle, item: *mut c_void) {
-    if table.is_null() {
-        println!("Table is null");
-        return;
-    }
-    if item.is_null() {
-        println!("Item is null");
-        return;
-    }
+pub extern "C" fn toml_edit_table_convert_item_to_table(
+    table: Handle,
+    key: LStrHandle,
+    recursive: LVBool,
+) -> i32 {
+    call("toml_edit_table_convert_item_to_table", || {
+        let key = read_str(key)?;
+
+        handle::with(table, |table: &mut Table| {
+            style::make_table(table, &key, recursive.into())
+        })
+    })
+}
+
+// write the table at a key in a Table with dotted keys (`a.b = 1`), or as a `[header]` table,
+// optionally along with every table inside it
+// dotted keys can only hold comments when the table is written on one line, so a table with
+// comments on more lines than that fails (5009) and is left as it was
+#[no_mangle]
+pub extern "C" fn toml_edit_table_set_item_dotted(
+    table: Handle,
+    key: LStrHandle,
+    dotted: LVBool,
+    recursive: LVBool,
+) -> i32 {
+    call("toml_edit_table_set_item_dotted", || {
+        let key = read_str(key)?;
+
+        handle::with(table, |table: &mut Table| {
+            style::set_dotted(table, &key, dotted.into(), recursive.into())
+        })
+    })
+}
+
+// check whether a Table is written with dotted keys (`a.b = 1`) rather than a `[header]`
+#[no_mangle]
+pub extern "C" fn toml_edit_table_is_dotted(table: Handle, dotted: *mut LVBool) -> i32 {
+    call("toml_edit_table_is_dotted", || {
+        let dotted = out(dotted, "Dotted output")?;
+
+        *dotted = handle::with(table, |table: &mut Table| Ok(table.is_dotted()))?.into();
+        Ok(())
+    })
+}
+
+// mark a Table as implicit, so its `[header]` is left out while it has no values of its own
+// (as `[a]` is when only `[a.b]` is written), or not, optionally along with every table inside it
+#[no_mangle]
+pub extern "C" fn toml_edit_table_set_implicit(
+    table: Handle,
+    implicit: LVBool,
+    recursive: LVBool,
+) -> i32 {
+    call("toml_edit_table_set_implicit", || {
+        handle::with(table, |table: &mut Table| {
+            style::set_implicit(table, implicit.into(), recursive.into());
+            Ok(())
+        })
+    })
+}
+
+// check whether a Table is implicit (see `toml_edit_table_set_implicit`)
+#[no_mangle]
+pub extern "C" fn toml_edit_table_is_implicit(table: Handle, implicit: *mut LVBool) -> i32 {
+    call("toml_edit_table_is_implicit", || {
+        let implicit = out(implicit, "Implicit output")?;
+
+        *implicit = handle::with(table, |table: &mut Table| Ok(table.is_implicit()))?.into();
+        Ok(())
+    })
+}
+
+// return a new InlineTable with the contents of a Table (and of every table inside it, as an
+// inline table can only hold values)
+#[no_mangle]
+pub extern "C" fn toml_edit_table_to_inline_table(table: Handle, inline_table: *mut Handle) -> i32 {
+    call("toml_edit_table_to_inline_table", || {
+        let inline_table = out(inline_table, "InlineTable output")?;
+        let found = handle::with(table, |table: &mut Table| Ok(table.clone()))?;
+
+        *inline_table = handle::insert(found.into_inline_table())?;
+        Ok(())
+    })
+}
+
+// get the zero-based place of a key among the keys of a Table
+#[no_mangle]
+pub extern "C" fn toml_edit_table_get_item_index(
+    table: Handle,
+    key: LStrHandle,
+    index: *mut i32,
+) -> i32 {
+    call("toml_edit_table_get_item_index", || {
+        let index = out(index, "Index output")?;
+        let key = read_str(key)?;
+
+        let found = handle::with(table, |table: &mut Table| keys::index(table, &key))?;
+        *index = found as i32;
+        Ok(())
+    })
+}
+
+// add an Item to a Table under a new key, at a zero-based place among its keys
+// (an index equal to the number of keys adds it at the end)
+// fails if the Table already has the key
+#[no_mangle]
+pub extern "C" fn toml_edit_table_insert_item(
+    table: Handle,
+    index: i32,
+    key: LStrHandle,
+    item: Handle,
+) -> i32 {
+    call("toml_edit_table_insert_item", || {
+        let item = handle::with(item, |item: &mut Item| Ok(item.clone()))?;
+        let key = read_str(key)?;
+
+        handle::with(table, |table: &mut Table| {
+            // inserting at the length is allowed, so check against one past the end
+            let index = array_index(index, table.len() + 1)
+                .map_err(|_| Error::out_of_range(index, table.len()))?;
+            keys::insert(table, index, &key, item)
+        })
+    })
+}
 
-    let table = unsafe { &mut *(table as *mut Table) };
-    let key = key.to_rust_string();
-    let item = unsafe { &mut *(item as *mut Item) };
+// add an Item to a Table under a new key, just before an existing key
+// fails if the Table already has the new key
+#[no_mangle]
+pub extern "C" fn toml_edit_table_insert_item_before(
+    table: Handle,
+    before_key: LStrHandle,
+    key: LStrHandle,
+    item: Handle,
+) -> i32 {
+    call("toml_edit_table_insert_item_before", || {
+        let item = handle::with(item, |item: &mut Item| Ok(item.clone()))?;
+        let before_key = read_str(before_key)?;
+        let key = read_str(key)?;
+
+        handle::with(table, |table: &mut Table| {
+            let index = keys::index(table, &before_key)?;
+            keys::insert(table, index, &key, item)
+        })
+    })
+}
 
-    table.insert(&key, item.clone());
+// add an Item to a Table under a new key, just after an existing key
+// fails if the Table already has the new key
+#[no_mangle]
+pub extern "C" fn toml_edit_table_insert_item_after(
+    table: Handle,
+    after_key: LStrHandle,
+    key: LStrHandle,
+    item: Handle,
+) -> i32 {
+    call("toml_edit_table_insert_item_after", || {
+        let item = handle::with(item, |item: &mut Item| Ok(item.clone()))?;
+        let after_key = read_str(after_key)?;
+        let key = read_str(key)?;
+
+        handle::with(table, |table: &mut Table| {
+            let index = keys::index(table, &after_key)? + 1;
+            keys::insert(table, index, &key, item)
+        })
+    })
 }
 
 // get the type of a value
 // takes a value as input
-#[allow(dead_code)]
 #[no_mangle]
-pub extern "C" fn toml_edit_get_value_type(value: *mut c_void, mut type_name: LStrHandle) {
-    if value.is_null() {
-        println!("Value is null");
-        return type_name.set_str("").expect("Valid Handle");
-    }
+pub extern "C" fn toml_edit_get_value_type(value: Handle, type_name: LStrHandle) -> i32 {
+    call("toml_edit_get_value_type", || {
+        let value_type = handle::with(value, |value: &mut Value| Ok(value_type_name(value)))?;
 
-    let value = unsafe { &mut *(value as *mut Value) };
+        write_str(type_name, value_type)
+    })
+}
 
-    let value_type = match value {
+fn value_type_name(value: &Value) -> &'static str {
+    match value {
         Value::String(_) => "String",
         Value::Integer(_) => "Integer",
         Value::Float(_) => "Float",
@@ -341,450 +1122,1251 @@ pub extern "C" fn toml_edit_get_value_type(value: *mut c_void, mut type_name: LS
         Value::Datetime(_) => "Datetime",
         Value::Array(_) => "Array",
         Value::InlineTable(_) => "InlineTable",
-    };
+    }
+}
 
-    type_name.set_str(value_type).expect("Valid Handle");
+// the type of an Item when listing a table: the type of its value, if it is one
+fn item_type_name(item: &Item) -> &'static str {
+    match item {
+        Item::None => "None",
+        Item::Value(value) => value_type_name(value),
+        Item::Table(_) => "Table",
+        Item::ArrayOfTables(_) => "ArrayOfTables",
+    }
 }
 
 // get the type of a Item
 // takes a Item as input
-#[allow(dead_code)]
 #[no_mangle]
-pub extern "C" fn toml_edit_item_get_type(item: *mut c_void, mut type_name: LStrHandle) {
-    // check if item has a null value
-    if item.is_null() {
-        return type_name.set_str("None").expect("Handle is Valid");
-    }
-
-    let item = unsafe { &mut *(item as *mut Item) };
-
-    let item_type = match item {
-        Item::None => "None",
-        Item::Value(_) => "Value",
-        Item::ArrayOfTables(_) => "ArrayOfTables",
-        Item::Table(_) => "Table",
-    };
+pub extern "C" fn toml_edit_item_get_type(item: Handle, type_name: LStrHandle) -> i32 {
+    call("toml_edit_item_get_type", || {
+        // a null item has no type
+        if item == 0 {
+            return write_str(type_name, "None");
+        }
 
-    type_name.set_str(item_type).expect("Valid String Handle");
+        let item_type = handle::with(item, |item: &mut Item| {
+            Ok(match item {
+                Item::None => "None",
+                Item::Value(_) => "Value",
+                Item::ArrayOfTables(_) => "ArrayOfTables",
+                Item::Table(_) => "Table",
+            })
+        })?;
+
+        write_str(type_name, item_type)
+    })
 }
 
 // get a value from a Item
-// takes a Item as input
-#[allow(dead_code)]
+// takes a Item as input, and returns a live view of its Value
 #[no_mangle]
-pub extern "C" fn toml_edit_item_into_value(item: *mut c_void) -> *mut c_void {
-    if item.is_null() {
-        println!("Item is null");
-        return ptr::null_mut();
-    }
+pub extern "C" fn toml_edit_item_into_value(item: Handle, value: *mut Handle) -> i32 {
+    call("toml_edit_item_into_value", || {
+        let value = out(value, "Value output")?;
 
-    let item = unsafe { &mut *(item as *mut Item) };
-
-    let value = match item {
-        Item::Value(value) => value,
-        _ => {
-            println!("Item is not a Value");
-            return ptr::null_mut();
-        }
-    };
-
-    let value = Box::new(value.clone());
-
-    Box::into_raw(value) as *mut c_void
+        *value = handle::view::<Item, Value>(item, &[])?;
+        Ok(())
+    })
 }
 
 // get a Table from a Item
-// takes a Item as input
-#[allow(dead_code)]
+// takes a Item as input, and returns a live view of its Table
 #[no_mangle]
-pub extern "C" fn toml_edit_item_into_table(item: *mut c_void) -> *mut c_void {
-    if item.is_null() {
-        println!("Item is null");
-        return ptr::null_mut();
-    }
+pub extern "C" fn toml_edit_item_into_table(item: Handle, table: *mut Handle) -> i32 {
+    call("toml_edit_item_into_table", || {
+        let table = out(table, "Table output")?;
 
-    let item = unsafe { &mut *(item as *mut Item) };
+        *table = handle::view::<Item, Table>(item, &[])?;
+        Ok(())
+    })
+}
 
-    let table = match item {
-        Item::Table(table) => table,
-        _ => {
-            println!("Item is not a Table");
-            return ptr::null_mut();
-        }
-    };
+// get an Array typed Value from a value
+// takes a value as input and returns a reference to an Array (a live view of the Value)
+#[no_mangle]
+pub extern "C" fn toml_edit_value_get_array(value: Handle, array: *mut Handle) -> i32 {
+    call("toml_edit_value_get_array", || {
+        let array = out(array, "Array output")?;
+
+        *array = handle::view::<Value, Array>(value, &[])?;
+        Ok(())
+    })
+}
 
-    let table = Box::new(table.clone());
+// get the comments and whitespace around a Value
+// the prefix is the whitespace between the `=` and the value, and the suffix is what follows it
+// on the line, e.g. `  # volts` (in an Array, the suffix comes before the `,`)
+#[no_mangle]
+pub extern "C" fn toml_edit_value_get_decor(
+    value: Handle,
+    prefix: LStrHandle,
+    suffix: LStrHandle,
+) -> i32 {
+    call("toml_edit_value_get_decor", || {
+        let (prefix_text, suffix_text) =
+            handle::with(value, |value: &mut Value| Ok(decor::get(value.decor())))?;
+
+        write_str(prefix, &prefix_text)?;
+        write_str(suffix, &suffix_text)
+    })
+}
 
-    Box::into_raw(table) as *mut c_void
+// set the comments and whitespace around a Value (see `toml_edit_value_get_decor`)
+// e.g. a suffix of ` # set by calibration on 2024-01-31` adds a comment at the end of the line
+// (the prefix only takes spaces and tabs; a value in an Array takes line endings but no comments,
+// and a value in an InlineTable only spaces and tabs)
+#[no_mangle]
+pub extern "C" fn toml_edit_value_set_decor(
+    value: Handle,
+    prefix: LStrHandle,
+    suffix: LStrHandle,
+) -> i32 {
+    call("toml_edit_value_set_decor", || {
+        let prefix = read_str(prefix)?;
+        let suffix = read_str(suffix)?;
+
+        handle::with_parent(value, |value: &mut Value, parent| {
+            let place = match parent {
+                Parent::None | Parent::Document | Parent::Table => Place::Value,
+                Parent::Array => Place::ArrayElement,
+                Parent::InlineTable => Place::Inline,
+            };
+            decor::set(value.decor_mut(), place, &prefix, &suffix)
+        })
+    })
 }
 
 // get a String typed Value from a value
 // takes a value as input
-#[allow(dead_code)]
 #[no_mangle]
-pub extern "C" fn toml_edit_value_get_string(value: *mut c_void, mut value_string: LStrHandle) {
-    // need a better error return
-    if value.is_null() {
-        println!("Value is null");
-        return;
-    }
-
-    let value = unsafe { &mut *(value as *mut Value) };
-
-    let value = match value {
-        Value::String(value) => value,
-        _ => {
-            println!("Value is not a String");
-            return;
-        }
-    };
-    value_string
-        .set_str(value.value())
-        .expect("String can be written");
+pub extern "C" fn toml_edit_value_get_string(value: Handle, value_string: LStrHandle) -> i32 {
+    call("toml_edit_value_get_string", || {
+        let string = handle::with(value, |value: &mut Value| {
+            value
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| Error::wrong_type("Value", "String"))
+        })?;
+
+        write_str(value_string, &string)
+    })
 }
 
 // get a i64 typed Value from a value
 // takes a value as input
-#[allow(dead_code)]
 #[no_mangle]
-pub extern "C" fn toml_edit_value_get_i64(value: *mut c_void) -> i64 {
-    // we really need a better way to return an error code. e.g. 0 and -1 are valid values for i64
-    if value.is_null() {
-        println!("Value is null");
-        return 0;
-    }
+pub extern "C" fn toml_edit_value_get_i64(value: Handle, integer: *mut i64) -> i32 {
+    call("toml_edit_value_get_i64", || {
+        let integer = out(integer, "Integer output")?;
+
+        *integer = handle::with(value, |value: &mut Value| {
+            value
+                .as_integer()
+                .ok_or_else(|| Error::wrong_type("Value", "Integer"))
+        })?;
+        Ok(())
+    })
+}
 
-    let value = unsafe { &mut *(value as *mut Value) };
+// get a f64 typed Value from a value (including `inf`, `-inf` and `nan`)
+// if `integer_as_float` is true, an Integer value is also accepted and converted to f64
+// (for files that mix `1` and `1.0`), unless it is too big for a f64 to hold exactly
+#[no_mangle]
+pub extern "C" fn toml_edit_value_get_f64(
+    value: Handle,
+    integer_as_float: LVBool,
+    float: *mut f64,
+) -> i32 {
+    call("toml_edit_value_get_f64", || {
+        let float = out(float, "Float output")?;
+
+        *float = handle::with(value, |value: &mut Value| match value {
+            Value::Float(float) => Ok(*float.value()),
+            Value::Integer(integer) if integer_as_float.into() => {
+                integer_to_f64(*integer.value(), "Value")
+            }
+            _ => Err(Error::wrong_type("Value", "Float")),
+        })?;
+        Ok(())
+    })
+}
 
-    let value = match value {
-        Value::Integer(value) => value,
-        _ => {
-            println!("Value is not a Integer");
-            return 0;
-        }
-    };
+// an Integer converted to f64, failing if the f64 would round it
+// (a f64 holds every integer up to 2^53 exactly, but only some beyond that)
+fn integer_to_f64(integer: i64, location: &str) -> Result<f64> {
+    let float = integer as f64;
+
+    // compared as i128, as `i64::MAX as f64` rounds up to 2^63, which an i64 can't hold
+    match float as i128 == integer as i128 {
+        true => Ok(float),
+        false => Err(Error::new(
+            ErrorCode::InvalidValue,
+            format!(
+                "{} ({}) is too big to convert to a float exactly",
+                location, integer
+            ),
+        )),
+    }
+}
 
-    let return_value = value.clone().into_value();
+// get the parts of a Datetime typed Value
+// `kind` is 0 = offset datetime, 1 = local datetime, 2 = local date, 3 = local time (see `datetime::DatetimeKind`)
+// parts that the kind of datetime doesn't have are 0, and an offset of `Z` is 0 minutes
+#[no_mangle]
+pub extern "C" fn toml_edit_value_get_datetime(
+    value: Handle,
+    kind: *mut i32,
+    year: *mut i32,
+    month: *mut i32,
+    day: *mut i32,
+    hour: *mut i32,
+    minute: *mut i32,
+    second: *mut i32,
+    nanosecond: *mut i32,
+    offset_minutes: *mut i32,
+) -> i32 {
+    call("toml_edit_value_get_datetime", || {
+        let kind = out(kind, "Kind output")?;
+        let year = out(year, "Year output")?;
+        let month = out(month, "Month output")?;
+        let day = out(day, "Day output")?;
+        let hour = out(hour, "Hour output")?;
+        let minute = out(minute, "Minute output")?;
+        let second = out(second, "Second output")?;
+        let nanosecond = out(nanosecond, "Nanosecond output")?;
+        let offset_minutes = out(offset_minutes, "Offset output")?;
+
+        let found = handle::with(value, |value: &mut Value| {
+            value
+                .as_datetime()
+                .copied()
+                .ok_or_else(|| Error::wrong_type("Value", "Datetime"))
+        })?;
+
+        let parts = DatetimeParts::from_datetime(&found);
+        *kind = DatetimeKind::of(&found) as i32;
+        *year = parts.year;
+        *month = parts.month;
+        *day = parts.day;
+        *hour = parts.hour;
+        *minute = parts.minute;
+        *second = parts.second;
+        *nanosecond = parts.nanosecond;
+        *offset_minutes = parts.offset_minutes;
+        Ok(())
+    })
+}
 
-    return return_value;
+// get a Datetime typed Value as a LabVIEW timestamp (see `datetime` for how each kind is converted)
+// a local datetime or local date is taken to be at `local_offset_minutes` from UTC
+#[no_mangle]
+pub extern "C" fn toml_edit_value_get_timestamp(
+    value: Handle,
+    local_offset_minutes: i32,
+    timestamp: *mut LVTime,
+) -> i32 {
+    call("toml_edit_value_get_timestamp", || {
+        let found = handle::with(value, |value: &mut Value| {
+            value
+                .as_datetime()
+                .copied()
+                .ok_or_else(|| Error::wrong_type("Value", "Datetime"))
+        })?;
+
+        write_time(
+            timestamp,
+            datetime::to_timestamp(&found, local_offset_minutes),
+            "Timestamp output",
+        )
+    })
 }
 
 // get a boolean typed Value from a value
 // takes a value as input
-// returns a i8 with 1 representing true, 0 representing false, and -1 representing an error
-#[allow(dead_code)]
 #[no_mangle]
-pub extern "C" fn toml_edit_value_get_bool(value: *mut c_void) -> i8 {
-    if value.is_null() {
-        println!("Value is null");
-        return -1;
-    }
-
-    let value = unsafe { &mut *(value as *mut Value) };
-
-    let value = match value {
-        Value::Boolean(value) => value,
-        _ => {
-            println!("Value is not a Boolean");
-            return -1;
-        }
-    };
-
-    let return_value = value.clone().into_value();
-
-    return return_value as i8;
+pub extern "C" fn toml_edit_value_get_bool(value: Handle, boolean: *mut LVBool) -> i32 {
+    call("toml_edit_value_get_bool", || {
+        let boolean = out(boolean, "Boolean output")?;
+
+        *boolean = handle::with(value, |value: &mut Value| {
+            value
+                .as_bool()
+                .ok_or_else(|| Error::wrong_type("Value", "Boolean"))
+        })?
+        .into();
+        Ok(())
+    })
 }
 
 // get an InlineTable typed Value from a value
-// takes a value as input and returns a raw pointer to a Table
-#[allow(dead_code)]
+// takes a value as input and returns a reference to an InlineTable (a live view of the Value)
 #[no_mangle]
-pub extern "C" fn toml_edit_value_get_inline_table(value: *mut c_void) -> *mut c_void {
-    if value.is_null() {
-        println!("Value is null");
-        return ptr::null_mut();
-    }
-
-    let value = unsafe { &mut *(value as *mut Value) };
-
-    let value = match value {
-        Value::InlineTable(value) => value,
-        _ => {
-            println!("Value is not a InlineTable");
-            return ptr::null_mut();
-        }
-    };
-
-    let value = Box::new(value.clone());
-
-    Box::into_raw(value) as *mut c_void
+pub extern "C" fn toml_edit_value_get_inline_table(
+    value: Handle,
+    inline_table: *mut Handle,
+) -> i32 {
+    call("toml_edit_value_get_inline_table", || {
+        let inline_table = out(inline_table, "InlineTable output")?;
+
+        *inline_table = handle::view::<Value, InlineTable>(value, &[])?;
+        Ok(())
+    })
 }
 
 // create a new Value::String from a string
 // takes a LStrHandle as input.
-#[allow(dead_code)]
 #[no_mangle]
-pub extern "C" fn toml_edit_item_new_value_from_string(string: LStrHandle) -> *mut c_void {
-    let string = string.to_rust_string();
-    let item = toml_edit::value(string.as_ref());
-
-    let item = Box::new(item);
-
-    Box::into_raw(item) as *mut c_void
+pub extern "C" fn toml_edit_item_new_value_from_string(
+    string: LStrHandle,
+    item: *mut Handle,
+) -> i32 {
+    call("toml_edit_item_new_value_from_string", || {
+        let item = out(item, "Item output")?;
+        let string = read_str(string)?;
+
+        *item = handle::insert(toml_edit::value(string))?;
+        Ok(())
+    })
 }
 
 // create a new Value::Integer from a i64
 // takes a i64 as input
-#[allow(dead_code)]
 #[no_mangle]
-pub extern "C" fn toml_edit_item_new_value_from_i64(integer: i64) -> *mut c_void {
-    let item = toml_edit::value(integer);
+pub extern "C" fn toml_edit_item_new_value_from_i64(integer: i64, item: *mut Handle) -> i32 {
+    call("toml_edit_item_new_value_from_i64", || {
+        let item = out(item, "Item output")?;
+
+        *item = handle::insert(toml_edit::value(integer))?;
+        Ok(())
+    })
+}
 
-    let item = Box::new(item);
+// create a new Value::Float from a f64
+// infinities and NaN are written as `inf`, `-inf` and `nan`
+#[no_mangle]
+pub extern "C" fn toml_edit_item_new_value_from_f64(float: f64, item: *mut Handle) -> i32 {
+    call("toml_edit_item_new_value_from_f64", || {
+        let item = out(item, "Item output")?;
 
-    Box::into_raw(item) as *mut c_void
+        *item = handle::insert(toml_edit::value(float))?;
+        Ok(())
+    })
 }
 
-// create a new Value::Boolean from a i8
-// takes a i8 as input
-#[allow(dead_code)]
+// create a new Value::Datetime from its parts
+// `kind` is 0 = offset datetime, 1 = local datetime, 2 = local date, 3 = local time (see `datetime::DatetimeKind`)
+// parts that the kind of datetime doesn't have are ignored, and an offset of 0 minutes is written as `Z`
 #[no_mangle]
-pub extern "C" fn toml_edit_item_new_value_from_bool(boolean: i8) -> *mut c_void {
-    let boolean = match boolean {
-        1 => true,
-        0 => false,
-        _ => {
-            println!("Invalid boolean value");
-            return ptr::null_mut();
-        }
-    };
+pub extern "C" fn toml_edit_item_new_value_from_datetime(
+    kind: i32,
+    year: i32,
+    month: i32,
+    day: i32,
+    hour: i32,
+    minute: i32,
+    second: i32,
+    nanosecond: i32,
+    offset_minutes: i32,
+    item: *mut Handle,
+) -> i32 {
+    call("toml_edit_item_new_value_from_datetime", || {
+        let item = out(item, "Item output")?;
+
+        let parts = DatetimeParts {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            nanosecond,
+            offset_minutes,
+        };
+        let found = parts.to_datetime(DatetimeKind::try_from(kind)?)?;
+
+        *item = handle::insert(toml_edit::value(found))?;
+        Ok(())
+    })
+}
 
-    let item = toml_edit::value(boolean);
+// create a new Value::Datetime from a LabVIEW timestamp
+// an offset datetime is written at `offset_minutes` from UTC, and a local datetime, local date or
+// local time is the wall-clock time at that offset (see `datetime` for how each kind is converted)
+#[no_mangle]
+pub extern "C" fn toml_edit_item_new_value_from_timestamp(
+    timestamp: *const LVTime,
+    kind: i32,
+    offset_minutes: i32,
+    item: *mut Handle,
+) -> i32 {
+    call("toml_edit_item_new_value_from_timestamp", || {
+        let item = out(item, "Item output")?;
+        let timestamp = read_time(timestamp, "Timestamp")?;
+
+        let found =
+            datetime::from_timestamp(timestamp, DatetimeKind::try_from(kind)?, offset_minutes)?;
+
+        *item = handle::insert(toml_edit::value(found))?;
+        Ok(())
+    })
+}
 
-    let item = Box::new(item);
+// create a new Value::Boolean from a LabVIEW boolean
+#[no_mangle]
+pub extern "C" fn toml_edit_item_new_value_from_bool(boolean: LVBool, item: *mut Handle) -> i32 {
+    call("toml_edit_item_new_value_from_bool", || {
+        let item = out(item, "Item output")?;
 
-    return Box::into_raw(item) as *mut c_void;
+        *item = handle::insert(toml_edit::value(bool::from(boolean)))?;
+        Ok(())
+    })
 }
 
 // create a new, empty Value::InlineTable
-#[allow(dead_code)]
 #[no_mangle]
-pub extern "C" fn toml_edit_item_new_value_inline_table() -> *mut c_void {
-    let item = toml_edit::value(InlineTable::default());
+pub extern "C" fn toml_edit_item_new_value_inline_table(item: *mut Handle) -> i32 {
+    call("toml_edit_item_new_value_inline_table", || {
+        let item = out(item, "Item output")?;
+
+        *item = handle::insert(toml_edit::value(InlineTable::default()))?;
+        Ok(())
+    })
+}
 
-    let item = Box::new(item);
+// create a new, empty Value::Array
+#[no_mangle]
+pub extern "C" fn toml_edit_item_new_value_array(item: *mut Handle) -> i32 {
+    call("toml_edit_item_new_value_array", || {
+        let item = out(item, "Item output")?;
 
-    Box::into_raw(item) as *mut c_void
+        *item = handle::insert(toml_edit::value(Array::default()))?;
+        Ok(())
+    })
 }
 
 // create a new, empty Table
-#[allow(dead_code)]
 #[no_mangle]
-pub extern "C" fn toml_edit_table_new() -> *mut c_void {
-    let table = Table::default();
+pub extern "C" fn toml_edit_table_new(table: *mut Handle) -> i32 {
+    call("toml_edit_table_new", || {
+        let table = out(table, "Table output")?;
 
-    let table = Box::new(table);
+        *table = handle::insert(Table::default())?;
+        Ok(())
+    })
+}
 
-    Box::into_raw(table) as *mut c_void
+// create a new Table from a LabVIEW cluster flattened with Flatten To String (big-endian, with
+// sizes prepended) and its type string from Variant To Flattened String
+// element labels become keys, nested clusters become tables, arrays become arrays, timestamps
+// become UTC datetimes and enums become the names of their items
+#[no_mangle]
+pub extern "C" fn toml_edit_table_from_flattened_data(
+    data: LStrHandle,
+    type_string: LVArrayHandle<1, i16>,
+    table: *mut Handle,
+) -> i32 {
+    call("toml_edit_table_from_flattened_data", || {
+        let table = out(table, "Table output")?;
+        let data = read_bytes(data)?;
+
+        *table = handle::insert(flattened::to_table(&data, &read_array(&type_string))?)?;
+        Ok(())
+    })
 }
 
 // check if an item exists in a table
-// takes a *const c_char as input
-#[allow(dead_code)]
 #[no_mangle]
-pub extern "C" fn toml_edit_table_contains_item(table: *mut c_void, key: LStrHandle) -> i64 {
-    if table.is_null() {
-        println!("Table is null");
-        return -1;
-    }
-    let table = unsafe { &mut *(table as *mut Table) };
-    let key = key.to_rust_string();
-
-    return if table.contains_key(&key) { 1 } else { 0 };
+pub extern "C" fn toml_edit_table_contains_item(
+    table: Handle,
+    key: LStrHandle,
+    contains: *mut LVBool,
+) -> i32 {
+    call("toml_edit_table_contains_item", || {
+        let contains = out(contains, "Contains output")?;
+        let key = read_str(key)?;
+
+        *contains = handle::with(table, |table: &mut Table| Ok(table.contains_key(&key)))?.into();
+        Ok(())
+    })
 }
 
 // create a new, empty InlineTable
-#[allow(dead_code)]
 #[no_mangle]
-pub extern "C" fn toml_edit_inline_table_new() -> *mut c_void {
-    let t = InlineTable::default();
+pub extern "C" fn toml_edit_inline_table_new(inline_table: *mut Handle) -> i32 {
+    call("toml_edit_inline_table_new", || {
+        let inline_table = out(inline_table, "InlineTable output")?;
 
-    let t = Box::new(t);
-
-    return Box::into_raw(t) as *mut c_void;
+        *inline_table = handle::insert(InlineTable::default())?;
+        Ok(())
+    })
 }
 
 // remove an item from a InlineTable
-// takes a InlineTable and a item name as inputs
-#[allow(dead_code)]
+// takes a InlineTable and a item name as inputs, and returns whether the item existed
 #[no_mangle]
 pub extern "C" fn toml_edit_inline_table_remove_item(
-    inline_table: *mut c_void,
+    inline_table: Handle,
     item_name: LStrHandle,
-) -> u64 {
-    if inline_table.is_null() {
-        println!("InlineTable is null");
-        return 0;
-    }
-    let inline_table = unsafe { &mut *(inline_table as *mut InlineTable) };
-    let item_name = item_name.to_rust_string();
+    removed: *mut LVBool,
+) -> i32 {
+    call("toml_edit_inline_table_remove_item", || {
+        let removed = out(removed, "Removed output")?;
+        let item_name = read_str(item_name)?;
+
+        *removed = handle::with(inline_table, |inline_table: &mut InlineTable| {
+            Ok(inline_table.remove(&item_name).is_some())
+        })?
+        .into();
+        Ok(())
+    })
+}
 
-    return if inline_table.contains_key(&item_name) {
-        inline_table.remove(&item_name);
-        1
-    } else {
-        0
-    };
+// rename a key in an InlineTable, keeping its place and its value
+// fails if the InlineTable already has the new key
+#[no_mangle]
+pub extern "C" fn toml_edit_inline_table_rename_key(
+    inline_table: Handle,
+    old_key: LStrHandle,
+    new_key: LStrHandle,
+) -> i32 {
+    call("toml_edit_inline_table_rename_key", || {
+        let old_key = read_str(old_key)?;
+        let new_key = read_str(new_key)?;
+
+        handle::with(inline_table, |inline_table: &mut InlineTable| {
+            keys::rename(inline_table, &old_key, &new_key)
+        })
+    })
 }
 
 // check if an item exists in an inline table
-#[allow(dead_code)]
 #[no_mangle]
-pub extern "C" fn toml_edit_inline_table_contains_item(table: *mut c_void, key: LStrHandle) -> i64 {
-    if table.is_null() {
-        println!("InlineTable is null");
-        return -1;
-    }
-
-    let table = unsafe { &mut *(table as *mut InlineTable) };
-    let key = key.to_rust_string();
-
-    return if table.contains_key(&key) { 1 } else { 0 };
+pub extern "C" fn toml_edit_inline_table_contains_item(
+    inline_table: Handle,
+    key: LStrHandle,
+    contains: *mut LVBool,
+) -> i32 {
+    call("toml_edit_inline_table_contains_item", || {
+        let contains = out(contains, "Contains output")?;
+        let key = read_str(key)?;
+
+        *contains = handle::with(inline_table, |inline_table: &mut InlineTable| {
+            Ok(inline_table.contains_key(&key))
+        })?
+        .into();
+        Ok(())
+    })
 }
 
-// Return a multi-line string of the keynames in an InlineTable
-// takes a InlineTable as input
-#[allow(dead_code)]
+// list the keys in an InlineTable as a LabVIEW array of strings
 #[no_mangle]
 pub extern "C" fn toml_edit_inline_table_list_items(
-    inline_table: *mut c_void,
-    mut item_list: LStrHandle,
-) {
-    if inline_table.is_null() {
-        println!("InlineTable is null");
-        return;
-    }
-
-    let inline_table = unsafe { &mut *(inline_table as *mut InlineTable) };
-
-    let mut return_string = String::new();
+    inline_table: Handle,
+    keys: *mut LVArrayHandle<1, LStrHandle>,
+) -> i32 {
+    call("toml_edit_inline_table_list_items", || {
+        let keys = out(keys, "Keys output")?;
+        let (key_list, _) = handle::with(inline_table, |inline_table: &mut InlineTable| {
+            Ok(inline_table_entries(inline_table))
+        })?;
+
+        write_str_array(keys, &key_list)
+    })
+}
 
-    for (key, _) in inline_table.iter() {
-        return_string.push_str(key);
-        return_string.push_str("\n");
-    }
+// list the keys in an InlineTable, and the type of each value, as two LabVIEW arrays of strings
+#[no_mangle]
+pub extern "C" fn toml_edit_inline_table_list_items_with_types(
+    inline_table: Handle,
+    keys: *mut LVArrayHandle<1, LStrHandle>,
+    types: *mut LVArrayHandle<1, LStrHandle>,
+) -> i32 {
+    call("toml_edit_inline_table_list_items_with_types", || {
+        let keys = out(keys, "Keys output")?;
+        let types = out(types, "Types output")?;
+        let (key_list, type_list) =
+            handle::with(inline_table, |inline_table: &mut InlineTable| {
+                Ok(inline_table_entries(inline_table))
+            })?;
+
+        write_str_array(keys, &key_list)?;
+        write_str_array(types, &type_list)
+    })
+}
 
-    let _ = item_list.set_str(&return_string);
+// the keys of an InlineTable, and the type names of their values
+fn inline_table_entries(inline_table: &InlineTable) -> (Vec<String>, Vec<&'static str>) {
+    inline_table
+        .iter()
+        .map(|(key, value)| (key.to_string(), value_type_name(value)))
+        .unzip()
 }
 
 // Get an value from a InlineTable
 // takes a InlineTable as input and a LStrHandle as the keyname
-#[allow(dead_code)]
+// the Value is a live view, so changes made through it edit the InlineTable
 #[no_mangle]
 pub extern "C" fn toml_edit_inline_table_get_item(
-    inline_table: *mut c_void,
+    inline_table: Handle,
     key: LStrHandle,
-) -> *mut c_void {
-    if inline_table.is_null() {
-        println!("InlineTable is null");
-        // review: a null pointer would make more sense?
-        // return an empty string
-        return CString::new("").unwrap().into_raw() as *mut c_void;
-    }
-    let inline_table = unsafe { &mut *(inline_table as *mut InlineTable) };
-
-    let key = key.to_rust_string();
-
-    let (_, item) = match inline_table.get_key_value(&key) {
-        Some(key_value_pair) => key_value_pair,
-        None => {
-            println!("Key not found");
-            return ptr::null_mut();
-        }
-    };
-
-    let item = Box::new(item.clone());
-
-    Box::into_raw(item) as *mut c_void
+    value: *mut Handle,
+) -> i32 {
+    call("toml_edit_inline_table_get_item", || {
+        let value = out(value, "Value output")?;
+        let key = read_str(key)?;
+
+        *value = handle::view::<InlineTable, Value>(inline_table, &[Step::Key(key)])?;
+        Ok(())
+    })
 }
 
-// Set an value in an InlineTable
+// get the comments and whitespace around a key in an InlineTable
+// (see `toml_edit_table_get_key_decor`)
+#[no_mangle]
+pub extern "C" fn toml_edit_inline_table_get_key_decor(
+    inline_table: Handle,
+    key: LStrHandle,
+    prefix: LStrHandle,
+    suffix: LStrHandle,
+) -> i32 {
+    call("toml_edit_inline_table_get_key_decor", || {
+        let key = read_str(key)?;
+        let (prefix_text, suffix_text) =
+            handle::with(inline_table, |inline_table: &mut InlineTable| {
+                let decor = inline_table
+                    .key_decor(&key)
+                    .ok_or_else(|| Error::not_found("Key", &key))?;
+                Ok(decor::get(decor))
+            })?;
+
+        write_str(prefix, &prefix_text)?;
+        write_str(suffix, &suffix_text)
+    })
+}
+
+// set the whitespace around a key in an InlineTable
+// (an inline table must stay on one line, so only spaces and tabs are allowed)
+#[no_mangle]
+pub extern "C" fn toml_edit_inline_table_set_key_decor(
+    inline_table: Handle,
+    key: LStrHandle,
+    prefix: LStrHandle,
+    suffix: LStrHandle,
+) -> i32 {
+    call("toml_edit_inline_table_set_key_decor", || {
+        let key = read_str(key)?;
+        let prefix = read_str(prefix)?;
+        let suffix = read_str(suffix)?;
+
+        handle::with(inline_table, |inline_table: &mut InlineTable| {
+            let decor = inline_table
+                .key_decor_mut(&key)
+                .ok_or_else(|| Error::not_found("Key", &key))?;
+            decor::set(decor, Place::Inline, &prefix, &suffix)
+        })
+    })
+}
+
+// Set an value in an InlineTable
 // takes a InlineTable as input and a LStr as the keyname
-#[allow(dead_code)]
 #[no_mangle]
 pub extern "C" fn toml_edit_inline_table_set_item(
-    inline_table: *mut c_void,
+    inline_table: Handle,
     key: LStrHandle,
-    item: *mut c_void,
-) {
-    if inline_table.is_null() {
-        println!("InlineTable is null");
-        return;
-    }
-    if item.is_null() {
-        println!("Item is null");
-        return;
-    }
-    let inline_table = unsafe { &mut *(inline_table as *mut InlineTable) };
+    item: Handle,
+) -> i32 {
+    call("toml_edit_inline_table_set_item", || {
+        let mut value = item_value(item)?;
+        let key = read_str(key)?;
+        decor::fit(value.decor_mut(), Place::Inline);
+
+        handle::with(inline_table, |inline_table: &mut InlineTable| {
+            inline_table.insert(key, value);
+            Ok(())
+        })
+    })
+}
 
-    let key = key.to_rust_string();
+// sort the keys of an InlineTable alphabetically, and optionally those of every inline table
+// inside it
+#[no_mangle]
+pub extern "C" fn toml_edit_inline_table_sort_items(
+    inline_table: Handle,
+    recursive: LVBool,
+) -> i32 {
+    call("toml_edit_inline_table_sort_items", || {
+        handle::with(inline_table, |inline_table: &mut InlineTable| {
+            keys::sort(inline_table, &Order::Alphabetical, recursive.into());
+            Ok(())
+        })
+    })
+}
 
-    let item = unsafe { &mut *(item as *mut Item) };
+// sort the keys of an InlineTable into the order given by a LabVIEW array of keys, and
+// optionally those of every inline table inside it (see `toml_edit_table_sort_items_by_order`)
+#[no_mangle]
+pub extern "C" fn toml_edit_inline_table_sort_items_by_order(
+    inline_table: Handle,
+    order: LVArrayHandle<1, LStrHandle>,
+    recursive: LVBool,
+) -> i32 {
+    call("toml_edit_inline_table_sort_items_by_order", || {
+        let order = read_str_array(&order)?;
+
+        handle::with(inline_table, |inline_table: &mut InlineTable| {
+            keys::sort(inline_table, &Order::Listed(&order), recursive.into());
+            Ok(())
+        })
+    })
+}
 
-    // verify that the item is a Item::Value
-    match item {
-        Item::Value(_) => {}
-        _ => {
-            println!("Item is not a Item::Value");
-            return;
-        }
-    }
+// return a new Table with the contents of an InlineTable, optionally turning every inline table
+// inside it into a table too
+#[no_mangle]
+pub extern "C" fn toml_edit_inline_table_to_table(
+    inline_table: Handle,
+    recursive: LVBool,
+    table: *mut Handle,
+) -> i32 {
+    call("toml_edit_inline_table_to_table", || {
+        let table = out(table, "Table output")?;
+        let found = handle::with(inline_table, |inline_table: &mut InlineTable| {
+            Ok(inline_table.clone())
+        })?;
+
+        *table = handle::insert(style::to_table(found, recursive.into()))?;
+        Ok(())
+    })
+}
 
-    // convert it to a value
-    let value = match item.as_value() {
-        Some(value) => value,
-        None => {
-            println!("Unable to convert item to Value");
-            return;
-        }
-    };
+// get the zero-based place of a key among the keys of an InlineTable
+#[no_mangle]
+pub extern "C" fn toml_edit_inline_table_get_item_index(
+    inline_table: Handle,
+    key: LStrHandle,
+    index: *mut i32,
+) -> i32 {
+    call("toml_edit_inline_table_get_item_index", || {
+        let index = out(index, "Index output")?;
+        let key = read_str(key)?;
+
+        let found = handle::with(inline_table, |inline_table: &mut InlineTable| {
+            keys::index(inline_table, &key)
+        })?;
+        *index = found as i32;
+        Ok(())
+    })
+}
+
+// add a value to an InlineTable under a new key, at a zero-based place among its keys
+// (an index equal to the number of keys adds it at the end)
+// fails if the InlineTable already has the key
+#[no_mangle]
+pub extern "C" fn toml_edit_inline_table_insert_item(
+    inline_table: Handle,
+    index: i32,
+    key: LStrHandle,
+    item: Handle,
+) -> i32 {
+    call("toml_edit_inline_table_insert_item", || {
+        let value = item_value(item)?;
+        let key = read_str(key)?;
+
+        handle::with(inline_table, |inline_table: &mut InlineTable| {
+            // inserting at the length is allowed, so check against one past the end
+            let index = array_index(index, inline_table.len() + 1)
+                .map_err(|_| Error::out_of_range(index, inline_table.len()))?;
+            keys::insert(inline_table, index, &key, value)
+        })
+    })
+}
+
+// add a value to an InlineTable under a new key, just before an existing key
+// fails if the InlineTable already has the new key
+#[no_mangle]
+pub extern "C" fn toml_edit_inline_table_insert_item_before(
+    inline_table: Handle,
+    before_key: LStrHandle,
+    key: LStrHandle,
+    item: Handle,
+) -> i32 {
+    call("toml_edit_inline_table_insert_item_before", || {
+        let value = item_value(item)?;
+        let before_key = read_str(before_key)?;
+        let key = read_str(key)?;
+
+        handle::with(inline_table, |inline_table: &mut InlineTable| {
+            let index = keys::index(inline_table, &before_key)?;
+            keys::insert(inline_table, index, &key, value)
+        })
+    })
+}
+
+// add a value to an InlineTable under a new key, just after an existing key
+// fails if the InlineTable already has the new key
+#[no_mangle]
+pub extern "C" fn toml_edit_inline_table_insert_item_after(
+    inline_table: Handle,
+    after_key: LStrHandle,
+    key: LStrHandle,
+    item: Handle,
+) -> i32 {
+    call("toml_edit_inline_table_insert_item_after", || {
+        let value = item_value(item)?;
+        let after_key = read_str(after_key)?;
+        let key = read_str(key)?;
+
+        handle::with(inline_table, |inline_table: &mut InlineTable| {
+            let index = keys::index(inline_table, &after_key)? + 1;
+            keys::insert(inline_table, index, &key, value)
+        })
+    })
+}
+
+// create a new, empty Array
+#[no_mangle]
+pub extern "C" fn toml_edit_array_new(array: *mut Handle) -> i32 {
+    call("toml_edit_array_new", || {
+        let array = out(array, "Array output")?;
+
+        *array = handle::insert(Array::default())?;
+        Ok(())
+    })
+}
+
+// get the number of values in an Array
+#[no_mangle]
+pub extern "C" fn toml_edit_array_len(array: Handle, len: *mut i32) -> i32 {
+    call("toml_edit_array_len", || {
+        let len = out(len, "Length output")?;
+
+        *len = handle::with(array, |array: &mut Array| Ok(array.len() as i32))?;
+        Ok(())
+    })
+}
+
+// Get a value from an Array by its (zero-based) index
+// the Value is a live view, so changes made through it edit the Array
+#[no_mangle]
+pub extern "C" fn toml_edit_array_get(array: Handle, index: i32, value: *mut Handle) -> i32 {
+    call("toml_edit_array_get", || {
+        let value = out(value, "Value output")?;
+
+        let len = handle::with(array, |array: &mut Array| Ok(array.len()))?;
+        let index = array_index(index, len)?;
+
+        *value = handle::view::<Array, Value>(array, &[Step::Index(index)])?;
+        Ok(())
+    })
+}
+
+// Replace the value at an index in an Array, keeping the formatting around it
+#[no_mangle]
+pub extern "C" fn toml_edit_array_set(array: Handle, index: i32, item: Handle) -> i32 {
+    call("toml_edit_array_set", || {
+        let value = item_value(item)?;
+
+        handle::with(array, |array: &mut Array| {
+            let index = array_index(index, array.len())?;
+            array.replace(index, value);
+            Ok(())
+        })
+    })
+}
+
+// Add a value to the end of an Array
+#[no_mangle]
+pub extern "C" fn toml_edit_array_push(array: Handle, item: Handle) -> i32 {
+    call("toml_edit_array_push", || {
+        let value = item_value(item)?;
+
+        handle::with(array, |array: &mut Array| {
+            array.push(value);
+            Ok(())
+        })
+    })
+}
+
+// Insert a value into an Array before an index (an index equal to the length adds it to the end)
+#[no_mangle]
+pub extern "C" fn toml_edit_array_insert(array: Handle, index: i32, item: Handle) -> i32 {
+    call("toml_edit_array_insert", || {
+        let value = item_value(item)?;
+
+        handle::with_shifting(array, &[], |array: &mut Array| {
+            // inserting at the length is allowed, so check against one past the end
+            let index = array_index(index, array.len() + 1)
+                .map_err(|_| Error::out_of_range(index, array.len()))?;
+            array::insert(array, index, value);
+            Ok(())
+        })
+    })
+}
+
+// remove a value from an Array
+// takes an Array and an index as inputs, and returns whether the value existed
+#[no_mangle]
+pub extern "C" fn toml_edit_array_remove(array: Handle, index: i32, removed: *mut LVBool) -> i32 {
+    call("toml_edit_array_remove", || {
+        let removed = out(removed, "Removed output")?;
+
+        *removed = handle::with_shifting(array, &[], |array: &mut Array| {
+            let exists = array_index(index, array.len()).is_ok();
+            if exists {
+                array::remove(array, index as usize);
+            }
+            Ok(exists)
+        })?
+        .into();
+        Ok(())
+    })
+}
+
+// read an Array of Integers into a LabVIEW array of I64 in one call
+// fails (leaving the LabVIEW array unchanged) if any element is not an Integer
+#[no_mangle]
+pub extern "C" fn toml_edit_array_get_i64s(
+    array: Handle,
+    integers: *mut LVArrayHandle<1, i64>,
+) -> i32 {
+    call("toml_edit_array_get_i64s", || {
+        let integers = out(integers, "Integers output")?;
+
+        let values = handle::with(array, |array: &mut Array| {
+            array::values(array, "Integer", Value::as_integer)
+        })?;
+        write_array(integers, &values)
+    })
+}
+
+// read an Array of Floats into a LabVIEW array of DBL in one call
+// if `integer_as_float` is true, Integer elements are also accepted and converted to f64, unless
+// one is too big for a f64 to hold exactly
+// fails (leaving the LabVIEW array unchanged) if any element is not a Float
+#[no_mangle]
+pub extern "C" fn toml_edit_array_get_f64s(
+    array: Handle,
+    integer_as_float: LVBool,
+    floats: *mut LVArrayHandle<1, f64>,
+) -> i32 {
+    call("toml_edit_array_get_f64s", || {
+        let floats = out(floats, "Floats output")?;
+
+        let values = handle::with(array, |array: &mut Array| {
+            // an Integer is converted afterwards, so a failure can say which element it is
+            let values = array::values(array, "Float", |value| match value {
+                Value::Float(float) => Some(Ok(*float.value())),
+                Value::Integer(integer) if integer_as_float.into() => Some(Err(*integer.value())),
+                _ => None,
+            })?;
+            values
+                .into_iter()
+                .enumerate()
+                .map(|(index, value)| match value {
+                    Ok(float) => Ok(float),
+                    Err(integer) => integer_to_f64(integer, &format!("Array element {}", index)),
+                })
+                .collect::<Result<Vec<f64>>>()
+        })?;
+        write_array(floats, &values)
+    })
+}
+
+// read an Array of Booleans into a LabVIEW array of Booleans in one call
+// fails (leaving the LabVIEW array unchanged) if any element is not a Boolean
+#[no_mangle]
+pub extern "C" fn toml_edit_array_get_bools(
+    array: Handle,
+    booleans: *mut LVArrayHandle<1, LVBool>,
+) -> i32 {
+    call("toml_edit_array_get_bools", || {
+        let booleans = out(booleans, "Booleans output")?;
+
+        let values = handle::with(array, |array: &mut Array| {
+            array::values(array, "Boolean", |value| value.as_bool().map(LVBool::from))
+        })?;
+        write_array(booleans, &values)
+    })
+}
+
+// read an Array of Strings into a LabVIEW array of strings in one call
+// fails (leaving the LabVIEW array unchanged) if any element is not a String
+#[no_mangle]
+pub extern "C" fn toml_edit_array_get_strings(
+    array: Handle,
+    strings: *mut LVArrayHandle<1, LStrHandle>,
+) -> i32 {
+    call("toml_edit_array_get_strings", || {
+        let strings = out(strings, "Strings output")?;
+
+        let values = handle::with(array, |array: &mut Array| {
+            array::values(array, "String", |value| value.as_str().map(str::to_string))
+        })?;
+        write_str_array(strings, &values)
+    })
+}
+
+// replace the contents of an Array with a LabVIEW array of I64 in one call
+// (the Array's own formatting, e.g. a trailing comma or comment before the `]`, is kept)
+#[no_mangle]
+pub extern "C" fn toml_edit_array_set_i64s(array: Handle, integers: LVArrayHandle<1, i64>) -> i32 {
+    call("toml_edit_array_set_i64s", || {
+        set_array_values(array, read_array(&integers))
+    })
+}
+
+// replace the contents of an Array with a LabVIEW array of DBL in one call
+#[no_mangle]
+pub extern "C" fn toml_edit_array_set_f64s(array: Handle, floats: LVArrayHandle<1, f64>) -> i32 {
+    call("toml_edit_array_set_f64s", || {
+        set_array_values(array, read_array(&floats))
+    })
+}
 
-    // insert the value into the inline table
-    // They key becomes owned internally so convert to owned first for type compatability.
-    inline_table.insert(key.into_owned(), value.clone());
+// replace the contents of an Array with a LabVIEW array of Booleans in one call
+#[no_mangle]
+pub extern "C" fn toml_edit_array_set_bools(
+    array: Handle,
+    booleans: LVArrayHandle<1, LVBool>,
+) -> i32 {
+    call("toml_edit_array_set_bools", || {
+        set_array_values(array, read_array(&booleans).into_iter().map(bool::from))
+    })
+}
+
+// replace the contents of an Array with a LabVIEW array of strings in one call
+#[no_mangle]
+pub extern "C" fn toml_edit_array_set_strings(
+    array: Handle,
+    strings: LVArrayHandle<1, LStrHandle>,
+) -> i32 {
+    call("toml_edit_array_set_strings", || {
+        set_array_values(array, read_str_array(&strings)?)
+    })
+}
+
+fn set_array_values<V: Into<Value>>(
+    array: Handle,
+    values: impl IntoIterator<Item = V>,
+) -> Result<()> {
+    let values: Vec<Value> = values.into_iter().map(Into::into).collect();
+
+    handle::with_shifting(array, &[], |array: &mut Array| {
+        array.clear();
+        array.extend(values);
+        Ok(())
+    })
+}
+
+// get an ArrayOfTables (`[[name]]` sections) from a Item
+// takes a Item as input, and returns a live view of its ArrayOfTables
+#[no_mangle]
+pub extern "C" fn toml_edit_item_into_array_of_tables(
+    item: Handle,
+    array_of_tables: *mut Handle,
+) -> i32 {
+    call("toml_edit_item_into_array_of_tables", || {
+        let array_of_tables = out(array_of_tables, "ArrayOfTables output")?;
+
+        *array_of_tables = handle::view::<Item, ArrayOfTables>(item, &[])?;
+        Ok(())
+    })
+}
+
+// create a new, empty ArrayOfTables
+#[no_mangle]
+pub extern "C" fn toml_edit_array_of_tables_new(array_of_tables: *mut Handle) -> i32 {
+    call("toml_edit_array_of_tables_new", || {
+        let array_of_tables = out(array_of_tables, "ArrayOfTables output")?;
+
+        *array_of_tables = handle::insert(ArrayOfTables::new())?;
+        Ok(())
+    })
+}
+
+// convert an ArrayOfTables to an Item
+#[no_mangle]
+pub extern "C" fn toml_edit_array_of_tables_to_item(
+    array_of_tables: Handle,
+    item: *mut Handle,
+) -> i32 {
+    call("toml_edit_array_of_tables_to_item", || {
+        let item = out(item, "Item output")?;
+        let found = handle::with(array_of_tables, |array: &mut ArrayOfTables| {
+            Ok(array.clone())
+        })?;
+
+        *item = handle::insert(Item::ArrayOfTables(found))?;
+        Ok(())
+    })
+}
+
+// get the number of tables in an ArrayOfTables
+#[no_mangle]
+pub extern "C" fn toml_edit_array_of_tables_len(array_of_tables: Handle, len: *mut i32) -> i32 {
+    call("toml_edit_array_of_tables_len", || {
+        let len = out(len, "Length output")?;
+
+        *len = handle::with(array_of_tables, |array: &mut ArrayOfTables| {
+            Ok(array.len() as i32)
+        })?;
+        Ok(())
+    })
+}
+
+// Get a table from an ArrayOfTables by its (zero-based) index
+// the Table is a live view, so changes made through it edit the ArrayOfTables
+#[no_mangle]
+pub extern "C" fn toml_edit_array_of_tables_get(
+    array_of_tables: Handle,
+    index: i32,
+    table: *mut Handle,
+) -> i32 {
+    call("toml_edit_array_of_tables_get", || {
+        let table = out(table, "Table output")?;
+
+        let len = handle::with(array_of_tables, |array: &mut ArrayOfTables| Ok(array.len()))?;
+        let index = array_index(index, len)?;
+
+        *table = handle::view::<ArrayOfTables, Table>(array_of_tables, &[Step::Index(index)])?;
+        Ok(())
+    })
+}
+
+// Replace the table at an index in an ArrayOfTables (it is written in the same place in the document)
+#[no_mangle]
+pub extern "C" fn toml_edit_array_of_tables_set(
+    array_of_tables: Handle,
+    index: i32,
+    table: Handle,
+) -> i32 {
+    call("toml_edit_array_of_tables_set", || {
+        let table = handle::with(table, |table: &mut Table| Ok(table.clone()))?;
+
+        handle::with(array_of_tables, |array: &mut ArrayOfTables| {
+            let index = array_index(index, array.len())?;
+            array::replace_table(array, index, table);
+            Ok(())
+        })
+    })
+}
+
+// Add a table to the end of an ArrayOfTables
+#[no_mangle]
+pub extern "C" fn toml_edit_array_of_tables_push(array_of_tables: Handle, table: Handle) -> i32 {
+    call("toml_edit_array_of_tables_push", || {
+        let table = handle::with(table, |table: &mut Table| Ok(table.clone()))?;
+
+        handle::with(array_of_tables, |array: &mut ArrayOfTables| {
+            array::push_table(array, table);
+            Ok(())
+        })
+    })
+}
+
+// Insert a table into an ArrayOfTables before an index (an index equal to the length appends)
+#[no_mangle]
+pub extern "C" fn toml_edit_array_of_tables_insert(
+    array_of_tables: Handle,
+    index: i32,
+    table: Handle,
+) -> i32 {
+    call("toml_edit_array_of_tables_insert", || {
+        let table = handle::with(table, |table: &mut Table| Ok(table.clone()))?;
+
+        handle::with_shifting(array_of_tables, &[], |array: &mut ArrayOfTables| {
+            let index = array_index(index, array.len() + 1)
+                .map_err(|_| Error::out_of_range(index, array.len()))?;
+            array::insert_table(array, index, table);
+            Ok(())
+        })
+    })
+}
+
+// remove a table from an ArrayOfTables
+// takes an ArrayOfTables and an index as inputs, and returns whether the table existed
+#[no_mangle]
+pub extern "C" fn toml_edit_array_of_tables_remove(
+    array_of_tables: Handle,
+    index: i32,
+    removed: *mut LVBool,
+) -> i32 {
+    call("toml_edit_array_of_tables_remove", || {
+        let removed = out(removed, "Removed output")?;
+
+        *removed = handle::with_shifting(array_of_tables, &[], |array: &mut ArrayOfTables| {
+            let exists = array_index(index, array.len()).is_ok();
+            if exists {
+                array.remove(index as usize);
+            }
+            Ok(exists)
+        })?
+        .into();
+        Ok(())
+    })
+}
+
+// check an index from LabVIEW is in range for an Array of length `len`
+fn array_index(index: i32, len: usize) -> Result<usize> {
+    usize::try_from(index)
+        .ok()
+        .filter(|index| *index < len)
+        .ok_or_else(|| Error::out_of_range(index, len))
+}
+
+// copy the Value out of an Item, as only a Value can be stored in an Array or an InlineTable
+fn item_value(item: Handle) -> Result<Value> {
+    handle::with(item, |item: &mut Item| {
+        item.as_value()
+            .cloned()
+            .ok_or_else(|| Error::wrong_type("Item", "Value"))
+    })
 }
 
 // Close an Item and free the memory
-#[allow(dead_code)]
 #[no_mangle]
-pub extern "C" fn toml_edit_item_close(item: *mut c_void) {
-    if item.is_null() {
-        return;
-    }
-    let item = unsafe { Box::from_raw(item as *mut Item) };
-    drop(item);
+pub extern "C" fn toml_edit_item_close(item: Handle) -> i32 {
+    call("toml_edit_item_close", || {
+        handle::remove::<Item>(item)?;
+        Ok(())
+    })
 }
 
 // Close a Value and free the memory
-#[allow(dead_code)]
 #[no_mangle]
-pub extern "C" fn toml_edit_value_close(value: *mut c_void) {
-    if value.is_null() {
-        return;
-    }
-    let value = unsafe { Box::from_raw(value as *mut Value) };
-    drop(value);
+pub extern "C" fn toml_edit_value_close(value: Handle) -> i32 {
+    call("toml_edit_value_close", || {
+        handle::remove::<Value>(value)?;
+        Ok(())
+    })
 }
 
 // Close an InlineTable and free the memory
-#[allow(dead_code)]
 #[no_mangle]
-pub extern "C" fn toml_edit_inline_table_close(table: *mut c_void) {
-    if table.is_null() {
-        return;
-    }
-    let table = unsafe { Box::from_raw(table as *mut InlineTable) };
-    drop(table);
+pub extern "C" fn toml_edit_inline_table_close(inline_table: Handle) -> i32 {
+    call("toml_edit_inline_table_close", || {
+        handle::remove::<InlineTable>(inline_table)?;
+        Ok(())
+    })
+}
+
+// Close an Array and free the memory
+#[no_mangle]
+pub extern "C" fn toml_edit_array_close(array: Handle) -> i32 {
+    call("toml_edit_array_close", || {
+        handle::remove::<Array>(array)?;
+        Ok(())
+    })
+}
+
+// Close an ArrayOfTables and free the memory
+#[no_mangle]
+pub extern "C" fn toml_edit_array_of_tables_close(array_of_tables: Handle) -> i32 {
+    call("toml_edit_array_of_tables_close", || {
+        handle::remove::<ArrayOfTables>(array_of_tables)?;
+        Ok(())
+    })
 }
 
 #[cfg(test)]
@@ -793,19 +2375,21 @@ mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
     use super::*;
 
+    use error::{last_error, ErrorCode, Result};
+    use labview_interop::{memory::UHandle, types::string::LStr};
+    use std::ptr;
+
     // function to remove leading whitespace from each line in a string.
     fn remove_indentation(s: &str) -> String {
         let mut result = String::new();
         for line in s.lines() {
             let trimmed_line = line.trim_start();
             result.push_str(trimmed_line);
-            result.push_str("\n");
+            result.push('\n');
         }
 
         // remove leading and trailing newlines
-        result = result.trim().to_string();
-
-        return result;
+        result.trim().to_string()
     }
 
     // function to assert that two strings are equal, ignoring indentation and leading/trailing newlines.
@@ -814,4 +2398,3126 @@ mod tests {
         let s2 = remove_indentation(s2);
         assert_eq!(s1, s2);
     }
+
+    // a LabVIEW string built in Rust memory, so string inputs can be passed to the exported functions.
+    // (writing to a string needs the LabVIEW memory manager, so only use these as inputs)
+    struct TestString {
+        _data: Vec<u32>,
+        pointer: Box<*mut LStr>,
+    }
+
+    impl TestString {
+        fn new(s: &str) -> Self {
+            TestString::from_bytes(s.as_bytes())
+        }
+
+        fn from_bytes(s: &[u8]) -> Self {
+            // store as u32 so the LStr size field is aligned
+            let mut data = vec![0u32; 1 + s.len().div_ceil(4)];
+            data[0] = s.len() as u32;
+            unsafe {
+                let bytes = (data.as_mut_ptr() as *mut u8).add(4);
+                ptr::copy_nonoverlapping(s.as_ptr(), bytes, s.len());
+            }
+            let pointer = ptr::slice_from_raw_parts_mut(data.as_mut_ptr() as *mut u8, s.len());
+            TestString {
+                _data: data,
+                pointer: Box::new(pointer as *mut LStr),
+            }
+        }
+
+        fn handle(&mut self) -> LStrHandle {
+            UHandle(&mut *self.pointer as *mut *mut LStr)
+        }
+    }
+
+    fn doc_from_str(toml: &str) -> Handle {
+        let mut doc = 0;
+        let mut toml = TestString::new(toml);
+        assert_eq!(toml_edit_doc_from_string(toml.handle(), &mut doc), 0);
+        doc
+    }
+
+    #[test]
+    fn test_string_round_trip() {
+        let mut string = TestString::new("hello = \"world\"");
+        assert_eq!(read_str(string.handle()).unwrap(), "hello = \"world\"");
+    }
+
+    #[test]
+    fn test_get_i64_zero_is_not_an_error() {
+        let mut item = 0;
+        let mut value = 0;
+        let mut integer = -1;
+
+        assert_eq!(toml_edit_item_new_value_from_i64(0, &mut item), 0);
+        assert_eq!(toml_edit_item_into_value(item, &mut value), 0);
+        assert_eq!(toml_edit_value_get_i64(value, &mut integer), 0);
+        assert_eq!(integer, 0);
+        assert_eq!(last_error(), None);
+
+        assert_eq!(toml_edit_value_close(value), 0);
+        assert_eq!(toml_edit_item_close(item), 0);
+    }
+
+    #[test]
+    fn test_get_i64_wrong_type_sets_last_error() {
+        let mut item = 0;
+        let mut value = 0;
+        let mut integer = 0;
+
+        assert_eq!(
+            toml_edit_item_new_value_from_bool(true.into(), &mut item),
+            0
+        );
+        assert_eq!(toml_edit_item_into_value(item, &mut value), 0);
+        assert_eq!(
+            toml_edit_value_get_i64(value, &mut integer),
+            ErrorCode::WrongType as i32
+        );
+
+        let error = last_error().unwrap();
+        assert_eq!(error.code, ErrorCode::WrongType as i32);
+        assert_eq!(error.message, "Value is not a Integer");
+        assert_eq!(error.function, "toml_edit_value_get_i64");
+
+        toml_edit_value_close(value);
+        toml_edit_item_close(item);
+    }
+
+    #[test]
+    fn test_null_reference_is_an_error() {
+        let mut table = 0;
+
+        assert_eq!(
+            toml_edit_doc_get_root_table(0, &mut table),
+            ErrorCode::NullPointer as i32
+        );
+        assert_eq!(table, 0);
+        assert_eq!(last_error().unwrap().message, "Document is null");
+    }
+
+    #[test]
+    fn test_wrong_reference_type_is_an_error() {
+        let mut table = 0;
+        let mut item = 0;
+        assert_eq!(toml_edit_table_new(&mut table), 0);
+
+        // a Table passed where an Item is expected
+        assert_eq!(
+            toml_edit_item_into_table(table, &mut item),
+            ErrorCode::WrongType as i32
+        );
+        assert_eq!(
+            last_error().unwrap().message,
+            "Item reference expected, but got a Table reference"
+        );
+
+        // closing it as the wrong type must not free it
+        assert_eq!(toml_edit_item_close(table), ErrorCode::WrongType as i32);
+        assert_eq!(toml_edit_table_to_item(table, &mut item), 0);
+
+        toml_edit_item_close(item);
+        toml_edit_table_close(table);
+    }
+
+    #[test]
+    fn test_closed_reference_is_an_error() {
+        let mut table = 0;
+        let mut item = 0;
+        assert_eq!(toml_edit_table_new(&mut table), 0);
+        assert_eq!(toml_edit_table_close(table), 0);
+
+        // use after close, and double close
+        assert_eq!(
+            toml_edit_table_to_item(table, &mut item),
+            ErrorCode::InvalidReference as i32
+        );
+        assert_eq!(
+            toml_edit_table_close(table),
+            ErrorCode::InvalidReference as i32
+        );
+
+        // a new object reusing the slot doesn't revive the old reference
+        let mut reused = 0;
+        assert_eq!(toml_edit_table_new(&mut reused), 0);
+        assert_ne!(reused, table);
+        assert_eq!(
+            toml_edit_table_close(table),
+            ErrorCode::InvalidReference as i32
+        );
+        assert_eq!(toml_edit_table_close(reused), 0);
+    }
+
+    #[test]
+    fn test_unknown_reference_is_an_error() {
+        let mut item = 0;
+
+        assert_eq!(
+            toml_edit_table_to_item(usize::MAX, &mut item),
+            ErrorCode::InvalidReference as i32
+        );
+        assert_eq!(
+            last_error().unwrap().message,
+            "Table reference is invalid (closed, or not from this library)"
+        );
+    }
+
+    fn doc_to_string(doc: Handle) -> String {
+        handle::with(doc, |doc: &mut Document| Ok(doc.to_string())).unwrap()
+    }
+
+    #[test]
+    fn test_edit_through_table_view_changes_document() {
+        let doc = doc_from_str("# settings\n[nipm]\nversion = \"1.2.3\"  # pinned\n\n[vipm]\n");
+        let mut table = 0;
+        let mut item = 0;
+        let mut name = TestString::new("nipm");
+        let mut key = TestString::new("url");
+
+        assert_eq!(toml_edit_doc_get_table(doc, name.handle(), &mut table), 0);
+        assert_eq!(
+            toml_edit_item_new_value_from_string(TestString::new("ni.com").handle(), &mut item),
+            0
+        );
+        assert_eq!(toml_edit_table_set_item(table, key.handle(), item), 0);
+
+        assert_eq!(
+            doc_to_string(doc),
+            "# settings\n[nipm]\nversion = \"1.2.3\"  # pinned\nurl = \"ni.com\"\n\n[vipm]\n"
+        );
+
+        // closing the view leaves the Document open
+        assert_eq!(toml_edit_table_close(table), 0);
+        assert!(doc_to_string(doc).contains("url = \"ni.com\""));
+
+        toml_edit_item_close(item);
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_edit_through_nested_views_changes_document() {
+        let doc = doc_from_str("[deps]\nmy_package = { version = \"1.0\", url = \"ni.com\" }\n");
+        let mut root = 0;
+        let mut deps_item = 0;
+        let mut deps = 0;
+        let mut package_item = 0;
+        let mut package_value = 0;
+        let mut package = 0;
+        let mut removed = LVBool::from(false);
+
+        assert_eq!(toml_edit_doc_get_root_table(doc, &mut root), 0);
+        assert_eq!(
+            toml_edit_table_get_item(root, TestString::new("deps").handle(), &mut deps_item),
+            0
+        );
+        assert_eq!(toml_edit_item_into_table(deps_item, &mut deps), 0);
+        assert_eq!(
+            toml_edit_table_get_item(
+                deps,
+                TestString::new("my_package").handle(),
+                &mut package_item
+            ),
+            0
+        );
+        assert_eq!(
+            toml_edit_item_into_value(package_item, &mut package_value),
+            0
+        );
+        assert_eq!(
+            toml_edit_value_get_inline_table(package_value, &mut package),
+            0
+        );
+        assert_eq!(
+            toml_edit_inline_table_remove_item(
+                package,
+                TestString::new("version").handle(),
+                &mut removed
+            ),
+            0
+        );
+
+        assert!(bool::from(removed));
+        assert_eq!(
+            doc_to_string(doc),
+            "[deps]\nmy_package = { url = \"ni.com\" }\n"
+        );
+
+        toml_edit_inline_table_close(package);
+        toml_edit_value_close(package_value);
+        toml_edit_item_close(package_item);
+        toml_edit_table_close(deps);
+        toml_edit_item_close(deps_item);
+        toml_edit_table_close(root);
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_view_of_closed_document_is_an_error() {
+        let doc = doc_from_str("[table]\nkey = 1\n");
+        let mut table = 0;
+        let mut item = 0;
+
+        assert_eq!(
+            toml_edit_doc_get_table(doc, TestString::new("table").handle(), &mut table),
+            0
+        );
+        assert_eq!(toml_edit_doc_close(doc), 0);
+
+        assert_eq!(
+            toml_edit_table_to_item(table, &mut item),
+            ErrorCode::InvalidReference as i32
+        );
+        // the view itself can still be closed
+        assert_eq!(toml_edit_table_close(table), 0);
+    }
+
+    #[test]
+    fn test_view_of_removed_key_is_not_found() {
+        let doc = doc_from_str("[table]\nkey = 1\n");
+        let mut root = 0;
+        let mut item = 0;
+        let mut removed = LVBool::from(false);
+
+        assert_eq!(toml_edit_doc_get_root_table(doc, &mut root), 0);
+        assert_eq!(
+            toml_edit_table_get_item(root, TestString::new("table").handle(), &mut item),
+            0
+        );
+        assert_eq!(
+            toml_edit_table_remove_item(root, TestString::new("table").handle(), &mut removed),
+            0
+        );
+
+        let mut table = 0;
+        assert_eq!(
+            toml_edit_item_into_table(item, &mut table),
+            ErrorCode::NotFound as i32
+        );
+        assert_eq!(last_error().unwrap().message, "Key not found: table");
+
+        toml_edit_item_close(item);
+        toml_edit_table_close(root);
+        toml_edit_doc_close(doc);
+    }
+
+    fn get_f64(value: Item, integer_as_float: bool) -> std::result::Result<f64, i32> {
+        let item = handle::insert(value).unwrap();
+        let mut value = 0;
+        let mut float = 0.0;
+        assert_eq!(toml_edit_item_into_value(item, &mut value), 0);
+
+        let status = toml_edit_value_get_f64(value, integer_as_float.into(), &mut float);
+
+        toml_edit_value_close(value);
+        toml_edit_item_close(item);
+        match status {
+            0 => Ok(float),
+            status => Err(status),
+        }
+    }
+
+    #[test]
+    fn test_get_f64() {
+        let doc = doc_from_str(
+            "a = 1.5\nb = inf\nc = -inf\nd = nan\ne = 1\n\
+             f = 9007199254740992\ng = 9007199254740993\nh = -9223372036854775808\n\
+             i = 9223372036854775807\n",
+        );
+        let get = |key: &str| handle::with(doc, |doc: &mut Document| Ok(doc[key].clone())).unwrap();
+
+        assert_eq!(get_f64(get("a"), false), Ok(1.5));
+        assert_eq!(get_f64(get("b"), false), Ok(f64::INFINITY));
+        assert_eq!(get_f64(get("c"), false), Ok(f64::NEG_INFINITY));
+        assert!(get_f64(get("d"), false).unwrap().is_nan());
+
+        // an Integer is only read as a float when asked to
+        assert_eq!(get_f64(get("e"), false), Err(ErrorCode::WrongType as i32));
+        assert_eq!(get_f64(get("e"), true), Ok(1.0));
+
+        // but not one a f64 would round
+        assert_eq!(get_f64(get("f"), true), Ok(9007199254740992.0));
+        assert_eq!(get_f64(get("g"), true), Err(ErrorCode::InvalidValue as i32));
+        assert_eq!(get_f64(get("h"), true), Ok(-9223372036854775808.0));
+        assert_eq!(get_f64(get("i"), true), Err(ErrorCode::InvalidValue as i32));
+
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_new_value_from_f64() {
+        for (float, toml) in [
+            (1.0, "1.0"),
+            (-0.25, "-0.25"),
+            (f64::INFINITY, "inf"),
+            (f64::NEG_INFINITY, "-inf"),
+            (f64::NAN, "nan"),
+        ] {
+            let mut item = 0;
+            assert_eq!(toml_edit_item_new_value_from_f64(float, &mut item), 0);
+            let text = handle::with(item, |item: &mut Item| Ok(item.to_string())).unwrap();
+            assert_eq!(text.trim(), toml);
+            toml_edit_item_close(item);
+        }
+    }
+
+    fn datetime_value(toml: &str) -> Handle {
+        let datetime: toml_edit::Datetime = toml.parse().unwrap();
+        handle::insert(Value::from(datetime)).unwrap()
+    }
+
+    fn new_from_timestamp(seconds: i64, fraction: u64, kind: i32, offset_minutes: i32) -> String {
+        let timestamp = LVTime::from_parts(seconds as u64, fraction);
+        let mut item = 0;
+        assert_eq!(
+            toml_edit_item_new_value_from_timestamp(&timestamp, kind, offset_minutes, &mut item),
+            0
+        );
+        let toml = handle::with(item, |item: &mut Item| Ok(item.to_string())).unwrap();
+        toml_edit_item_close(item);
+        toml.trim().to_string()
+    }
+
+    #[test]
+    fn test_get_timestamp() {
+        for (toml, local_offset_minutes, seconds, fraction) in [
+            ("1970-01-01T00:00:00Z", 0, 2_082_844_800i64, 0u64),
+            ("2024-02-29T12:34:56.5+02:00", 0, 3_792_047_696, 1 << 63),
+            ("2024-02-29T10:34:56.5", 0, 3_792_047_696, 1 << 63),
+            ("2024-02-29T12:34:56.5", 120, 3_792_047_696, 1 << 63),
+            ("1900-01-01", 0, -126_144_000, 0),
+            ("01:00:00", 0, 3600, 0),
+            ("01:00:00", 120, 3600, 0),
+        ] {
+            let value = datetime_value(toml);
+            let mut timestamp = LVTime::from_parts(0, 0);
+
+            assert_eq!(
+                toml_edit_value_get_timestamp(value, local_offset_minutes, &mut timestamp),
+                0
+            );
+            assert_eq!(timestamp.to_parts(), (seconds as u64, fraction), "{}", toml);
+
+            toml_edit_value_close(value);
+        }
+    }
+
+    #[test]
+    fn test_new_value_from_timestamp() {
+        let seconds = 3_792_047_696;
+        let fraction = 1 << 63;
+
+        let kinds = [
+            (DatetimeKind::OffsetDatetime, 0, "2024-02-29T10:34:56.5Z"),
+            (
+                DatetimeKind::OffsetDatetime,
+                -90,
+                "2024-02-29T09:04:56.5-01:30",
+            ),
+            (DatetimeKind::LocalDatetime, 120, "2024-02-29T12:34:56.5"),
+            (DatetimeKind::LocalDate, 0, "2024-02-29"),
+            (DatetimeKind::LocalTime, 0, "10:34:56.5"),
+        ];
+        for (kind, offset_minutes, toml) in kinds {
+            assert_eq!(
+                new_from_timestamp(seconds, fraction, kind as i32, offset_minutes),
+                toml
+            );
+        }
+
+        // before the LabVIEW epoch
+        assert_eq!(
+            new_from_timestamp(-1, 0, DatetimeKind::OffsetDatetime as i32, 0),
+            "1903-12-31T23:59:59Z"
+        );
+    }
+
+    #[test]
+    fn test_timestamp_round_trip_keeps_nanoseconds() {
+        let value = datetime_value("2001-02-03T04:05:06.123456789Z");
+        let mut timestamp = LVTime::from_parts(0, 0);
+        assert_eq!(toml_edit_value_get_timestamp(value, 0, &mut timestamp), 0);
+        toml_edit_value_close(value);
+
+        let (seconds, fraction) = timestamp.to_parts();
+        assert_eq!(
+            new_from_timestamp(
+                seconds as i64,
+                fraction,
+                DatetimeKind::OffsetDatetime as i32,
+                0
+            ),
+            "2001-02-03T04:05:06.123456789Z"
+        );
+    }
+
+    #[test]
+    fn test_datetime_parts() {
+        let value = datetime_value("1979-05-27T07:32:00.25-07:00");
+        let mut parts = [0; 10];
+        let [kind, year, month, day, hour, minute, second, nanosecond, offset, _] = &mut parts;
+
+        assert_eq!(
+            toml_edit_value_get_datetime(
+                value, kind, year, month, day, hour, minute, second, nanosecond, offset
+            ),
+            0
+        );
+        assert_eq!(parts[..9], [0, 1979, 5, 27, 7, 32, 0, 250_000_000, -420]);
+        toml_edit_value_close(value);
+
+        let mut item = 0;
+        assert_eq!(
+            toml_edit_item_new_value_from_datetime(2, 2024, 2, 29, 99, 99, 99, -1, 99, &mut item),
+            0
+        );
+        let toml = handle::with(item, |item: &mut Item| Ok(item.to_string())).unwrap();
+        assert_eq!(toml.trim(), "2024-02-29");
+        toml_edit_item_close(item);
+
+        assert_eq!(
+            toml_edit_item_new_value_from_datetime(2, 2023, 2, 29, 0, 0, 0, 0, 0, &mut item),
+            ErrorCode::InvalidValue as i32
+        );
+        assert_eq!(
+            last_error().unwrap().message,
+            "Invalid datetime: day 29 is not in 1..=28"
+        );
+        assert_eq!(
+            toml_edit_item_new_value_from_datetime(7, 2023, 1, 1, 0, 0, 0, 0, 0, &mut item),
+            ErrorCode::InvalidValue as i32
+        );
+    }
+
+    fn new_i64(integer: i64) -> Handle {
+        let mut item = 0;
+        assert_eq!(toml_edit_item_new_value_from_i64(integer, &mut item), 0);
+        item
+    }
+
+    fn array_to_string(array: Handle) -> String {
+        handle::with(array, |array: &mut Array| Ok(array.to_string())).unwrap()
+    }
+
+    #[test]
+    fn test_build_array() {
+        let mut array = 0;
+        let mut len = -1;
+        let mut removed = LVBool::from(false);
+        let (one, two, three) = (new_i64(1), new_i64(2), new_i64(3));
+
+        assert_eq!(toml_edit_array_new(&mut array), 0);
+        assert_eq!(toml_edit_array_push(array, one), 0);
+        assert_eq!(toml_edit_array_push(array, three), 0);
+        assert_eq!(toml_edit_array_insert(array, 1, two), 0);
+        assert_eq!(toml_edit_array_insert(array, 3, one), 0);
+        assert_eq!(array_to_string(array), "[1, 2, 3, 1]");
+
+        assert_eq!(toml_edit_array_set(array, 3, three), 0);
+        assert_eq!(toml_edit_array_remove(array, 0, &mut removed), 0);
+        assert!(bool::from(removed));
+        assert_eq!(toml_edit_array_remove(array, 3, &mut removed), 0);
+        assert!(!bool::from(removed));
+        assert_eq!(toml_edit_array_len(array, &mut len), 0);
+        assert_eq!(len, 3);
+        assert_eq!(array_to_string(array), "[2, 3, 3]");
+
+        // out of range
+        assert_eq!(
+            toml_edit_array_set(array, 3, one),
+            ErrorCode::NotFound as i32
+        );
+        assert_eq!(
+            toml_edit_array_insert(array, 4, one),
+            ErrorCode::NotFound as i32
+        );
+        assert_eq!(
+            last_error().unwrap().message,
+            "Index 4 is out of range (length 3)"
+        );
+        assert_eq!(
+            toml_edit_array_set(array, -1, one),
+            ErrorCode::NotFound as i32
+        );
+
+        // only values can go in an array
+        let mut table = 0;
+        assert_eq!(toml_edit_table_new(&mut table), 0);
+        let mut table_item = 0;
+        assert_eq!(toml_edit_table_to_item(table, &mut table_item), 0);
+        assert_eq!(
+            toml_edit_array_push(array, table_item),
+            ErrorCode::WrongType as i32
+        );
+
+        for item in [one, two, three, table_item] {
+            toml_edit_item_close(item);
+        }
+        toml_edit_table_close(table);
+        toml_edit_array_close(array);
+    }
+
+    #[test]
+    fn test_edit_array_in_document() {
+        let doc = doc_from_str("channels = [\"ai0\",  \"ai1\"]  # inputs\n");
+        let mut item = 0;
+        let mut value = 0;
+        let mut array = 0;
+        let mut element = 0;
+        let mut name = TestString::new("channels");
+
+        assert_eq!(toml_edit_doc_get_root_table(doc, &mut item), 0);
+        let root = item;
+        assert_eq!(toml_edit_table_get_item(root, name.handle(), &mut item), 0);
+        assert_eq!(toml_edit_item_into_value(item, &mut value), 0);
+        assert_eq!(toml_edit_value_get_array(value, &mut array), 0);
+        assert_eq!(toml_edit_array_get(array, 1, &mut element), 0);
+        assert_eq!(
+            handle::with(element, |value: &mut Value| Ok(value
+                .as_str()
+                .unwrap()
+                .to_string())),
+            Ok("ai1".to_string())
+        );
+
+        let mut new = 0;
+        let mut string = TestString::new("ai2");
+        assert_eq!(
+            toml_edit_item_new_value_from_string(string.handle(), &mut new),
+            0
+        );
+        assert_eq!(toml_edit_array_set(array, 1, new), 0);
+        assert_eq!(toml_edit_array_push(array, new), 0);
+
+        assert_eq!(
+            doc_to_string(doc),
+            "channels = [\"ai0\",  \"ai2\", \"ai2\"]  # inputs\n"
+        );
+
+        let mut len = 0;
+        assert_eq!(
+            toml_edit_array_get(array, 3, &mut element),
+            ErrorCode::NotFound as i32
+        );
+        assert_eq!(toml_edit_array_len(array, &mut len), 0);
+        assert_eq!(len, 3);
+
+        toml_edit_item_close(new);
+        toml_edit_value_close(element);
+        toml_edit_array_close(array);
+        toml_edit_value_close(value);
+        toml_edit_item_close(item);
+        toml_edit_table_close(root);
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_element_view_is_not_found_after_elements_move() {
+        let doc = doc_from_str("samples = [10, 20, 30]\nother = [1, 2]\n");
+        let (mut root, mut item, mut value, mut array) = (0, 0, 0, 0);
+        let (mut second, mut other) = (0, 0);
+        let mut integer = 0;
+        let mut removed = LVBool::from(false);
+        let s = TestString::new;
+
+        assert_eq!(toml_edit_doc_get_root_table(doc, &mut root), 0);
+        assert_eq!(
+            toml_edit_table_get_item(root, s("samples").handle(), &mut item),
+            0
+        );
+        assert_eq!(toml_edit_item_into_value(item, &mut value), 0);
+        assert_eq!(toml_edit_value_get_array(value, &mut array), 0);
+        assert_eq!(toml_edit_array_get(array, 1, &mut second), 0);
+        assert_eq!(toml_edit_value_get_i64(second, &mut integer), 0);
+        assert_eq!(integer, 20);
+
+        // replacing or adding an element leaves the others where they were
+        let new = new_i64(40);
+        assert_eq!(toml_edit_array_push(array, new), 0);
+        assert_eq!(toml_edit_array_set(array, 1, new), 0);
+        assert_eq!(toml_edit_value_get_i64(second, &mut integer), 0);
+        assert_eq!(integer, 40);
+
+        // but removing an earlier element moves it, so the view no longer finds it
+        assert_eq!(toml_edit_array_remove(array, 0, &mut removed), 0);
+        assert_eq!(
+            toml_edit_value_get_i64(second, &mut integer),
+            ErrorCode::NotFound as i32
+        );
+        assert_eq!(toml_edit_value_close(second), 0);
+
+        // as does inserting one, or replacing them all
+        assert_eq!(toml_edit_array_get(array, 1, &mut second), 0);
+        assert_eq!(toml_edit_array_insert(array, 0, new), 0);
+        assert_eq!(
+            toml_edit_value_get_i64(second, &mut integer),
+            ErrorCode::NotFound as i32
+        );
+        toml_edit_value_close(second);
+        assert_eq!(toml_edit_array_get(array, 1, &mut second), 0);
+        let mut integers = TestArray::new(vec![1i64, 2]);
+        assert_eq!(toml_edit_array_set_i64s(array, integers.handle()), 0);
+        assert_eq!(
+            toml_edit_value_get_i64(second, &mut integer),
+            ErrorCode::NotFound as i32
+        );
+        toml_edit_value_close(second);
+
+        // or removing one by its key path, which leaves views of other arrays alone
+        assert_eq!(toml_edit_array_get(array, 1, &mut second), 0);
+        let (mut other_value, mut other_array) = (0, 0);
+        assert_eq!(
+            toml_edit_table_get_item(root, s("other").handle(), &mut other),
+            0
+        );
+        assert_eq!(toml_edit_item_into_value(other, &mut other_value), 0);
+        assert_eq!(toml_edit_value_get_array(other_value, &mut other_array), 0);
+        let mut last = 0;
+        assert_eq!(toml_edit_array_get(other_array, 1, &mut last), 0);
+        assert_eq!(
+            toml_edit_doc_remove_by_path(doc, s("samples[0]").handle(), &mut removed),
+            0
+        );
+        assert_eq!(
+            toml_edit_value_get_i64(second, &mut integer),
+            ErrorCode::NotFound as i32
+        );
+        assert_eq!(toml_edit_value_get_i64(last, &mut integer), 0);
+        assert_eq!(integer, 2);
+        assert_eq!(doc_to_string(doc), "samples = [2]\nother = [1, 2]\n");
+
+        toml_edit_value_close(last);
+        toml_edit_array_close(other_array);
+        toml_edit_value_close(other_value);
+        toml_edit_item_close(other);
+        toml_edit_value_close(second);
+        toml_edit_item_close(new);
+        toml_edit_array_close(array);
+        toml_edit_value_close(value);
+        toml_edit_item_close(item);
+        toml_edit_table_close(root);
+        toml_edit_doc_close(doc);
+    }
+
+    // a LabVIEW 1D array built in Rust memory, so array inputs can be passed to the exported functions
+    // (like `TestString`, resizing it needs the LabVIEW memory manager, so only use these as inputs)
+    struct TestArray<T> {
+        _data: Vec<u64>,
+        pointer: Box<*mut u8>,
+        _element: std::marker::PhantomData<T>,
+    }
+
+    impl<T> TestArray<T> {
+        fn new(values: Vec<T>) -> Self {
+            // the i32 length, then the elements aligned to their size (as 64-bit LabVIEW lays them out)
+            let offset = std::mem::align_of::<T>().max(4);
+            let bytes = offset + values.len() * std::mem::size_of::<T>();
+            let mut data = vec![0u64; bytes.div_ceil(8)];
+            let block = data.as_mut_ptr() as *mut u8;
+            unsafe {
+                (block as *mut i32).write(values.len() as i32);
+                for (index, value) in values.into_iter().enumerate() {
+                    (block.add(offset) as *mut T)
+                        .add(index)
+                        .write_unaligned(value);
+                }
+            }
+            TestArray {
+                _data: data,
+                pointer: Box::new(block),
+                _element: std::marker::PhantomData,
+            }
+        }
+
+        fn handle(&mut self) -> LVArrayHandle<1, T> {
+            UHandle(&mut *self.pointer as *mut *mut u8 as *mut _)
+        }
+    }
+
+    #[test]
+    fn test_set_array_from_labview_arrays() {
+        let mut array = 0;
+        assert_eq!(toml_edit_array_new(&mut array), 0);
+
+        let mut integers = TestArray::new(vec![1i64, -2, 3]);
+        assert_eq!(toml_edit_array_set_i64s(array, integers.handle()), 0);
+        assert_eq!(array_to_string(array), "[1, -2, 3]");
+
+        let mut floats = TestArray::new(vec![0.5, f64::INFINITY]);
+        assert_eq!(toml_edit_array_set_f64s(array, floats.handle()), 0);
+        assert_eq!(array_to_string(array), "[0.5, inf]");
+
+        let mut booleans = TestArray::new(vec![LVBool::from(true), LVBool::from(false)]);
+        assert_eq!(toml_edit_array_set_bools(array, booleans.handle()), 0);
+        assert_eq!(array_to_string(array), "[true, false]");
+
+        let (mut ai0, mut ai1) = (TestString::new("ai0"), TestString::new("ai1"));
+        let mut strings =
+            TestArray::new(vec![ai0.handle(), UHandle(ptr::null_mut()), ai1.handle()]);
+        assert_eq!(toml_edit_array_set_strings(array, strings.handle()), 0);
+        assert_eq!(array_to_string(array), "[\"ai0\", \"\", \"ai1\"]");
+
+        // LabVIEW can pass an empty array as a null handle
+        assert_eq!(toml_edit_array_set_i64s(array, UHandle(ptr::null_mut())), 0);
+        assert_eq!(array_to_string(array), "[]");
+
+        toml_edit_array_close(array);
+    }
+
+    #[test]
+    fn test_set_array_keeps_array_formatting() {
+        let doc = doc_from_str("samples = [1, 2,]  # raw\n");
+        let mut samples = TestArray::new(vec![10i64, 20, 30]);
+        let mut root = 0;
+        let mut item = 0;
+        let mut value = 0;
+        let mut array = 0;
+
+        assert_eq!(toml_edit_doc_get_root_table(doc, &mut root), 0);
+        assert_eq!(
+            toml_edit_table_get_item(root, TestString::new("samples").handle(), &mut item),
+            0
+        );
+        assert_eq!(toml_edit_item_into_value(item, &mut value), 0);
+        assert_eq!(toml_edit_value_get_array(value, &mut array), 0);
+
+        assert_eq!(toml_edit_array_set_i64s(array, samples.handle()), 0);
+        assert_eq!(doc_to_string(doc), "samples = [10, 20, 30,]  # raw\n");
+
+        toml_edit_array_close(array);
+        toml_edit_value_close(value);
+        toml_edit_item_close(item);
+        toml_edit_table_close(root);
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_get_mixed_array_is_an_error() {
+        let doc = doc_from_str("samples = [1, 2.5, 3]\n");
+        let mut item = 0;
+        let mut value = 0;
+        let mut array = 0;
+        let mut integers: LVArrayHandle<1, i64> = UHandle(ptr::null_mut());
+        let mut floats: LVArrayHandle<1, f64> = UHandle(ptr::null_mut());
+
+        assert_eq!(
+            toml_edit_doc_get_by_path(doc, TestString::new("samples").handle(), &mut item),
+            0
+        );
+        assert_eq!(toml_edit_item_into_value(item, &mut value), 0);
+        assert_eq!(toml_edit_value_get_array(value, &mut array), 0);
+
+        assert_eq!(
+            toml_edit_array_get_i64s(array, &mut integers),
+            ErrorCode::WrongType as i32
+        );
+        assert_eq!(
+            last_error().unwrap().message,
+            "Array element 1 (the Array is mixed) is not a Integer"
+        );
+        assert_eq!(
+            toml_edit_array_get_f64s(array, LVBool::from(false), &mut floats),
+            ErrorCode::WrongType as i32
+        );
+
+        // with integers accepted as floats the array is readable, but writing the LabVIEW array
+        // needs the LabVIEW memory manager, which isn't there outside LabVIEW
+        assert_eq!(
+            toml_edit_array_get_f64s(array, LVBool::from(true), &mut floats),
+            ErrorCode::LabviewMemory as i32
+        );
+        assert!(floats.0.is_null());
+
+        // but not with an integer a f64 would round
+        let huge = doc_from_str("samples = [1.5, 9007199254740993]\n");
+        let mut huge_array = 0;
+        toml_edit_item_close(item);
+        assert_eq!(
+            toml_edit_doc_get_by_path(huge, TestString::new("samples").handle(), &mut item),
+            0
+        );
+        toml_edit_value_close(value);
+        assert_eq!(toml_edit_item_into_value(item, &mut value), 0);
+        assert_eq!(toml_edit_value_get_array(value, &mut huge_array), 0);
+        assert_eq!(
+            toml_edit_array_get_f64s(huge_array, LVBool::from(true), &mut floats),
+            ErrorCode::InvalidValue as i32
+        );
+        assert_eq!(
+            last_error().unwrap().message,
+            "Array element 1 (9007199254740993) is too big to convert to a float exactly"
+        );
+        assert!(floats.0.is_null());
+
+        toml_edit_array_close(huge_array);
+        toml_edit_doc_close(huge);
+        toml_edit_array_close(array);
+        toml_edit_value_close(value);
+        toml_edit_item_close(item);
+        toml_edit_doc_close(doc);
+    }
+
+    // a detached Table holding the keys in `toml`
+    fn new_table(toml: &str) -> Handle {
+        let doc = Document::from_str(toml).unwrap();
+        handle::insert(doc.as_table().clone()).unwrap()
+    }
+
+    #[test]
+    fn test_edit_array_of_tables() {
+        let doc = doc_from_str(
+            r#"
+            title = "recipe"
+
+            [[test_step]]
+            name = "power on"
+
+            [[test_step]]
+            name = "measure"
+            [test_step.limits]
+            low = 1
+
+            [report]
+            path = "out.csv"
+            "#,
+        );
+        let mut root = 0;
+        let mut item = 0;
+        let mut steps = 0;
+        let mut len = 0;
+        let mut step = 0;
+        let mut removed = LVBool::from(false);
+
+        assert_eq!(toml_edit_doc_get_root_table(doc, &mut root), 0);
+        assert_eq!(
+            toml_edit_table_get_item(root, TestString::new("test_step").handle(), &mut item),
+            0
+        );
+        assert_eq!(toml_edit_item_into_array_of_tables(item, &mut steps), 0);
+        assert_eq!(toml_edit_array_of_tables_len(steps, &mut len), 0);
+        assert_eq!(len, 2);
+
+        // a table from the array is a live view
+        assert_eq!(toml_edit_array_of_tables_get(steps, 0, &mut step), 0);
+        let mut value = 0;
+        assert_eq!(toml_edit_item_new_value_from_i64(5, &mut value), 0);
+        assert_eq!(
+            toml_edit_table_set_item(step, TestString::new("volts").handle(), value),
+            0
+        );
+
+        let (settle, wait, log) = (
+            new_table("name = \"settle\""),
+            new_table("name = \"wait\""),
+            new_table("name = \"log\""),
+        );
+        assert_eq!(toml_edit_array_of_tables_push(steps, log), 0);
+        assert_eq!(toml_edit_array_of_tables_insert(steps, 1, settle), 0);
+        assert_eq!(toml_edit_array_of_tables_insert(steps, 0, wait), 0);
+        assert_eq!(toml_edit_array_of_tables_remove(steps, 4, &mut removed), 0);
+        assert!(bool::from(removed));
+        assert_eq!(toml_edit_array_of_tables_set(steps, 2, log), 0);
+
+        assert_equal_ignore_indentation(
+            &doc_to_string(doc),
+            r#"
+            title = "recipe"
+
+            [[test_step]]
+            name = "wait"
+
+            [[test_step]]
+            name = "power on"
+            volts = 5
+
+            [[test_step]]
+            name = "log"
+
+            [[test_step]]
+            name = "measure"
+            [test_step.limits]
+            low = 1
+
+            [report]
+            path = "out.csv"
+            "#,
+        );
+
+        // the view of "power on" was taken before tables were inserted ahead of it, so it no
+        // longer finds it, rather than editing whichever table is now at its old index
+        let before = doc_to_string(doc);
+        assert_eq!(
+            toml_edit_table_set_item(step, TestString::new("volts").handle(), value),
+            ErrorCode::NotFound as i32
+        );
+        assert_eq!(doc_to_string(doc), before);
+
+        // as does a view of a table after one that is removed
+        let mut measure = 0;
+        assert_eq!(toml_edit_array_of_tables_get(steps, 3, &mut measure), 0);
+        assert_eq!(toml_edit_array_of_tables_remove(steps, 0, &mut removed), 0);
+        assert_eq!(
+            toml_edit_table_set_item(measure, TestString::new("volts").handle(), value),
+            ErrorCode::NotFound as i32
+        );
+        assert_eq!(toml_edit_table_close(measure), 0);
+        assert_eq!(toml_edit_array_of_tables_insert(steps, 0, wait), 0);
+
+        // out of range
+        assert_eq!(
+            toml_edit_array_of_tables_get(steps, 4, &mut step),
+            ErrorCode::NotFound as i32
+        );
+        assert_eq!(
+            toml_edit_array_of_tables_insert(steps, 5, log),
+            ErrorCode::NotFound as i32
+        );
+        assert_eq!(toml_edit_array_of_tables_remove(steps, 4, &mut removed), 0);
+        assert!(!bool::from(removed));
+
+        for table in [step, settle, wait, log, root] {
+            toml_edit_table_close(table);
+        }
+        toml_edit_item_close(value);
+        toml_edit_array_of_tables_close(steps);
+        toml_edit_item_close(item);
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_build_array_of_tables() {
+        let mut steps = 0;
+        let mut item = 0;
+        let doc = doc_from_str("");
+        let (one, two) = (new_table("n = 1"), new_table("n = 2"));
+
+        assert_eq!(toml_edit_array_of_tables_new(&mut steps), 0);
+        assert_eq!(toml_edit_array_of_tables_push(steps, two), 0);
+        assert_eq!(toml_edit_array_of_tables_insert(steps, 0, one), 0);
+        assert_eq!(toml_edit_array_of_tables_to_item(steps, &mut item), 0);
+        assert_eq!(
+            toml_edit_doc_set_item(doc, TestString::new("step").handle(), item),
+            0
+        );
+        assert_eq!(doc_to_string(doc), "[[step]]\nn = 1\n\n[[step]]\nn = 2\n");
+
+        // an array of tables only holds tables
+        let mut value = 0;
+        assert_eq!(toml_edit_item_new_value_from_i64(1, &mut value), 0);
+        assert_eq!(
+            toml_edit_array_of_tables_push(steps, value),
+            ErrorCode::WrongType as i32
+        );
+
+        toml_edit_item_close(value);
+        toml_edit_item_close(item);
+        toml_edit_table_close(one);
+        toml_edit_table_close(two);
+        toml_edit_array_of_tables_close(steps);
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_table_entries_keep_keys_with_newlines() {
+        let doc = Document::from_str(
+            r#"
+            "two\nlines" = 1
+            point = { x = 1.5, label = "a" }
+            [[step]]
+            [settings]
+            "#,
+        )
+        .unwrap();
+
+        let (keys, types) = table_entries(doc.as_table());
+        assert_eq!(keys, ["two\nlines", "point", "step", "settings"]);
+        assert_eq!(types, ["Integer", "InlineTable", "ArrayOfTables", "Table"]);
+
+        let point = doc["point"].as_inline_table().unwrap();
+        assert_eq!(
+            inline_table_entries(point),
+            (
+                vec!["x".to_string(), "label".to_string()],
+                vec!["Float", "String"]
+            )
+        );
+    }
+
+    // a path in a fresh temporary directory, removed (with its directory) when dropped
+    struct TestFile {
+        dir: std::path::PathBuf,
+        path: std::path::PathBuf,
+    }
+
+    impl TestFile {
+        fn new(name: &str) -> Self {
+            static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+            let count = COUNT.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            let dir = std::env::temp_dir().join(format!(
+                "toml_edit_test_{}_{}",
+                std::process::id(),
+                count
+            ));
+            std::fs::create_dir_all(&dir).unwrap();
+            let path = dir.join(name);
+            TestFile { dir, path }
+        }
+
+        fn path_str(&self) -> TestString {
+            TestString::new(self.path.to_str().unwrap())
+        }
+    }
+
+    impl Drop for TestFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn test_open_and_save_file_keeps_bom_and_crlf() {
+        let file = TestFile::new("config.toml");
+        std::fs::write(
+            &file.path,
+            "\u{feff}# settings\r\nrate = 10\r\n\r\n[dev]\r\nname = \"ai0\"\r\n",
+        )
+        .unwrap();
+
+        let mut doc = 0;
+        assert_eq!(
+            toml_edit_doc_open_file(file.path_str().handle(), &mut doc),
+            0
+        );
+        assert_eq!(
+            doc_to_string(doc),
+            "# settings\nrate = 10\n\n[dev]\nname = \"ai0\"\n"
+        );
+
+        let mut rate = 0;
+        assert_eq!(toml_edit_item_new_value_from_i64(20, &mut rate), 0);
+        assert_eq!(
+            toml_edit_doc_set_item(doc, TestString::new("rate").handle(), rate),
+            0
+        );
+        assert_eq!(toml_edit_doc_save_file(doc, file.path_str().handle()), 0);
+        assert_eq!(
+            std::fs::read_to_string(&file.path).unwrap(),
+            "\u{feff}# settings\r\nrate = 20\r\n\r\n[dev]\r\nname = \"ai0\"\r\n"
+        );
+
+        // a `\r\n` set in a comment isn't doubled
+        let mut dev = 0;
+        assert_eq!(
+            toml_edit_doc_get_table(doc, TestString::new("dev").handle(), &mut dev),
+            0
+        );
+        assert_eq!(
+            toml_edit_table_set_decor(
+                dev,
+                TestString::new("\r\n# device\r\n").handle(),
+                TestString::new("").handle()
+            ),
+            0
+        );
+        let expected =
+            "\u{feff}# settings\r\nrate = 20\r\n\r\n# device\r\n[dev]\r\nname = \"ai0\"\r\n";
+        assert_eq!(toml_edit_doc_save_file(doc, file.path_str().handle()), 0);
+        assert_eq!(std::fs::read_to_string(&file.path).unwrap(), expected);
+
+        // saving as a new file keeps the format the Document was read with
+        let new_file = TestFile::new("new.toml");
+        assert_eq!(
+            toml_edit_doc_save_file(doc, new_file.path_str().handle()),
+            0
+        );
+        assert_eq!(std::fs::read_to_string(&new_file.path).unwrap(), expected);
+
+        // a Document that wasn't read from a file takes the format of the file it replaces
+        let other = doc_from_str("rate = 30\n");
+        assert_eq!(
+            toml_edit_doc_save_file(other, new_file.path_str().handle()),
+            0
+        );
+        assert_eq!(
+            std::fs::read_to_string(&new_file.path).unwrap(),
+            "\u{feff}rate = 30\r\n"
+        );
+        let lf_file = TestFile::new("lf.toml");
+        assert_eq!(
+            toml_edit_doc_save_file(other, lf_file.path_str().handle()),
+            0
+        );
+        assert_eq!(
+            std::fs::read_to_string(&lf_file.path).unwrap(),
+            "rate = 30\n"
+        );
+
+        // and a Document read from a file with `\n` writes `\n` over a file with `\r\n`
+        let mut lf_doc = 0;
+        assert_eq!(
+            toml_edit_doc_open_file(lf_file.path_str().handle(), &mut lf_doc),
+            0
+        );
+        assert_eq!(toml_edit_doc_save_file(lf_doc, file.path_str().handle()), 0);
+        assert_eq!(std::fs::read_to_string(&file.path).unwrap(), "rate = 30\n");
+
+        toml_edit_doc_close(lf_doc);
+        toml_edit_doc_close(other);
+        toml_edit_table_close(dev);
+        toml_edit_item_close(rate);
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_save_file_rotates_backups() {
+        let file = TestFile::new("station.toml");
+        let backup = |name: &str| std::fs::read_to_string(file.dir.join(name)).ok();
+
+        for version in 1..=4 {
+            let doc = doc_from_str(&format!("version = {}\n", version));
+            assert_eq!(
+                toml_edit_doc_save_file_with_backups(doc, file.path_str().handle(), 2),
+                0
+            );
+            toml_edit_doc_close(doc);
+        }
+
+        assert_eq!(backup("station.toml").unwrap(), "version = 4\n");
+        assert_eq!(backup("station.toml.bak").unwrap(), "version = 3\n");
+        assert_eq!(backup("station.toml.bak.1").unwrap(), "version = 2\n");
+        assert_eq!(backup("station.toml.bak.2"), None);
+
+        // no temporary files are left behind
+        assert_eq!(std::fs::read_dir(&file.dir).unwrap().count(), 3);
+
+        let doc = doc_from_str("");
+        assert_eq!(
+            toml_edit_doc_save_file_with_backups(doc, file.path_str().handle(), -1),
+            ErrorCode::InvalidValue as i32
+        );
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_file_lock_times_out() {
+        let file = TestFile::new("shared.toml");
+        std::fs::write(&file.path, "a = 1\n").unwrap();
+        let (mut lock, mut other, mut doc) = (0, 0, 0);
+
+        assert_eq!(
+            toml_edit_file_lock(file.path_str().handle(), LVBool::from(true), 0, &mut lock),
+            0
+        );
+        assert_eq!(
+            toml_edit_file_lock(
+                file.path_str().handle(),
+                LVBool::from(false),
+                50,
+                &mut other
+            ),
+            ErrorCode::LockTimeout as i32
+        );
+        assert_eq!(
+            toml_edit_doc_open_file_locked(file.path_str().handle(), 50, &mut doc),
+            ErrorCode::LockTimeout as i32
+        );
+
+        // shared locks don't block each other
+        assert_eq!(toml_edit_file_unlock(lock), 0);
+        assert_eq!(
+            toml_edit_file_lock(file.path_str().handle(), LVBool::from(false), 0, &mut lock),
+            0
+        );
+        assert_eq!(
+            toml_edit_doc_open_file_locked(file.path_str().handle(), 0, &mut doc),
+            0
+        );
+        assert_eq!(
+            toml_edit_doc_save_file_locked(doc, file.path_str().handle(), 50),
+            ErrorCode::LockTimeout as i32
+        );
+
+        assert_eq!(toml_edit_file_unlock(lock), 0);
+        assert_eq!(
+            toml_edit_file_unlock(lock),
+            ErrorCode::InvalidReference as i32
+        );
+        assert_eq!(
+            toml_edit_doc_save_file_locked(doc, file.path_str().handle(), 0),
+            0
+        );
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_concurrent_writers_dont_clobber_each_other() {
+        let file = TestFile::new("counter.toml");
+        std::fs::write(&file.path, "# shared counter\ncount = 0\n").unwrap();
+        let path = file.path.to_str().unwrap().to_string();
+
+        let writers: Vec<_> = (0..4)
+            .map(|_| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..10 {
+                        let (mut lock, mut doc, mut item) = (0, 0, 0);
+                        let path = || TestString::new(&path);
+
+                        assert_eq!(
+                            toml_edit_file_lock(path().handle(), LVBool::from(true), -1, &mut lock),
+                            0
+                        );
+                        assert_eq!(toml_edit_doc_open_file(path().handle(), &mut doc), 0);
+                        let count = handle::with(doc, |doc: &mut Document| {
+                            Ok(doc["count"].as_integer().unwrap())
+                        })
+                        .unwrap();
+                        assert_eq!(toml_edit_item_new_value_from_i64(count + 1, &mut item), 0);
+                        assert_eq!(
+                            toml_edit_doc_set_item(doc, TestString::new("count").handle(), item),
+                            0
+                        );
+                        assert_eq!(toml_edit_doc_save_file(doc, path().handle()), 0);
+                        assert_eq!(toml_edit_file_unlock(lock), 0);
+
+                        toml_edit_item_close(item);
+                        toml_edit_doc_close(doc);
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        assert_eq!(
+            std::fs::read_to_string(&file.path).unwrap(),
+            "# shared counter\ncount = 40\n"
+        );
+    }
+
+    #[test]
+    fn test_update_file_merges_edits() {
+        let file = TestFile::new("station.toml");
+        std::fs::write(
+            &file.path,
+            "[dev]\nname = \"ai0\"\nrate = 10 # Hz\n\n[report]\npath = \"a.csv\"\n",
+        )
+        .unwrap();
+        let edits = doc_from_str("dev.rate = 20\n\n[log]\nlevel = \"debug\"\n");
+        let mut doc = 0;
+
+        assert_eq!(
+            toml_edit_doc_update_file(file.path_str().handle(), edits, 0, &mut doc),
+            0
+        );
+        let expected = "[dev]\nname = \"ai0\"\nrate = 20 # Hz\n\n[report]\npath = \"a.csv\"\n\n[log]\nlevel = \"debug\"\n";
+        assert_eq!(std::fs::read_to_string(&file.path).unwrap(), expected);
+        assert_eq!(doc_to_string(doc), expected);
+        toml_edit_doc_close(doc);
+
+        // a file that doesn't exist yet is created
+        let new_file = TestFile::new("new.toml");
+        assert_eq!(
+            toml_edit_doc_update_file(new_file.path_str().handle(), edits, 0, &mut doc),
+            0
+        );
+        assert_eq!(
+            std::fs::read_to_string(&new_file.path).unwrap(),
+            "dev.rate = 20\n\n[log]\nlevel = \"debug\"\n"
+        );
+
+        toml_edit_doc_close(doc);
+        toml_edit_doc_close(edits);
+    }
+
+    #[test]
+    fn test_lock_open_and_unlock_save() {
+        let file = TestFile::new("station.toml");
+        std::fs::write(&file.path, "[dev]\nname = \"ai0\"\nold = 1\n").unwrap();
+        let (mut lock, mut doc, mut dev, mut other) = (0, 0, 0, 0);
+        let s = TestString::new;
+
+        assert_eq!(
+            toml_edit_file_lock_open(file.path_str().handle(), 0, &mut lock, &mut doc),
+            0
+        );
+
+        // no one else can lock the file until it is saved
+        assert_eq!(
+            toml_edit_file_lock(file.path_str().handle(), LVBool::from(false), 0, &mut other),
+            ErrorCode::LockTimeout as i32
+        );
+
+        // a key can be removed and renamed, which `toml_edit_doc_update_file` can't do
+        assert_eq!(toml_edit_doc_get_table(doc, s("dev").handle(), &mut dev), 0);
+        let mut removed = LVBool::from(false);
+        assert_eq!(
+            toml_edit_table_remove_item(dev, s("old").handle(), &mut removed),
+            0
+        );
+        assert_eq!(
+            toml_edit_table_rename_key(dev, s("name").handle(), s("channel").handle()),
+            0
+        );
+        assert_eq!(toml_edit_file_unlock_save(lock, doc), 0);
+        assert_eq!(
+            std::fs::read_to_string(&file.path).unwrap(),
+            "[dev]\nchannel = \"ai0\"\n"
+        );
+
+        // the lock is gone, so the file can be locked again
+        assert_eq!(
+            toml_edit_file_unlock_save(lock, doc),
+            ErrorCode::InvalidReference as i32
+        );
+        assert_eq!(
+            toml_edit_file_lock(file.path_str().handle(), LVBool::from(true), 0, &mut other),
+            0
+        );
+
+        // a shared lock can't be used to save
+        toml_edit_file_unlock(other);
+        assert_eq!(
+            toml_edit_file_lock(file.path_str().handle(), LVBool::from(false), 0, &mut other),
+            0
+        );
+        assert_eq!(
+            toml_edit_file_unlock_save(other, doc),
+            ErrorCode::InvalidValue as i32
+        );
+        assert_eq!(
+            toml_edit_file_lock(file.path_str().handle(), LVBool::from(true), 0, &mut other),
+            0
+        );
+        toml_edit_file_unlock(other);
+
+        // a file that doesn't exist yet starts empty
+        let new_file = TestFile::new("new.toml");
+        assert_eq!(
+            toml_edit_file_lock_open(new_file.path_str().handle(), 0, &mut lock, &mut other),
+            0
+        );
+        assert_eq!(doc_to_string(other), "");
+        assert_eq!(toml_edit_file_unlock_save(lock, doc), 0);
+        assert_eq!(
+            std::fs::read_to_string(&new_file.path).unwrap(),
+            "[dev]\nchannel = \"ai0\"\n"
+        );
+
+        toml_edit_doc_close(other);
+        toml_edit_table_close(dev);
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_file_errors() {
+        let file = TestFile::new("broken.toml");
+        let mut doc = 0;
+
+        assert_eq!(
+            toml_edit_doc_open_file(file.path_str().handle(), &mut doc),
+            ErrorCode::FileNotFound as i32
+        );
+
+        std::fs::write(&file.path, "key = \n").unwrap();
+        assert_eq!(
+            toml_edit_doc_open_file(file.path_str().handle(), &mut doc),
+            ErrorCode::ParseError as i32
+        );
+        let message = last_error().unwrap().message;
+        assert!(message.starts_with(&format!("Could not parse {}", file.path.display())));
+        assert!(message.contains("line 1"), "{}", message);
+        assert_eq!(doc, 0);
+
+        // saving into a directory that doesn't exist
+        let doc = doc_from_str("key = 1");
+        let mut missing =
+            TestString::new(file.dir.join("missing").join("a.toml").to_str().unwrap());
+        assert_eq!(
+            toml_edit_doc_save_file(doc, missing.handle()),
+            ErrorCode::FileNotFound as i32
+        );
+
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_concurrent_calls_are_thread_safe() {
+        const THREADS: usize = 8;
+        const PUSHES: usize = 200;
+
+        // every thread pushes onto the same Array (a view into a shared Document), while also
+        // reading the Document and building Documents of its own
+        let shared = doc_from_str("samples = []\n");
+
+        let threads: Vec<_> = (0..THREADS)
+            .map(|thread| {
+                std::thread::spawn(move || {
+                    let (mut root, mut item, mut value, mut array) = (0, 0, 0, 0);
+                    assert_eq!(toml_edit_doc_get_root_table(shared, &mut root), 0);
+                    assert_eq!(
+                        toml_edit_table_get_item(
+                            root,
+                            TestString::new("samples").handle(),
+                            &mut item
+                        ),
+                        0
+                    );
+                    assert_eq!(toml_edit_item_into_value(item, &mut value), 0);
+                    assert_eq!(toml_edit_value_get_array(value, &mut array), 0);
+
+                    for push in 0..PUSHES {
+                        let sample = new_i64((thread * PUSHES + push) as i64);
+                        assert_eq!(toml_edit_array_push(array, sample), 0);
+                        toml_edit_item_close(sample);
+
+                        // a view of the last element, checked against a whole-document read
+                        let (mut len, mut element) = (0, 0);
+                        assert_eq!(toml_edit_array_len(array, &mut len), 0);
+                        assert_eq!(toml_edit_array_get(array, len - 1, &mut element), 0);
+                        assert_eq!(toml_edit_value_close(element), 0);
+                        assert!(doc_to_string(shared).starts_with("samples = ["));
+
+                        let own =
+                            doc_from_str(&format!("thread = {}\n[t]\nn = {}\n", thread, push));
+                        let mut n = 0;
+                        assert_eq!(
+                            toml_edit_doc_get_by_path(own, TestString::new("t.n").handle(), &mut n),
+                            0
+                        );
+                        assert_eq!(
+                            toml_edit_doc_set_by_path(
+                                own,
+                                TestString::new("t.copy").handle(),
+                                n,
+                                LVBool::from(false)
+                            ),
+                            0
+                        );
+                        assert_eq!(
+                            doc_to_string(own),
+                            format!("thread = {}\n[t]\nn = {}\ncopy = {}\n", thread, push, push)
+                        );
+                        toml_edit_item_close(n);
+                        toml_edit_doc_close(own);
+                    }
+
+                    toml_edit_array_close(array);
+                    toml_edit_value_close(value);
+                    toml_edit_item_close(item);
+                    toml_edit_table_close(root);
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        // no push was lost, and each thread's pushes stayed in order
+        let samples = handle::with(shared, |doc: &mut Document| {
+            Ok(doc["samples"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|sample| sample.as_integer().unwrap() as usize)
+                .collect::<Vec<_>>())
+        })
+        .unwrap();
+        assert_eq!(samples.len(), THREADS * PUSHES);
+        for thread in 0..THREADS {
+            let pushed: Vec<_> = samples
+                .iter()
+                .filter(|sample| *sample / PUSHES == thread)
+                .collect();
+            assert!(pushed.windows(2).all(|pair| pair[0] < pair[1]));
+            assert_eq!(pushed.len(), PUSHES);
+        }
+
+        toml_edit_doc_close(shared);
+    }
+
+    #[test]
+    fn test_closing_a_document_while_another_thread_uses_it() {
+        for _ in 0..50 {
+            let doc = doc_from_str("a = 1\n");
+            let mut root = 0;
+            assert_eq!(toml_edit_doc_get_root_table(doc, &mut root), 0);
+
+            let reader = std::thread::spawn(move || {
+                // each call either finishes on the document or finds it closed
+                for _ in 0..100 {
+                    let mut item = 0;
+                    match toml_edit_table_get_item(root, TestString::new("a").handle(), &mut item) {
+                        0 => assert_eq!(toml_edit_item_close(item), 0),
+                        code => assert_eq!(code, ErrorCode::InvalidReference as i32),
+                    }
+                }
+            });
+            assert_eq!(toml_edit_doc_close(doc), 0);
+            reader.join().unwrap();
+            toml_edit_table_close(root);
+        }
+    }
+
+    #[test]
+    fn test_set_decor() {
+        let doc = doc_from_str("title = \"station\"\n\n[dev]\nrate = 10\n");
+        let (mut dev, mut rate_item, mut rate) = (0, 0, 0);
+        let s = TestString::new;
+
+        assert_eq!(toml_edit_doc_get_table(doc, s("dev").handle(), &mut dev), 0);
+        assert_eq!(
+            toml_edit_table_get_item(dev, s("rate").handle(), &mut rate_item),
+            0
+        );
+        assert_eq!(toml_edit_item_into_value(rate_item, &mut rate), 0);
+
+        assert_eq!(
+            toml_edit_table_set_key_decor(
+                dev,
+                s("rate").handle(),
+                s("# set by calibration on 2024-01-31\n").handle(),
+                s(" ").handle()
+            ),
+            0
+        );
+        assert_eq!(
+            toml_edit_value_set_decor(rate, s(" ").handle(), s("  # Hz").handle()),
+            0
+        );
+        assert_eq!(
+            toml_edit_table_set_decor(dev, s("\n# main device\n").handle(), s(" # ai").handle()),
+            0
+        );
+        let expected = "title = \"station\"\n\n# main device\n[dev] # ai\n# set by calibration on 2024-01-31\nrate = 10  # Hz\n";
+        assert_eq!(doc_to_string(doc), expected);
+
+        // and they read back the same after parsing
+        let parsed = Document::from_str(expected).unwrap();
+        let parsed_dev = parsed["dev"].as_table().unwrap();
+        assert_eq!(
+            decor::get(parsed_dev.key_decor("rate").unwrap()),
+            (
+                "# set by calibration on 2024-01-31\n".to_string(),
+                " ".to_string()
+            )
+        );
+        assert_eq!(
+            decor::get(parsed_dev["rate"].as_value().unwrap().decor()),
+            (" ".to_string(), "  # Hz".to_string())
+        );
+        assert_eq!(
+            decor::get(parsed_dev.decor()),
+            ("\n# main device\n".to_string(), " # ai".to_string())
+        );
+
+        // a comment that isn't ended would swallow the key after it, and one (or a line ending)
+        // between the key and the `=` would leave the key without its value
+        let parses = |doc| Document::from_str(&doc_to_string(doc)).is_ok();
+        for (prefix, suffix) in [
+            ("# note", ""),
+            ("note\n", ""),
+            ("", "\0"),
+            ("", " # c\n"),
+            ("", "\n"),
+        ] {
+            assert_eq!(
+                toml_edit_table_set_key_decor(
+                    dev,
+                    s("rate").handle(),
+                    s(prefix).handle(),
+                    s(suffix).handle()
+                ),
+                ErrorCode::InvalidValue as i32
+            );
+            assert!(parses(doc));
+        }
+        for (prefix, suffix) in [("\n", ""), (" # c\n", ""), ("", " # c\n# d")] {
+            assert_eq!(
+                toml_edit_value_set_decor(rate, s(prefix).handle(), s(suffix).handle()),
+                ErrorCode::InvalidValue as i32
+            );
+            assert!(parses(doc));
+        }
+        assert_eq!(
+            toml_edit_table_set_decor(dev, s("").handle(), s(" # ai\nrate = 1").handle()),
+            ErrorCode::InvalidValue as i32
+        );
+        assert!(parses(doc));
+        assert_eq!(
+            toml_edit_table_set_key_decor(
+                dev,
+                s("missing").handle(),
+                s("").handle(),
+                s("").handle()
+            ),
+            ErrorCode::NotFound as i32
+        );
+        assert_eq!(doc_to_string(doc), expected);
+
+        toml_edit_value_close(rate);
+        toml_edit_item_close(rate_item);
+        toml_edit_table_close(dev);
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_set_decor_in_array_and_inline_table() {
+        let doc = doc_from_str("limits = [1, 2]\npoint = {x = 1, y = 2}\n");
+        let (mut item, mut limits, mut first) = (0, 0, 0);
+        let (mut point_item, mut point_value, mut point, mut x) = (0, 0, 0, 0);
+        let s = TestString::new;
+        let parses = |doc| Document::from_str(&doc_to_string(doc)).is_ok();
+
+        let mut root = 0;
+        assert_eq!(toml_edit_doc_get_root_table(doc, &mut root), 0);
+        assert_eq!(
+            toml_edit_table_get_item(root, s("limits").handle(), &mut item),
+            0
+        );
+        assert_eq!(toml_edit_item_into_value(item, &mut limits), 0);
+        let mut array = 0;
+        assert_eq!(toml_edit_value_get_array(limits, &mut array), 0);
+        assert_eq!(toml_edit_array_get(array, 0, &mut first), 0);
+
+        // an array can be spread over lines, but a comment would swallow the `,` after it
+        assert_eq!(
+            toml_edit_value_set_decor(first, s("\n  ").handle(), s("").handle()),
+            0
+        );
+        assert!(parses(doc));
+        for (prefix, suffix) in [("", " # c"), ("", " # c\n"), ("# c\n", "")] {
+            assert_eq!(
+                toml_edit_value_set_decor(first, s(prefix).handle(), s(suffix).handle()),
+                ErrorCode::InvalidValue as i32
+            );
+            assert!(parses(doc));
+        }
+
+        // an inline table must stay on one line
+        assert_eq!(
+            toml_edit_table_get_item(root, s("point").handle(), &mut point_item),
+            0
+        );
+        assert_eq!(toml_edit_item_into_value(point_item, &mut point_value), 0);
+        assert_eq!(toml_edit_value_get_inline_table(point_value, &mut point), 0);
+        assert_eq!(
+            toml_edit_inline_table_get_item(point, s("x").handle(), &mut x),
+            0
+        );
+        for (prefix, suffix) in [("", " # c"), ("", "\n"), ("\n", "")] {
+            assert_eq!(
+                toml_edit_value_set_decor(x, s(prefix).handle(), s(suffix).handle()),
+                ErrorCode::InvalidValue as i32
+            );
+            assert!(parses(doc));
+        }
+
+        // a value with a comment after it loses it on going into an inline table
+        let (z, mut z_value) = (new_i64(3), 0);
+        assert_eq!(toml_edit_item_into_value(z, &mut z_value), 0);
+        assert_eq!(
+            toml_edit_value_set_decor(z_value, s(" ").handle(), s(" # c").handle()),
+            0
+        );
+        assert_eq!(
+            toml_edit_inline_table_set_item(point, s("z").handle(), z),
+            0
+        );
+        assert!(parses(doc));
+        assert_eq!(
+            doc_to_string(doc),
+            "limits = [\n  1, 2]\npoint = {x = 1, y = 2, z = 3 }\n"
+        );
+
+        toml_edit_value_close(z_value);
+        toml_edit_item_close(z);
+        toml_edit_value_close(x);
+        toml_edit_inline_table_close(point);
+        toml_edit_value_close(point_value);
+        toml_edit_item_close(point_item);
+        toml_edit_value_close(first);
+        toml_edit_array_close(array);
+        toml_edit_value_close(limits);
+        toml_edit_item_close(item);
+        toml_edit_table_close(root);
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_set_decor_of_table_without_header() {
+        let toml = "a.b = 1\n\n[c]\n\n[d.e]\nf = 1\n";
+        let doc = doc_from_str(toml);
+        let s = TestString::new;
+        let set = |table| {
+            toml_edit_table_set_decor(
+                table,
+                s("# set by calibration\n").handle(),
+                s(" # ai").handle(),
+            )
+        };
+
+        // the root table, a dotted table and an implicit one have no header to decorate
+        let mut root = 0;
+        assert_eq!(toml_edit_doc_get_root_table(doc, &mut root), 0);
+        assert_eq!(set(root), ErrorCode::InvalidValue as i32);
+        let mut tables = vec![root];
+        for key in ["a", "d"] {
+            let mut table = 0;
+            assert_eq!(toml_edit_doc_get_table(doc, s(key).handle(), &mut table), 0);
+            assert_eq!(set(table), ErrorCode::InvalidValue as i32, "{}", key);
+            tables.push(table);
+        }
+        assert_eq!(doc_to_string(doc), toml);
+
+        // but an empty table that isn't implicit does, as does one not in a document yet
+        let mut c = 0;
+        assert_eq!(toml_edit_doc_get_table(doc, s("c").handle(), &mut c), 0);
+        assert_eq!(set(c), 0);
+        assert_eq!(
+            doc_to_string(doc),
+            "a.b = 1\n# set by calibration\n[c] # ai\n\n[d.e]\nf = 1\n"
+        );
+        let detached = new_table("x = 1");
+        assert_eq!(set(detached), 0);
+
+        for table in tables.into_iter().chain([c, detached]) {
+            toml_edit_table_close(table);
+        }
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_set_inline_table_key_decor() {
+        let doc = doc_from_str("point = {x = 1, y = 2}\n");
+        let (mut item, mut value, mut point) = (0, 0, 0);
+        let s = TestString::new;
+
+        assert_eq!(
+            toml_edit_doc_get_by_path(doc, s("point").handle(), &mut item),
+            0
+        );
+        assert_eq!(toml_edit_item_into_value(item, &mut value), 0);
+        assert_eq!(toml_edit_value_get_inline_table(value, &mut point), 0);
+
+        assert_eq!(
+            toml_edit_inline_table_set_key_decor(
+                point,
+                s("y").handle(),
+                s("   ").handle(),
+                s("").handle()
+            ),
+            0
+        );
+        assert_eq!(
+            toml_edit_inline_table_set_key_decor(
+                point,
+                s("y").handle(),
+                s("# c\n").handle(),
+                s("").handle()
+            ),
+            ErrorCode::InvalidValue as i32
+        );
+        assert_eq!(
+            handle::with(item, |item: &mut Item| Ok(item.to_string())),
+            Ok(" {x = 1,   y= 2}".to_string())
+        );
+
+        toml_edit_inline_table_close(point);
+        toml_edit_value_close(value);
+        toml_edit_item_close(item);
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_rename_key_keeps_place_and_comments() {
+        let doc = doc_from_str(
+            "[dev]\n# sample rate\nrate = 10  # Hz\nname = \"dev1\"\n\n[dev.limits]\nmax = 5\n",
+        );
+        let mut dev = 0;
+        let s = TestString::new;
+
+        assert_eq!(toml_edit_doc_get_table(doc, s("dev").handle(), &mut dev), 0);
+        assert_eq!(
+            toml_edit_table_rename_key(dev, s("rate").handle(), s("sample rate").handle()),
+            0
+        );
+        assert_eq!(
+            toml_edit_doc_rename_by_path(doc, s("dev.limits").handle(), s("range").handle()),
+            0
+        );
+        assert_eq!(
+            doc_to_string(doc),
+            "[dev]\n# sample rate\n\"sample rate\" = 10  # Hz\nname = \"dev1\"\n\n[dev.range]\nmax = 5\n"
+        );
+
+        // a key that is taken, or missing, leaves the table as it was
+        assert_eq!(
+            toml_edit_table_rename_key(dev, s("name").handle(), s("range").handle()),
+            ErrorCode::KeyExists as i32
+        );
+        assert_eq!(
+            toml_edit_table_rename_key(dev, s("missing").handle(), s("other").handle()),
+            ErrorCode::NotFound as i32
+        );
+        assert_eq!(
+            toml_edit_doc_rename_by_path(doc, s("dev.missing").handle(), s("other").handle()),
+            ErrorCode::NotFound as i32
+        );
+        assert_eq!(last_error().unwrap().message, "Key not found: dev.missing");
+        assert_eq!(
+            toml_edit_doc_rename_by_path(doc, s("dev[0]").handle(), s("other").handle()),
+            ErrorCode::WrongType as i32
+        );
+
+        toml_edit_table_close(dev);
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_rename_inline_table_key() {
+        let doc = doc_from_str("point = { x = 1, y = 2, z = 3 }\n");
+        let (mut item, mut value, mut point) = (0, 0, 0);
+        let s = TestString::new;
+
+        assert_eq!(
+            toml_edit_doc_rename_by_path(doc, s("point.y").handle(), s("height").handle()),
+            0
+        );
+        assert_eq!(doc_to_string(doc), "point = { x = 1, height = 2, z = 3 }\n");
+
+        assert_eq!(
+            toml_edit_doc_get_by_path(doc, s("point").handle(), &mut item),
+            0
+        );
+        assert_eq!(toml_edit_item_into_value(item, &mut value), 0);
+        assert_eq!(toml_edit_value_get_inline_table(value, &mut point), 0);
+        assert_eq!(
+            toml_edit_inline_table_rename_key(point, s("x").handle(), s("z").handle()),
+            ErrorCode::KeyExists as i32
+        );
+        assert_eq!(
+            toml_edit_inline_table_rename_key(point, s("x").handle(), s("width").handle()),
+            0
+        );
+        assert_eq!(
+            handle::with(item, |item: &mut Item| Ok(item.to_string())),
+            Ok(" { width = 1, height = 2, z = 3 }".to_string())
+        );
+
+        toml_edit_inline_table_close(point);
+        toml_edit_value_close(value);
+        toml_edit_item_close(item);
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_insert_item_next_to_a_key() {
+        let doc = doc_from_str("[dev]\n# sample rate\nrate = 10\nname = \"dev1\"\n");
+        let mut dev = 0;
+        let mut index = -1;
+        let s = TestString::new;
+
+        assert_eq!(toml_edit_doc_get_table(doc, s("dev").handle(), &mut dev), 0);
+        assert_eq!(
+            toml_edit_table_insert_item_after(
+                dev,
+                s("rate").handle(),
+                s("min_rate").handle(),
+                new_i64(1)
+            ),
+            0
+        );
+        assert_eq!(
+            toml_edit_table_insert_item_before(
+                dev,
+                s("rate").handle(),
+                s("channels").handle(),
+                new_i64(4)
+            ),
+            0
+        );
+        assert_eq!(
+            toml_edit_table_insert_item(dev, 4, s("slot").handle(), new_i64(2)),
+            0
+        );
+        assert_eq!(
+            doc_to_string(doc),
+            "[dev]\nchannels = 4\n# sample rate\nrate = 10\nmin_rate = 1\nname = \"dev1\"\nslot = 2\n"
+        );
+        assert_eq!(
+            toml_edit_table_get_item_index(dev, s("name").handle(), &mut index),
+            0
+        );
+        assert_eq!(index, 3);
+
+        assert_eq!(
+            toml_edit_table_insert_item(dev, 0, s("rate").handle(), new_i64(2)),
+            ErrorCode::KeyExists as i32
+        );
+        assert_eq!(
+            toml_edit_table_insert_item(dev, 6, s("other").handle(), new_i64(2)),
+            ErrorCode::NotFound as i32
+        );
+        assert_eq!(
+            toml_edit_table_insert_item_after(
+                dev,
+                s("missing").handle(),
+                s("other").handle(),
+                new_i64(2)
+            ),
+            ErrorCode::NotFound as i32
+        );
+        assert_eq!(
+            toml_edit_table_get_item_index(dev, s("missing").handle(), &mut index),
+            ErrorCode::NotFound as i32
+        );
+
+        toml_edit_table_close(dev);
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_insert_table_between_tables() {
+        let doc =
+            doc_from_str("title = \"station\"\n\n[a]\nx = 1\n\n[a.sub]\nw = 0\n\n[c]\nz = 3\n");
+        let (mut root, mut b, mut first) = (0, 0, 0);
+        let s = TestString::new;
+
+        assert_eq!(toml_edit_doc_get_root_table(doc, &mut root), 0);
+        let new_table = |key: &str, value: i64| {
+            let mut table = Table::new();
+            table.insert(key, toml_edit::value(value));
+            handle::insert(table).unwrap()
+        };
+
+        assert_eq!(toml_edit_table_to_item(new_table("y", 2), &mut b), 0);
+        assert_eq!(
+            toml_edit_table_insert_item_after(root, s("a").handle(), s("b").handle(), b),
+            0
+        );
+        assert_eq!(toml_edit_table_to_item(new_table("v", 0), &mut first), 0);
+        assert_eq!(
+            toml_edit_table_insert_item(root, 1, s("first").handle(), first),
+            0
+        );
+        assert_eq!(
+            doc_to_string(doc),
+            "title = \"station\"\n\n[first]\nv = 0\n\n[a]\nx = 1\n\n[a.sub]\nw = 0\n\n[b]\ny = 2\n\n[c]\nz = 3\n"
+        );
+
+        toml_edit_table_close(root);
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_insert_inline_table_item() {
+        let doc = doc_from_str("point = {x = 1, z = 3}\n");
+        let (mut item, mut value, mut point) = (0, 0, 0);
+        let mut index = -1;
+        let s = TestString::new;
+
+        assert_eq!(
+            toml_edit_doc_get_by_path(doc, s("point").handle(), &mut item),
+            0
+        );
+        assert_eq!(toml_edit_item_into_value(item, &mut value), 0);
+        assert_eq!(toml_edit_value_get_inline_table(value, &mut point), 0);
+
+        assert_eq!(
+            toml_edit_inline_table_insert_item_before(
+                point,
+                s("z").handle(),
+                s("y").handle(),
+                new_i64(2)
+            ),
+            0
+        );
+        assert_eq!(
+            toml_edit_inline_table_insert_item_after(
+                point,
+                s("z").handle(),
+                s("w").handle(),
+                new_i64(4)
+            ),
+            0
+        );
+        assert_eq!(
+            toml_edit_inline_table_insert_item(point, 0, s("id").handle(), new_i64(0)),
+            0
+        );
+        assert_eq!(
+            toml_edit_inline_table_get_item_index(point, s("z").handle(), &mut index),
+            0
+        );
+        assert_eq!(index, 3);
+        assert_eq!(
+            toml_edit_inline_table_insert_item(point, -1, s("v").handle(), new_i64(0)),
+            ErrorCode::NotFound as i32
+        );
+        assert_eq!(
+            handle::with(item, |item: &mut Item| Ok(item.to_string())),
+            Ok(" {id = 0, x = 1, y = 2, z = 3, w = 4}".to_string())
+        );
+
+        toml_edit_inline_table_close(point);
+        toml_edit_value_close(value);
+        toml_edit_item_close(item);
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_sort_items_and_tables() {
+        let doc = doc_from_str(
+            r#"[vipm]
+
+# packages
+[vipm.dependencies]
+oglib_string = "3.1.4"
+# arrays
+oglib_array = { version = "6.5.4", url = "https://jki.net"}
+
+[nipm]
+version = "1.2.3"
+"#,
+        );
+        let mut root = 0;
+
+        assert_eq!(toml_edit_doc_get_root_table(doc, &mut root), 0);
+        assert_eq!(toml_edit_table_sort_items(root, LVBool::from(true)), 0);
+        // the keys are sorted, but the sections are written in their old order
+        assert_eq!(
+            doc_to_string(doc),
+            r#"[vipm]
+
+# packages
+[vipm.dependencies]
+# arrays
+oglib_array = { url = "https://jki.net", version = "6.5.4"}
+oglib_string = "3.1.4"
+
+[nipm]
+version = "1.2.3"
+"#
+        );
+
+        assert_eq!(toml_edit_doc_sort_tables(doc), 0);
+        assert_eq!(
+            doc_to_string(doc),
+            r#"[nipm]
+version = "1.2.3"
+
+[vipm]
+
+# packages
+[vipm.dependencies]
+# arrays
+oglib_array = { url = "https://jki.net", version = "6.5.4"}
+oglib_string = "3.1.4"
+"#
+        );
+
+        toml_edit_table_close(root);
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_sort_items_keeps_file_header_comment() {
+        let doc = doc_from_str("# top\nb = 2\na = 1\n\n[c]\ny = 2\nx = 1\n");
+        let mut root = 0;
+
+        assert_eq!(toml_edit_doc_get_root_table(doc, &mut root), 0);
+        assert_eq!(toml_edit_table_sort_items(root, LVBool::from(true)), 0);
+        // the file's comment stays at the top, rather than moving down with `b`
+        assert_eq!(
+            doc_to_string(doc),
+            "# top\na = 1\nb = 2\n\n[c]\nx = 1\ny = 2\n"
+        );
+        toml_edit_table_close(root);
+        toml_edit_doc_close(doc);
+
+        // a comment on the first key itself, below a blank line, stays with that key
+        let doc = doc_from_str("# top\n\n# about b\nb = 2\na = 1\n");
+        assert_eq!(toml_edit_doc_get_root_table(doc, &mut root), 0);
+        assert_eq!(toml_edit_table_sort_items(root, LVBool::from(false)), 0);
+        assert_eq!(doc_to_string(doc), "# top\n\na = 1\n# about b\nb = 2\n");
+        toml_edit_table_close(root);
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_sort_items_by_order() {
+        let doc = doc_from_str(
+            "[package]\nlicense = \"MIT\"\nname = \"x\"\ndeps = {version = \"1\", name = \"y\", url = \"z\"}\nversion = \"0.1\"\n",
+        );
+        let (mut package, mut item, mut value, mut deps) = (0, 0, 0, 0);
+        let (mut name, mut version) = (TestString::new("name"), TestString::new("version"));
+        let mut order = TestArray::new(vec![name.handle(), version.handle()]);
+        let s = TestString::new;
+
+        assert_eq!(
+            toml_edit_doc_get_table(doc, s("package").handle(), &mut package),
+            0
+        );
+        assert_eq!(
+            toml_edit_table_sort_items_by_order(package, order.handle(), LVBool::from(false)),
+            0
+        );
+        assert_eq!(
+            doc_to_string(doc),
+            "[package]\nname = \"x\"\nversion = \"0.1\"\nlicense = \"MIT\"\ndeps = {version = \"1\", name = \"y\", url = \"z\"}\n"
+        );
+
+        assert_eq!(
+            toml_edit_table_get_item(package, s("deps").handle(), &mut item),
+            0
+        );
+        assert_eq!(toml_edit_item_into_value(item, &mut value), 0);
+        assert_eq!(toml_edit_value_get_inline_table(value, &mut deps), 0);
+        assert_eq!(
+            toml_edit_inline_table_sort_items_by_order(deps, order.handle(), LVBool::from(true)),
+            0
+        );
+        assert!(
+            doc_to_string(doc).ends_with("deps = {name = \"y\", version = \"1\", url = \"z\"}\n")
+        );
+        assert_eq!(
+            toml_edit_inline_table_sort_items(deps, LVBool::from(false)),
+            0
+        );
+        assert!(
+            doc_to_string(doc).ends_with("deps = {name = \"y\", url = \"z\", version = \"1\"}\n")
+        );
+
+        toml_edit_inline_table_close(deps);
+        toml_edit_value_close(value);
+        toml_edit_item_close(item);
+        toml_edit_table_close(package);
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_table_position() {
+        let doc = doc_from_str("[a]\nx = 1\n\n[b]\ny = 2\n");
+        let (mut a, mut b) = (0, 0);
+        let mut position = -1;
+        let s = TestString::new;
+
+        assert_eq!(toml_edit_doc_get_table(doc, s("a").handle(), &mut a), 0);
+        assert_eq!(toml_edit_doc_get_table(doc, s("b").handle(), &mut b), 0);
+        assert_eq!(toml_edit_table_get_position(b, &mut position), 0);
+        assert_eq!(toml_edit_table_set_position(a, position + 1), 0);
+        assert_eq!(doc_to_string(doc), "\n[b]\ny = 2\n[a]\nx = 1\n");
+        assert_eq!(
+            toml_edit_table_set_position(a, -1),
+            ErrorCode::InvalidValue as i32
+        );
+
+        let mut detached = 0;
+        assert_eq!(toml_edit_table_new(&mut detached), 0);
+        assert_eq!(toml_edit_table_get_position(detached, &mut position), 0);
+        assert_eq!(position, -1);
+
+        toml_edit_table_close(detached);
+        toml_edit_table_close(b);
+        toml_edit_table_close(a);
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_convert_table_to_inline_and_back() {
+        let source = "title = \"station\"\n\n# main device\n[dev] # ai\nrate = 10\n\n[dev.limits]\nmax = 5\n\n[other]\nx = 1\n";
+        let doc = doc_from_str(source);
+        let mut root = 0;
+        let s = TestString::new;
+
+        assert_eq!(toml_edit_doc_get_root_table(doc, &mut root), 0);
+        assert_eq!(
+            toml_edit_table_convert_item_to_inline(root, s("dev").handle()),
+            0
+        );
+        assert_eq!(
+            doc_to_string(doc),
+            "title = \"station\"\n\n# main device\ndev = { rate = 10, limits = { max = 5 } } # ai\n\n[other]\nx = 1\n"
+        );
+
+        assert_eq!(
+            toml_edit_table_convert_item_to_table(root, s("dev").handle(), LVBool::from(true)),
+            0
+        );
+        assert_eq!(
+            doc_to_string(doc),
+            "title = \"station\"\n\n# main device\n[dev] # ai\nrate = 10\n\n[dev.limits]\nmax = 5\n\n[other]\nx = 1\n"
+        );
+
+        assert_eq!(
+            toml_edit_table_convert_item_to_table(root, s("title").handle(), LVBool::from(true)),
+            ErrorCode::WrongType as i32
+        );
+        assert_eq!(
+            toml_edit_table_convert_item_to_inline(root, s("title").handle()),
+            ErrorCode::WrongType as i32
+        );
+        assert_eq!(
+            toml_edit_table_convert_item_to_inline(root, s("missing").handle()),
+            ErrorCode::NotFound as i32
+        );
+
+        toml_edit_table_close(root);
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_convert_array_of_inline_tables() {
+        let doc =
+            doc_from_str("steps = [{ name = \"a\", limits = { max = 1 } }, { name = \"b\" }]\n");
+        let mut root = 0;
+        let s = TestString::new;
+
+        assert_eq!(toml_edit_doc_get_root_table(doc, &mut root), 0);
+        assert_eq!(
+            toml_edit_table_convert_item_to_table(root, s("steps").handle(), LVBool::from(false)),
+            0
+        );
+        assert_eq!(
+            doc_to_string(doc),
+            "[[steps]]\nname = \"a\"\nlimits = { max = 1 }\n\n[[steps]]\nname = \"b\"\n"
+        );
+        assert_eq!(
+            toml_edit_table_convert_item_to_inline(root, s("steps").handle()),
+            0
+        );
+        assert_eq!(
+            doc_to_string(doc),
+            "steps = [{ name = \"a\", limits = { max = 1 } }, { name = \"b\" }]\n"
+        );
+
+        toml_edit_table_close(root);
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_set_item_dotted() {
+        let doc = doc_from_str("[tool]\nname = \"x\"\n\n# package settings\n[tool.package]\nversion = \"1\"\n\n[tool.package.deps]\na = 1\n");
+        let (mut tool, mut package) = (0, 0);
+        let mut flag = LVBool::from(false);
+        let s = TestString::new;
+
+        assert_eq!(
+            toml_edit_doc_get_table(doc, s("tool").handle(), &mut tool),
+            0
+        );
+
+        // `package.version` and `package.deps.a` would both be written below the comment, so
+        // it has nowhere to go
+        let before = doc_to_string(doc);
+        assert_eq!(
+            toml_edit_table_set_item_dotted(
+                tool,
+                s("package").handle(),
+                LVBool::from(true),
+                LVBool::from(true)
+            ),
+            ErrorCode::InvalidValue as i32
+        );
+        assert_eq!(doc_to_string(doc), before);
+
+        assert_eq!(
+            toml_edit_table_get_item(tool, s("package").handle(), &mut package),
+            0
+        );
+        let mut package_table = 0;
+        assert_eq!(toml_edit_item_into_table(package, &mut package_table), 0);
+        assert_eq!(
+            toml_edit_table_set_decor(package_table, s("\n").handle(), s("").handle()),
+            0
+        );
+        assert_eq!(
+            toml_edit_table_set_item_dotted(
+                tool,
+                s("package").handle(),
+                LVBool::from(true),
+                LVBool::from(true)
+            ),
+            0
+        );
+        assert_eq!(
+            doc_to_string(doc),
+            "[tool]\nname = \"x\"\npackage.version = \"1\"\npackage.deps.a = 1\n"
+        );
+        assert_eq!(toml_edit_table_is_dotted(package_table, &mut flag), 0);
+        assert!(bool::from(flag));
+
+        assert_eq!(
+            toml_edit_table_set_item_dotted(
+                tool,
+                s("package").handle(),
+                LVBool::from(false),
+                LVBool::from(false)
+            ),
+            0
+        );
+        assert_eq!(
+            doc_to_string(doc),
+            "[tool]\nname = \"x\"\n\n[tool.package]\nversion = \"1\"\ndeps.a = 1\n"
+        );
+        assert_eq!(
+            toml_edit_table_set_item_dotted(
+                tool,
+                s("name").handle(),
+                LVBool::from(true),
+                LVBool::from(false)
+            ),
+            ErrorCode::WrongType as i32
+        );
+
+        toml_edit_table_close(package_table);
+        toml_edit_item_close(package);
+        toml_edit_table_close(tool);
+        toml_edit_doc_close(doc);
+
+        // a dotted table that is written on one line takes its header's comments, and those of
+        // its key, which can't go in the middle of `b.x`
+        let doc = doc_from_str("[a]\ny = 0\n\n# only x\n[a.b] # on b\n# about x\nx = 1\n");
+        let mut a = 0;
+        assert_eq!(toml_edit_doc_get_table(doc, s("a").handle(), &mut a), 0);
+        assert_eq!(
+            toml_edit_table_set_item_dotted(
+                a,
+                s("b").handle(),
+                LVBool::from(true),
+                LVBool::from(false)
+            ),
+            0
+        );
+        assert_eq!(
+            doc_to_string(doc),
+            "[a]\ny = 0\n\n# only x\n# about x\nb.x = 1 # on b\n"
+        );
+        assert!(Document::from_str(&doc_to_string(doc)).is_ok());
+
+        toml_edit_table_close(a);
+        toml_edit_doc_close(doc);
+
+        // a table written on more lines than one can't keep them, so it is left as it was
+        let doc = doc_from_str("# before a\n[a] # on a\nb = 1\n[a.c]\nd = 2\n");
+        let mut root = 0;
+        assert_eq!(toml_edit_doc_get_root_table(doc, &mut root), 0);
+        assert_eq!(
+            toml_edit_table_set_item_dotted(
+                root,
+                s("a").handle(),
+                LVBool::from(true),
+                LVBool::from(true)
+            ),
+            ErrorCode::InvalidValue as i32
+        );
+        assert_eq!(
+            doc_to_string(doc),
+            "# before a\n[a] # on a\nb = 1\n[a.c]\nd = 2\n"
+        );
+
+        // but without `[a.c]` it is a single line again
+        assert_eq!(
+            toml_edit_table_set_item_dotted(
+                root,
+                s("a").handle(),
+                LVBool::from(true),
+                LVBool::from(false)
+            ),
+            0
+        );
+        assert_eq!(
+            doc_to_string(doc),
+            "# before a\na.b = 1 # on a\n[a.c]\nd = 2\n"
+        );
+
+        toml_edit_table_close(root);
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_set_implicit_and_copy_conversions() {
+        let doc = doc_from_str("[a]\n\n[a.b]\nx = 1\n");
+        let (mut a, mut inline_table, mut table) = (0, 0, 0);
+        let mut flag = LVBool::from(false);
+        let s = TestString::new;
+
+        assert_eq!(toml_edit_doc_get_table(doc, s("a").handle(), &mut a), 0);
+        assert_eq!(toml_edit_table_is_implicit(a, &mut flag), 0);
+        assert!(!bool::from(flag));
+        assert_eq!(
+            toml_edit_table_set_implicit(a, LVBool::from(true), LVBool::from(false)),
+            0
+        );
+        assert_eq!(doc_to_string(doc), "\n[a.b]\nx = 1\n");
+
+        assert_eq!(toml_edit_table_to_inline_table(a, &mut inline_table), 0);
+        assert_eq!(
+            handle::with(inline_table, |inline_table: &mut InlineTable| Ok(
+                inline_table.to_string()
+            )),
+            Ok("{ b = { x = 1 } }".to_string())
+        );
+        assert_eq!(
+            toml_edit_inline_table_to_table(inline_table, LVBool::from(true), &mut table),
+            0
+        );
+        assert_eq!(
+            handle::with(table, |table: &mut Table| Ok(table
+                .get("b")
+                .is_some_and(Item::is_table))),
+            Ok(true)
+        );
+
+        toml_edit_table_close(table);
+        toml_edit_inline_table_close(inline_table);
+        toml_edit_table_close(a);
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_doc_to_json() {
+        let toml = concat!(
+            "title = \"x\"\n",
+            "when = 1979-05-27T07:32:00-07:00\n",
+            "big = 9007199254740992\n",
+            "safe = -9007199254740991\n",
+            "floats = [1.5, nan, inf, -inf]\n",
+            "point = { y = 2, x = 1 }\n",
+            "\n",
+            "[[steps]]\n",
+            "on = true\n",
+        );
+        let doc = Document::from_str(toml).unwrap();
+
+        assert_eq!(
+            json::to_json(&doc, false),
+            concat!(
+                r#"{"title":"x","when":"1979-05-27T07:32:00-07:00","#,
+                r#""big":"9007199254740992","safe":-9007199254740991,"#,
+                r#""floats":[1.5,"nan","inf","-inf"],"point":{"y":2,"x":1},"#,
+                r#""steps":[{"on":true}]}"#,
+            )
+        );
+        assert_eq!(
+            json::to_json(&Document::from_str("a = [1]").unwrap(), true),
+            "{\n  \"a\": [\n    1\n  ]\n}"
+        );
+    }
+
+    #[test]
+    fn test_doc_from_json() {
+        let mut doc = 0;
+        let mut json = TestString::new(
+            r#"{"name": "x", "n": 3, "f": 0.5, "big": 18446744073709551615,
+                "list": [1, {"a": true}], "server": {"db": {"port": 80}, "empty": {}},
+                "steps": [{"id": 1}, {"id": 2}]}"#,
+        );
+
+        assert_eq!(toml_edit_doc_from_json(json.handle(), &mut doc), 0);
+        assert_eq!(
+            doc_to_string(doc),
+            concat!(
+                "name = \"x\"\n",
+                "n = 3\n",
+                "f = 0.5\n",
+                "big = 18446744073709552000.0\n",
+                "list = [1, { a = true }]\n",
+                "\n",
+                "[server.db]\n",
+                "port = 80\n",
+                "\n",
+                "[server.empty]\n",
+                "\n",
+                "[[steps]]\n",
+                "id = 1\n",
+                "\n",
+                "[[steps]]\n",
+                "id = 2\n",
+            )
+        );
+        toml_edit_doc_close(doc);
+
+        let mut json = TestString::new(r#"{"a": {"b": [1, null]}}"#);
+        assert_eq!(
+            toml_edit_doc_from_json(json.handle(), &mut doc),
+            ErrorCode::InvalidValue as i32
+        );
+        assert_eq!(
+            last_error().unwrap().message,
+            "JSON null has no TOML equivalent: a.b[1]"
+        );
+
+        let mut json = TestString::new("[1, 2]");
+        assert_eq!(
+            toml_edit_doc_from_json(json.handle(), &mut doc),
+            ErrorCode::WrongType as i32
+        );
+        let mut json = TestString::new("{\"a\": ");
+        assert_eq!(
+            toml_edit_doc_from_json(json.handle(), &mut doc),
+            ErrorCode::ParseError as i32
+        );
+    }
+
+    #[test]
+    fn test_doc_from_ini() {
+        let ini = concat!(
+            "; station settings\r\n",
+            "[Station]\r\n",
+            "name = \"Bench\\s1\\09\" ; shown in the title\r\n",
+            "enabled = TRUE\r\n",
+            "\r\n",
+            "; in seconds\r\n",
+            "timeout = 2.500000\r\n",
+            "retries = 3\r\n",
+            "serial = 007\r\n",
+            "path = C:\\data;old\r\n",
+            "retries = 4\r\n",
+            "\r\n",
+            "[Limits.Upper]\r\n",
+            "max = NaN\r\n",
+            "pi = 3.14159265358979323\r\n",
+            "oops\r\n",
+        );
+        let (doc, lossy) = ini::from_ini(ini);
+
+        assert_eq!(
+            doc.to_string(),
+            concat!(
+                "# station settings\n",
+                "[Station]\n",
+                "name = \"Bench 1\\t\" # shown in the title\n",
+                "enabled = true\n",
+                "\n",
+                "# in seconds\n",
+                "timeout = 2.5\n",
+                "retries = 3\n",
+                "serial = \"007\"\n",
+                "path = 'C:\\data;old'\n",
+                "\n",
+                "[\"Limits.Upper\"]\n",
+                "max = nan\n",
+                "pi = 3.141592653589793\n",
+            )
+        );
+        assert_eq!(
+            lossy,
+            vec![
+                "Station.retries: duplicate key, kept the first value and dropped `4`",
+                "\"Limits.Upper\".pi: 3.14159265358979323 was rounded to 3.141592653589793",
+                "line 16: not a section, key or comment, dropped `oops`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_doc_to_ini() {
+        let toml = concat!(
+            "# written by the station\n",
+            "version = 2\n",
+            "\n",
+            "[station]\n",
+            "name = \"Bench \\\"1\\\"\" # shown in the title\n",
+            "# in seconds\n",
+            "timeout = 2.5 # at least 1\n",
+            "enabled = true\n",
+            "limits.upper = 5.0\n",
+            "point = { x = 1, y = [nan, inf] }\n",
+            "when = 1979-05-27\n",
+            "none = []\n",
+            "\"a=b\" = 1\n",
+            "\n",
+            "[station.db] # the results database\n",
+            "port = 80\n",
+            "\n",
+            "[[steps]]\n",
+            "id = 1\n",
+            "\n",
+            "[[steps]]\n",
+            "id = 2\n",
+            "# the end\n",
+        );
+        let (ini, lossy) = ini::to_ini(&Document::from_str(toml).unwrap());
+
+        assert_eq!(
+            ini,
+            concat!(
+                "; written by the station\n",
+                "version = 2\n",
+                "\n",
+                "[station]\n",
+                "name = \"Bench \\221\\22\" ; shown in the title\n",
+                "; in seconds\n",
+                "; at least 1\n",
+                "timeout = 2.5\n",
+                "enabled = TRUE\n",
+                "limits.upper = 5.0\n",
+                "point.x = 1\n",
+                "point.y.0 = NaN\n",
+                "point.y.1 = Inf\n",
+                "when = \"1979-05-27\"\n",
+                "\n",
+                "[station.db] ; the results database\n",
+                "port = 80\n",
+                "\n",
+                "[steps.0]\n",
+                "id = 1\n",
+                "\n",
+                "[steps.1]\n",
+                "id = 2\n",
+                "; the end\n",
+            )
+        );
+        assert_eq!(
+            lossy,
+            vec![
+                "version: it was written before the first section, where the Config File VIs \
+                 can't read it",
+                "station.none: an empty array, it was left out",
+                "station.\"a=b\": the key can't be written in INI, it was left out",
+            ]
+        );
+
+        // what is written reads back in
+        let (doc, lossy) = ini::from_ini(&ini);
+        assert_eq!(doc["station"]["name"].as_str(), Some("Bench \"1\""));
+        assert_eq!(doc["station"]["point.y.1"].as_float(), Some(f64::INFINITY));
+        assert_eq!(doc["version"].as_integer(), Some(2));
+        assert_eq!(lossy, Vec::<String>::new());
+    }
+
+    // a flattened type descriptor: its length, flags and type code, what the type needs, and its
+    // label (padded to an even length)
+    fn type_descriptor(code: u8, content: &[u8], label: &str) -> Vec<u8> {
+        let mut descriptor = vec![0, 0, 0x40, code];
+        descriptor.extend_from_slice(content);
+        descriptor.push(label.len() as u8);
+        descriptor.extend_from_slice(label.as_bytes());
+        if descriptor.len() % 2 == 1 {
+            descriptor.push(0);
+        }
+        let length = descriptor.len() as u16;
+        descriptor[..2].copy_from_slice(&length.to_be_bytes());
+        descriptor
+    }
+
+    #[test]
+    fn test_table_from_flattened_data() {
+        let td = type_descriptor;
+        let count = |n: u16| n.to_be_bytes().to_vec();
+        let limits = [count(1), td(0x05, &[], "low")].concat();
+        let point = [count(1), td(0x09, &[], "x")].concat();
+        let enum_items = [count(2), b"\x03Off\x04Auto".to_vec()].concat();
+        let elements = [
+            td(0x03, &[], "count"),
+            td(0x0A, &[], "gain"),
+            td(0x21, &[], "on"),
+            td(0x30, &[0xFF; 4], "name"),
+            td(0x16, &enum_items, "mode"),
+            td(0x54, &count(6), "when"),
+            td(
+                0x40,
+                &[count(1), vec![0xFF; 4], td(0x02, &[], "")].concat(),
+                "codes",
+            ),
+            td(0x50, &limits, "limits"),
+            td(
+                0x40,
+                &[count(1), vec![0xFF; 4], td(0x50, &point, "")].concat(),
+                "points",
+            ),
+        ];
+        let cluster = td(
+            0x50,
+            &[count(elements.len() as u16), elements.concat()].concat(),
+            "",
+        );
+        let type_string: Vec<i16> = cluster
+            .chunks(2)
+            .map(|word| i16::from_be_bytes([word[0], word[1]]))
+            .collect();
+
+        let data = [
+            (-5i32).to_be_bytes().to_vec(),
+            2.5f64.to_be_bytes().to_vec(),
+            vec![1],
+            [3i32.to_be_bytes().to_vec(), b"DUT".to_vec()].concat(),
+            1u16.to_be_bytes().to_vec(),
+            // 2024-01-02T03:04:05.5Z, in seconds since 1904
+            [3787009445i64.to_be_bytes(), (1u64 << 63).to_be_bytes()].concat(),
+            [2i32.to_be_bytes().to_vec(), vec![0, 1, 0xFF, 0xFE]].concat(),
+            vec![3],
+            [1i32.to_be_bytes(), 0.5f32.to_be_bytes()].concat(),
+        ]
+        .concat();
+
+        let mut table = 0;
+        let mut data_string = TestString::from_bytes(&data);
+        let mut types = TestArray::new(type_string.clone());
+        assert_eq!(
+            toml_edit_table_from_flattened_data(data_string.handle(), types.handle(), &mut table),
+            0
+        );
+
+        let mut doc = Document::new();
+        let found = handle::with(table, |table: &mut Table| Ok(table.clone())).unwrap();
+        *doc.as_table_mut() = found;
+        assert_eq!(
+            doc.to_string(),
+            concat!(
+                "count = -5\n",
+                "gain = 2.5\n",
+                "on = true\n",
+                "name = \"DUT\"\n",
+                "mode = \"Auto\"\n",
+                "when = 2024-01-02T03:04:05.5Z\n",
+                "codes = [1, -2]\n",
+                "points = [{ x = 0.5 }]\n",
+                "\n",
+                "[limits]\n",
+                "low = 3\n",
+            )
+        );
+        toml_edit_table_close(table);
+
+        // the data must match the type
+        let mut short = TestString::from_bytes(&data[..10]);
+        assert_eq!(
+            toml_edit_table_from_flattened_data(short.handle(), types.handle(), &mut table),
+            ErrorCode::InvalidValue as i32
+        );
+        assert_eq!(
+            last_error().unwrap().message,
+            "Flattened data ends early, at byte 4 of 10"
+        );
+    }
+
+    #[test]
+    fn test_table_from_flattened_data_limits() {
+        let td = type_descriptor;
+        let count = |n: u16| n.to_be_bytes().to_vec();
+        let words = |bytes: Vec<u8>| -> Vec<i16> {
+            bytes
+                .chunks(2)
+                .map(|word| i16::from_be_bytes([word[0], word[1]]))
+                .collect()
+        };
+        let to_string = |table: Table| {
+            let mut doc = Document::new();
+            *doc.as_table_mut() = table;
+            doc.to_string()
+        };
+
+        // an array of empty clusters takes no bytes but its size, even at the end of the data
+        let empty = td(0x50, &count(0), "");
+        let elements = [
+            td(0x03, &[], "n"),
+            td(0x40, &[count(1), vec![0xFF; 4], empty].concat(), "empties"),
+        ];
+        let cluster = words(td(0x50, &[count(2), elements.concat()].concat(), ""));
+        let data = [7i32.to_be_bytes(), 3i32.to_be_bytes()].concat();
+        let table = flattened::to_table(&data, &cluster).unwrap();
+        assert_eq!(to_string(table), "n = 7\nempties = [{}, {}, {}]\n");
+
+        // but there can't be any number of them
+        let data = [7i32.to_be_bytes(), i32::MAX.to_be_bytes()].concat();
+        let error = flattened::to_table(&data, &cluster).unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidValue);
+        assert_eq!(
+            error.message,
+            "Flattened data is too big, for elements that take no bytes (at most 1048576), \
+             for an array of [2147483647] elements: empties"
+        );
+
+        // clusters can be nested 64 deep, but no deeper
+        let nested = |depth: usize| {
+            let mut descriptor = td(0x03, &[], "x");
+            for _ in 0..depth {
+                descriptor = td(0x50, &[count(1), descriptor].concat(), "c");
+            }
+            words(descriptor)
+        };
+        assert!(flattened::to_table(&5i32.to_be_bytes(), &nested(64)).is_ok());
+        let error = flattened::to_table(&5i32.to_be_bytes(), &nested(65)).unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidValue);
+        assert_eq!(
+            error.message,
+            "Type descriptor at byte 390 is nested more than 64 deep"
+        );
+    }
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(
+            path::parse("servers[2].host").unwrap(),
+            vec![
+                Step::Key("servers".into()),
+                Step::Index(2),
+                Step::Key("host".into())
+            ]
+        );
+        assert_eq!(
+            path::parse("tool.\"my.package\" . 'a\\b'[0][1]").unwrap(),
+            vec![
+                Step::Key("tool".into()),
+                Step::Key("my.package".into()),
+                Step::Key("a\\b".into()),
+                Step::Index(0),
+                Step::Index(1)
+            ]
+        );
+        assert_eq!(
+            path::display(&path::parse("a.\"b.c\"[3].d").unwrap()),
+            "a.\"b.c\"[3].d"
+        );
+
+        for invalid in ["", "[0]", "a.", "a[x]", "a[0", "a[0]b", "\"a", "a..b"] {
+            let error = path::parse(invalid).unwrap_err();
+            assert_eq!(error.code, ErrorCode::InvalidPath, "{}", invalid);
+        }
+    }
+
+    // the item at a path, as TOML text, or the error code
+    fn get_by_path(doc: Handle, path: &str) -> std::result::Result<String, i32> {
+        let mut item = 0;
+        match toml_edit_doc_get_by_path(doc, TestString::new(path).handle(), &mut item) {
+            0 => {}
+            status => return Err(status),
+        }
+
+        let found = handle::with(item, |item: &mut Item| Ok(item.to_string())).unwrap();
+        toml_edit_item_close(item);
+        Ok(found.trim().to_string())
+    }
+
+    fn set_by_path(doc: Handle, path: &str, item: Item, create_missing: bool) -> i32 {
+        let item = handle::insert(item).unwrap();
+        let status = toml_edit_doc_set_by_path(
+            doc,
+            TestString::new(path).handle(),
+            item,
+            create_missing.into(),
+        );
+        toml_edit_item_close(item);
+        status
+    }
+
+    #[test]
+    fn test_get_by_path() {
+        let doc = doc_from_str(
+            "[nipm.dependencies]\nmy_package2 = { version = \"1.2.3\" }\n\n\
+             [[servers]]\nhost = \"a\"\n[[servers]]\nhost = \"b\"\nports = [80, 443]\n",
+        );
+
+        assert_eq!(
+            get_by_path(doc, "nipm.dependencies.my_package2.version"),
+            Ok("\"1.2.3\"".to_string())
+        );
+        assert_eq!(get_by_path(doc, "servers[1].host"), Ok("\"b\"".to_string()));
+        assert_eq!(
+            get_by_path(doc, "servers[1].ports[1]"),
+            Ok("443".to_string())
+        );
+        assert_eq!(
+            get_by_path(doc, "servers[2].host"),
+            Err(ErrorCode::NotFound as i32)
+        );
+        assert_eq!(last_error().unwrap().message, "Key not found: servers[2]");
+        assert_eq!(
+            get_by_path(doc, "servers.host"),
+            Err(ErrorCode::WrongType as i32)
+        );
+        assert_eq!(
+            get_by_path(doc, "nipm[0]"),
+            Err(ErrorCode::WrongType as i32)
+        );
+        assert_eq!(
+            get_by_path(doc, "nipm.[0]"),
+            Err(ErrorCode::InvalidPath as i32)
+        );
+
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_set_by_path() {
+        let doc = doc_from_str(
+            "[nipm]\nversion = \"1.2.3\"  # pinned\n\n\
+             [nipm.dependencies]\nmy_package2 = { version = \"1.2.3\" }\n\n\
+             [[servers]]\nports = [80, 443]\n",
+        );
+
+        assert_eq!(
+            set_by_path(doc, "nipm.version", toml_edit::value("2.0.0"), false),
+            0
+        );
+        assert_eq!(
+            set_by_path(
+                doc,
+                "nipm.dependencies.my_package2.version",
+                toml_edit::value("2.0"),
+                false
+            ),
+            0
+        );
+        assert_eq!(
+            set_by_path(doc, "servers[0].ports[1]", toml_edit::value(8443), false),
+            0
+        );
+
+        // missing tables are only created when asked to
+        assert_eq!(
+            set_by_path(doc, "tools.lint.enabled", toml_edit::value(true), false),
+            ErrorCode::NotFound as i32
+        );
+        assert_eq!(
+            set_by_path(doc, "tools.lint.enabled", toml_edit::value(true), true),
+            0
+        );
+
+        // an inline table can only hold values
+        assert_eq!(
+            set_by_path(
+                doc,
+                "nipm.dependencies.my_package2.x",
+                Item::Table(Table::new()),
+                false
+            ),
+            ErrorCode::WrongType as i32
+        );
+
+        assert_eq!(
+            doc_to_string(doc),
+            "[nipm]\nversion = \"2.0.0\"  # pinned\n\n\
+             [nipm.dependencies]\nmy_package2 = { version = \"2.0\" }\n\n\
+             [[servers]]\nports = [80, 8443]\n\n\
+             [tools.lint]\nenabled = true\n"
+        );
+
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_remove_by_path() {
+        let doc = doc_from_str("a = { b = 1, c = 2 }\nlist = [1, 2, 3]\n");
+        let mut removed = LVBool::from(false);
+
+        let mut remove = |path: &str| {
+            assert_eq!(
+                toml_edit_doc_remove_by_path(doc, TestString::new(path).handle(), &mut removed),
+                0
+            );
+            bool::from(removed)
+        };
+
+        assert!(remove("a.b"));
+        assert!(remove("list[1]"));
+        assert!(!remove("a.missing"));
+        assert!(!remove("missing.b"));
+        assert!(!remove("list[5]"));
+
+        assert_eq!(doc_to_string(doc), "a = { c = 2 }\nlist = [1, 3]\n");
+
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_parse_failure_is_an_error() {
+        let mut doc = 0;
+        let mut toml = TestString::new("key = ");
+
+        assert_eq!(
+            toml_edit_doc_from_string(toml.handle(), &mut doc),
+            ErrorCode::ParseError as i32
+        );
+        assert_eq!(doc, 0);
+        assert_eq!(last_error().unwrap().function, "toml_edit_doc_from_string");
+    }
+
+    #[test]
+    fn test_missing_table_is_not_found() {
+        let doc = doc_from_str("[present]\nkey = 1\n");
+        let mut table = 0;
+        let mut name = TestString::new("missing");
+
+        assert_eq!(
+            toml_edit_doc_get_table(doc, name.handle(), &mut table),
+            ErrorCode::NotFound as i32
+        );
+
+        let mut name = TestString::new("present");
+        assert_eq!(toml_edit_doc_get_table(doc, name.handle(), &mut table), 0);
+        assert_ne!(table, 0);
+
+        toml_edit_table_close(table);
+        toml_edit_doc_close(doc);
+    }
+
+    #[test]
+    fn test_panic_is_caught_and_reported() {
+        let status = call("panicking_function", || -> Result<()> {
+            // indexing a Table with a missing key panics inside toml_edit
+            let table = Table::new();
+            let _ = &table["missing"];
+            Ok(())
+        });
+
+        assert_eq!(status, ErrorCode::Panic as i32);
+
+        let error = last_error().unwrap();
+        assert_eq!(error.code, ErrorCode::Panic as i32);
+        assert_eq!(error.function, "panicking_function");
+        assert_eq!(error.message, "Internal error (panic): index not found");
+    }
+
+    #[test]
+    fn test_success_after_panic_clears_last_error() {
+        call("panicking_function", || panic!("boom"));
+        assert!(last_error().unwrap().message.ends_with("boom"));
+
+        let mut table = 0;
+        assert_eq!(toml_edit_table_new(&mut table), 0);
+        assert_eq!(last_error(), None);
+        toml_edit_table_close(table);
+    }
+
+    #[test]
+    fn test_parse_error_details() {
+        let source = "a = 1\r\nb = \"x\r\nc = 3";
+        let error = Document::from_str(source).unwrap_err();
+
+        let details = ParseErrorDetails::new(source, &error);
+        assert_eq!(details.message, "invalid basic string");
+        assert_eq!(details.line, 2);
+        assert_eq!(details.column, 7);
+        assert_eq!(details.span, 13..14);
+        assert_eq!(details.source_line, "b = \"x");
+    }
+
+    #[test]
+    fn test_parse_error_details_at_end_of_input() {
+        let source = "[table]\nkey = ";
+        let error = Document::from_str(source).unwrap_err();
+
+        let details = ParseErrorDetails::new(source, &error);
+        assert_eq!(details.message, "TOML parse error");
+        assert_eq!(details.line, 2);
+        assert_eq!(details.column, 7);
+        assert_eq!(details.span, 14..14);
+        assert_eq!(details.source_line, "key = ");
+    }
+
+    #[test]
+    fn test_parse_error_column_counts_characters() {
+        let source = "name = \"é\" x";
+        let error = Document::from_str(source).unwrap_err();
+
+        let details = ParseErrorDetails::new(source, &error);
+        assert_eq!(details.line, 1);
+        assert_eq!(details.column, 12);
+        assert_eq!(details.span.start, 12);
+    }
 }
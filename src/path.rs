@@ -0,0 +1,466 @@
+// Key paths, for reaching into nested tables, inline tables and arrays in one call,
+// e.g. `nipm.dependencies.my_package2.version` or `servers[2].host`.
+//
+// Keys are written in TOML's own dotted key syntax, so a key containing a dot (or anything
+// else) can be quoted: `tool."my.package".version`. A number in square brackets selects an
+// element of an Array or an Array of Tables.
+
+use toml_edit::{Array, ArrayOfTables, Document, InlineTable, Item, Key, Table, Value};
+
+use crate::decor::{self, Place};
+use crate::error::{Error, ErrorCode, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Step {
+    Key(String),
+    Index(usize),
+}
+
+// parse a path into its steps
+pub fn parse(path: &str) -> Result<Vec<Step>> {
+    let invalid = |reason: &str| {
+        Error::new(
+            ErrorCode::InvalidPath,
+            format!("Invalid path ({}): {}", reason, path),
+        )
+    };
+
+    let mut steps = Vec::new();
+    // the dotted keys since the start of the path or the last index
+    let mut keys = String::new();
+    let mut quote = None;
+    let mut chars = path.chars();
+
+    while let Some(c) = chars.next() {
+        keys.push(c);
+
+        match (quote, c) {
+            // skip over escaped characters, so `\"` doesn't end a basic string
+            (Some('"'), '\\') => keys.extend(chars.next()),
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '[') => {
+                keys.pop();
+                push_keys(&mut steps, &keys).map_err(invalid)?;
+                keys.clear();
+
+                let mut index = String::new();
+                loop {
+                    match chars.next() {
+                        Some(']') => break,
+                        Some(c) => index.push(c),
+                        None => return Err(invalid("missing `]`")),
+                    }
+                }
+
+                let index = index
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid("index is not a number"))?;
+                steps.push(Step::Index(index));
+            }
+            (None, _) => {}
+        }
+    }
+
+    if quote.is_some() {
+        return Err(invalid("unterminated quote"));
+    }
+
+    push_keys(&mut steps, &keys).map_err(invalid)?;
+
+    if steps.is_empty() {
+        return Err(invalid("empty"));
+    }
+
+    Ok(steps)
+}
+
+// add the keys of a dotted key expression that came before an index, or the end of the path
+fn push_keys(steps: &mut Vec<Step>, keys: &str) -> std::result::Result<(), &'static str> {
+    let keys = keys.trim();
+
+    let keys = match steps.last() {
+        // the path starts with keys (an index can only follow a key)
+        None if keys.is_empty() => return Err("expected a key"),
+        None => keys,
+        // after an index comes another index, the end of the path, or `.` and more keys
+        Some(Step::Index(_)) if keys.is_empty() => return Ok(()),
+        Some(Step::Index(_)) => keys.strip_prefix('.').ok_or("expected `.` after `]`")?,
+        Some(Step::Key(_)) => unreachable!("keys are only pushed at the start, or after an index"),
+    };
+
+    let keys = Key::parse(keys).map_err(|_| "invalid key")?;
+    steps.extend(keys.iter().map(|key| Step::Key(key.get().to_string())));
+    Ok(())
+}
+
+// write a path back out, quoting keys where needed
+pub fn display(path: &[Step]) -> String {
+    let mut display = String::new();
+
+    for step in path {
+        match step {
+            Step::Key(key) => {
+                if !display.is_empty() {
+                    display.push('.');
+                }
+                display.push_str(&Key::new(key).display_repr());
+            }
+            Step::Index(index) => display.push_str(&format!("[{}]", index)),
+        }
+    }
+
+    display
+}
+
+// a position inside a TOML object, while following a path
+pub enum Node<'a> {
+    Table(&'a mut Table),
+    Item(&'a mut Item),
+    Value(&'a mut Value),
+    InlineTable(&'a mut InlineTable),
+    Array(&'a mut Array),
+    ArrayOfTables(&'a mut ArrayOfTables),
+}
+
+impl<'a> Node<'a> {
+    // whether this can have a child at `step` (even if it doesn't right now)
+    fn accepts(&self, step: &Step) -> bool {
+        match step {
+            Step::Key(_) => matches!(
+                self,
+                Node::Table(_)
+                    | Node::Item(Item::Table(_))
+                    | Node::InlineTable(_)
+                    | Node::Item(Item::Value(Value::InlineTable(_)))
+                    | Node::Value(Value::InlineTable(_))
+            ),
+            Step::Index(_) => matches!(
+                self,
+                Node::ArrayOfTables(_)
+                    | Node::Item(Item::ArrayOfTables(_))
+                    | Node::Array(_)
+                    | Node::Item(Item::Value(Value::Array(_)))
+                    | Node::Value(Value::Array(_))
+            ),
+        }
+    }
+
+    // the child at `step`, if there is one
+    fn child(self, step: &Step) -> Option<Node<'a>> {
+        match (self, step) {
+            (Node::Table(table) | Node::Item(Item::Table(table)), Step::Key(key)) => {
+                table.get_mut(key).map(Node::Item)
+            }
+            (
+                Node::InlineTable(inline_table)
+                | Node::Item(Item::Value(Value::InlineTable(inline_table)))
+                | Node::Value(Value::InlineTable(inline_table)),
+                Step::Key(key),
+            ) => inline_table.get_mut(key).map(Node::Value),
+            (
+                Node::ArrayOfTables(array) | Node::Item(Item::ArrayOfTables(array)),
+                Step::Index(index),
+            ) => array.get_mut(*index).map(Node::Table),
+            (
+                Node::Array(array)
+                | Node::Item(Item::Value(Value::Array(array)))
+                | Node::Value(Value::Array(array)),
+                Step::Index(index),
+            ) => array.get_mut(*index).map(Node::Value),
+            _ => None,
+        }
+    }
+
+    // the child at `key`, inserting an empty table if it doesn't exist yet
+    // (an implicit table, so `a.b.c = 1` is written as `[a.b]` rather than `[a]` and `[a.b]`)
+    fn child_or_insert(self, key: &str) -> Option<Node<'a>> {
+        match self {
+            Node::Table(table) | Node::Item(Item::Table(table)) => {
+                let item = table.entry(key).or_insert_with(|| {
+                    let mut table = Table::new();
+                    table.set_implicit(true);
+                    Item::Table(table)
+                });
+                Some(Node::Item(item))
+            }
+            Node::InlineTable(inline_table)
+            | Node::Item(Item::Value(Value::InlineTable(inline_table)))
+            | Node::Value(Value::InlineTable(inline_table)) => Some(Node::Value(
+                inline_table
+                    .entry(key)
+                    .or_insert_with(|| Value::InlineTable(InlineTable::new())),
+            )),
+            _ => None,
+        }
+    }
+
+    // a Document is only ever the start of a path, never the end of one
+    pub fn into_document(self) -> Option<&'a mut Document> {
+        None
+    }
+
+    pub fn into_table(self) -> Option<&'a mut Table> {
+        match self {
+            Node::Table(table) | Node::Item(Item::Table(table)) => Some(table),
+            _ => None,
+        }
+    }
+
+    pub fn into_item(self) -> Option<&'a mut Item> {
+        match self {
+            Node::Item(item) => Some(item),
+            _ => None,
+        }
+    }
+
+    pub fn into_value(self) -> Option<&'a mut Value> {
+        match self {
+            Node::Item(Item::Value(value)) | Node::Value(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn into_inline_table(self) -> Option<&'a mut InlineTable> {
+        match self {
+            Node::InlineTable(inline_table)
+            | Node::Item(Item::Value(Value::InlineTable(inline_table)))
+            | Node::Value(Value::InlineTable(inline_table)) => Some(inline_table),
+            _ => None,
+        }
+    }
+
+    pub fn into_array(self) -> Option<&'a mut Array> {
+        match self {
+            Node::Array(array)
+            | Node::Item(Item::Value(Value::Array(array)))
+            | Node::Value(Value::Array(array)) => Some(array),
+            _ => None,
+        }
+    }
+
+    pub fn into_array_of_tables(self) -> Option<&'a mut ArrayOfTables> {
+        match self {
+            Node::ArrayOfTables(array) | Node::Item(Item::ArrayOfTables(array)) => Some(array),
+            _ => None,
+        }
+    }
+
+    // a copy of whatever this is, as an Item
+    pub fn to_item(&self) -> Item {
+        match self {
+            Node::Table(table) => Item::Table((*table).clone()),
+            Node::Item(item) => (*item).clone(),
+            Node::Value(value) => Item::Value((*value).clone()),
+            Node::InlineTable(inline_table) => {
+                Item::Value(Value::InlineTable((*inline_table).clone()))
+            }
+            Node::Array(array) => Item::Value(Value::Array((*array).clone())),
+            Node::ArrayOfTables(array) => Item::ArrayOfTables((*array).clone()),
+        }
+    }
+}
+
+// the error for a step that can't be taken from the node at `path`
+fn wrong_container(path: &[Step], step: &Step) -> Error {
+    let expected = match step {
+        Step::Key(_) => "Table",
+        Step::Index(_) => "Array",
+    };
+    Error::wrong_type(&display(path), expected)
+}
+
+// follow a path from `node`
+pub fn get<'a>(mut node: Node<'a>, path: &[Step]) -> Result<Node<'a>> {
+    for (depth, step) in path.iter().enumerate() {
+        if !node.accepts(step) {
+            return Err(wrong_container(&path[..depth], step));
+        }
+
+        node = node
+            .child(step)
+            .ok_or_else(|| Error::not_found("Key", &display(&path[..=depth])))?;
+    }
+
+    Ok(node)
+}
+
+// follow all but the last step of a path, optionally inserting missing tables on the way
+fn parent<'a, 'p>(
+    mut node: Node<'a>,
+    path: &'p [Step],
+    create: bool,
+) -> Result<(Node<'a>, &'p Step)> {
+    let (last, parents) = path.split_last().expect("paths are never empty");
+
+    for (depth, step) in parents.iter().enumerate() {
+        if !node.accepts(step) {
+            return Err(wrong_container(&path[..depth], step));
+        }
+
+        node = match step {
+            // only keys followed by another key can be created (an Array can't be made up)
+            Step::Key(key) if create && matches!(path[depth + 1], Step::Key(_)) => {
+                node.child_or_insert(key)
+            }
+            step => node.child(step),
+        }
+        .ok_or_else(|| Error::not_found("Key", &display(&path[..=depth])))?;
+    }
+
+    if !node.accepts(last) {
+        return Err(wrong_container(parents, last));
+    }
+
+    Ok((node, last))
+}
+
+// set the item at the end of a path, replacing any existing item in place
+// (an existing key keeps its position and formatting, and an existing value keeps its comments)
+pub fn set(root: Node<'_>, path: &[Step], item: Item, create: bool) -> Result<()> {
+    let (parent, last) = parent(root, path, create)?;
+
+    let not_found = || Error::not_found("Key", &display(path));
+
+    // `Item::into_value` and `Item::into_table` would convert between tables and inline tables,
+    // but only an item of the right type can be stored in an inline table, array or array of tables
+    let into_value = |item| match item {
+        Item::Value(value) => Ok(value),
+        _ => Err(Error::wrong_type("Item", "Value")),
+    };
+
+    match (parent, last) {
+        (Node::Table(table) | Node::Item(Item::Table(table)), Step::Key(key)) => {
+            match table.get_mut(key) {
+                Some(existing) => replace(existing, item),
+                None => {
+                    table.insert(key, item);
+                }
+            }
+        }
+        (node, Step::Key(key)) => {
+            let inline_table = node.into_inline_table().expect("parent accepts keys");
+            let mut value = into_value(item)?;
+
+            match inline_table.get_mut(key) {
+                Some(existing) => {
+                    *value.decor_mut() = existing.decor().clone();
+                    *existing = value;
+                }
+                None => {
+                    decor::fit(value.decor_mut(), Place::Inline);
+                    inline_table.insert(key, value);
+                }
+            }
+        }
+        (
+            Node::ArrayOfTables(array) | Node::Item(Item::ArrayOfTables(array)),
+            Step::Index(index),
+        ) => {
+            let table = match item {
+                Item::Table(table) => table,
+                _ => return Err(Error::wrong_type("Item", "Table")),
+            };
+            if *index >= array.len() {
+                return Err(not_found());
+            }
+            crate::array::replace_table(array, *index, table);
+        }
+        (node, Step::Index(index)) => {
+            let array = node.into_array().expect("parent accepts indexes");
+            let value = into_value(item)?;
+
+            if *index >= array.len() {
+                return Err(not_found());
+            }
+            array.replace(*index, value);
+        }
+    }
+
+    Ok(())
+}
+
+// replace an item, keeping the comments and whitespace around a value
+fn replace(existing: &mut Item, mut item: Item) {
+    if let (Item::Value(existing), Item::Value(value)) = (&*existing, &mut item) {
+        *value.decor_mut() = existing.decor().clone();
+    }
+    *existing = item;
+}
+
+// rename the key at the end of a path, keeping its place and comments (see `keys::rename`)
+pub fn rename(root: Node<'_>, path: &[Step], new_key: &str) -> Result<()> {
+    let (parent, last) = parent(root, path, false)?;
+
+    let key = match last {
+        Step::Key(key) => key,
+        Step::Index(_) => {
+            return Err(Error::new(
+                ErrorCode::InvalidPath,
+                format!(
+                    "Invalid path (only a key can be renamed): {}",
+                    display(path)
+                ),
+            ))
+        }
+    };
+
+    match parent {
+        Node::Table(table) | Node::Item(Item::Table(table)) => {
+            crate::keys::rename(table, key, new_key)
+        }
+        node => crate::keys::rename(
+            node.into_inline_table().expect("parent accepts keys"),
+            key,
+            new_key,
+        ),
+    }
+    .map_err(|error| match error.code {
+        // name the whole path, not just the last key
+        ErrorCode::NotFound => Error::not_found("Key", &display(path)),
+        _ => error,
+    })
+}
+
+// remove the item at the end of a path, returning whether it existed
+pub fn remove(root: Node<'_>, path: &[Step]) -> Result<bool> {
+    let (parent, last) = match parent(root, path, false) {
+        Ok(found) => found,
+        Err(error) if error.code == ErrorCode::NotFound => return Ok(false),
+        Err(error) => return Err(error),
+    };
+
+    let removed = match (parent, last) {
+        (Node::Table(table) | Node::Item(Item::Table(table)), Step::Key(key)) => {
+            table.remove(key).is_some()
+        }
+        (node, Step::Key(key)) => node
+            .into_inline_table()
+            .expect("parent accepts keys")
+            .remove(key)
+            .is_some(),
+        (
+            Node::ArrayOfTables(array) | Node::Item(Item::ArrayOfTables(array)),
+            Step::Index(index),
+        ) => {
+            let exists = *index < array.len();
+            if exists {
+                array.remove(*index);
+            }
+            exists
+        }
+        (node, Step::Index(index)) => {
+            let array = node.into_array().expect("parent accepts indexes");
+
+            let exists = *index < array.len();
+            if exists {
+                crate::array::remove(array, *index);
+            }
+            exists
+        }
+    };
+
+    Ok(removed)
+}
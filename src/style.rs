@@ -0,0 +1,293 @@
+// Switching a table between the ways TOML can write it: a `[header]` section, an inline table
+// (`a = { b = 1 }`) or dotted keys (`a.b = 1`).
+//
+// A `[header]`'s comments belong to the table, but those of `a = ...` or `a.b = ...` belong to
+// its key in the parent table, so tables are converted through their parent, and the comments
+// are moved across. An inline table has to fit on one line, so the comments inside a table are
+// dropped when it becomes one. Dotted keys can only hold the comments of a single line, so a
+// table with more comments than that isn't converted to them, rather than losing them.
+
+use std::mem;
+
+use toml_edit::{Decor, InlineTable, Item, Table, Value};
+
+use crate::decor;
+use crate::error::{Error, ErrorCode, Result};
+use crate::keys::{self, Entries};
+
+// a table version of an inline table, optionally with every inline table inside it (and every
+// array of only inline tables) turned into a table (or an array of tables) too
+pub fn to_table(inline_table: InlineTable, recursive: bool) -> Table {
+    let dotted = inline_table.is_dotted();
+    let mut table = inline_table.into_table();
+    table.set_dotted(dotted);
+
+    if recursive {
+        for (_, item) in table.iter_mut() {
+            expand(item);
+        }
+    }
+    table
+}
+
+// turn an inline table, or an array of only inline tables, into a table or an array of tables,
+// along with everything inside it
+fn expand(item: &mut Item) {
+    *item = match mem::take(item) {
+        Item::Value(Value::InlineTable(inline_table)) => Item::Table(to_table(inline_table, true)),
+        Item::Value(Value::Array(array)) => {
+            match Item::Value(Value::Array(array)).into_array_of_tables() {
+                Ok(mut array) => {
+                    for table in array.iter_mut() {
+                        for (_, item) in table.iter_mut() {
+                            expand(item);
+                        }
+                    }
+                    Item::ArrayOfTables(array)
+                }
+                Err(item) => item,
+            }
+        }
+        Item::Table(mut table) => {
+            for (_, item) in table.iter_mut() {
+                expand(item);
+            }
+            Item::Table(table)
+        }
+        item => item,
+    };
+}
+
+// the comment lines (if there are any) of a prefix or suffix, which can be moved between a
+// `[header]` and a key
+fn comments(text: String) -> Option<String> {
+    text.contains('#').then_some(text)
+}
+
+// the comments around a table's `[header]` (or the first one of an array of tables)
+fn header_comments(item: &Item) -> (Option<String>, Option<String>) {
+    let header = match item {
+        Item::Table(table) => Some(table.decor()),
+        Item::ArrayOfTables(array) => array.get(0).map(Table::decor),
+        _ => None,
+    };
+    let (prefix, suffix) = header.map(decor::get).unwrap_or_default();
+    (comments(prefix), comments(suffix))
+}
+
+// move comments to a table's `[header]` (or the first one of an array of tables)
+fn set_header_comments(item: &mut Item, prefix: Option<String>, suffix: Option<String>) {
+    let header = match item {
+        Item::Table(table) => Some(table.decor_mut()),
+        Item::ArrayOfTables(array) => array.get_mut(0).map(Table::decor_mut),
+        _ => None,
+    };
+    if let Some(header) = header {
+        set_comments(header, prefix, suffix);
+    }
+}
+
+fn set_comments(decor: &mut Decor, prefix: Option<String>, suffix: Option<String>) {
+    if let Some(prefix) = prefix {
+        decor.set_prefix(prefix);
+    }
+    if let Some(suffix) = suffix {
+        decor.set_suffix(suffix);
+    }
+}
+
+// write the table (or array of tables) at `key` as an inline table (or an array of inline
+// tables), along with every table inside it
+pub fn make_inline(parent: &mut Table, key: &str) -> Result<()> {
+    let item = parent
+        .get_mut(key)
+        .ok_or_else(|| Error::not_found("Key", key))?;
+    if !matches!(item, Item::Table(_) | Item::ArrayOfTables(_)) {
+        return Err(Error::wrong_type(key, "Table or ArrayOfTables"));
+    }
+
+    let (prefix, suffix) = header_comments(item);
+    item.make_value();
+    if let Some(value) = item.as_value_mut() {
+        set_comments(value.decor_mut(), None, suffix);
+    }
+
+    // the key was written in a `[header]`, with no spaces around it
+    let key_decor = parent.key_decor_mut(key).expect("key was just found");
+    key_decor.clear();
+    set_comments(key_decor, prefix, None);
+    Ok(())
+}
+
+// write the inline table (or array of only inline tables) at `key` as a `[header]` table (or
+// an array of tables), optionally along with every inline table inside it
+pub fn make_table(parent: &mut Table, key: &str, recursive: bool) -> Result<()> {
+    let index = keys::index(parent, key)?;
+    let item = parent.get(key).expect("key was just found");
+
+    let mut table = match item.clone() {
+        Item::Value(Value::InlineTable(inline_table)) => {
+            Item::Table(to_table(inline_table, recursive))
+        }
+        Item::Value(Value::Array(array)) => {
+            let array = Item::Value(Value::Array(array))
+                .into_array_of_tables()
+                .map_err(|_| Error::wrong_type(key, "Array of InlineTables"))?;
+            let mut item = Item::ArrayOfTables(array);
+            if recursive {
+                expand(&mut item);
+            }
+            item
+        }
+        _ => return Err(Error::wrong_type(key, "InlineTable")),
+    };
+
+    let (prefix, _) = decor::get(parent.key_decor(key).expect("key was just found"));
+    let (_, suffix) = item
+        .as_value()
+        .map(|value| decor::get(value.decor()))
+        .unwrap_or_default();
+    set_header_comments(&mut table, comments(prefix), comments(suffix));
+
+    // the new table goes between the tables around it, and its key is written in its header
+    parent.fit(index, &mut table);
+    *parent.get_mut(key).expect("key was just found") = table;
+    parent
+        .key_decor_mut(key)
+        .expect("key was just found")
+        .clear();
+    Ok(())
+}
+
+// write the table at `key` with dotted keys (`a.b = 1`) or as a `[header]` table, optionally
+// along with every table inside it
+pub fn set_dotted(parent: &mut Table, key: &str, dotted: bool, recursive: bool) -> Result<()> {
+    let index = keys::index(parent, key)?;
+    if !parent.get(key).is_some_and(Item::is_table) {
+        return Err(Error::wrong_type(key, "Table"));
+    }
+
+    let (key_prefix, _) = decor::get(parent.key_decor(key).expect("key was just found"));
+    let mut item = parent.get(key).expect("key was just found").clone();
+    let table = item.as_table_mut().expect("item was just checked");
+
+    if recursive {
+        let children: Vec<String> = table
+            .iter()
+            .filter(|(_, item)| item.is_table())
+            .map(|(key, _)| key.to_string())
+            .collect();
+        for child in children {
+            set_dotted(table, &child, dotted, true)?;
+        }
+    }
+
+    let switched = table.is_dotted() != dotted;
+    let mut moved_prefix = None;
+    if switched {
+        table.set_dotted(dotted);
+        match dotted {
+            true => moved_prefix = move_comments_to_dotted(table, key)?,
+            false => set_comments(table.decor_mut(), comments(key_prefix), None),
+        }
+    }
+
+    // a new `[header]` goes between the tables around it, and its key is written in it
+    if switched && !dotted {
+        parent.fit(index, &mut item);
+    }
+    *parent.get_mut(key).expect("key was just found") = item;
+    if switched {
+        let key_decor = parent.key_decor_mut(key).expect("key was just found");
+        key_decor.clear();
+        set_comments(key_decor, moved_prefix, None);
+    }
+    Ok(())
+}
+
+// move the comments of a table just made dotted to where dotted keys can hold them, returning
+// those for above its line, for the prefix of its key in the parent table
+//
+// `a.b = 1` can't hold a comment between its keys, and the prefix of `a` is written on every
+// line of the table, so only a table written on one line can keep the comments of its header
+// and its keys (with its header's comment at the end of that line)
+fn move_comments_to_dotted(table: &mut Table, key: &str) -> Result<Option<String>> {
+    let lines = table.get_values().len();
+    let (prefix, suffix) = decor::get(table.decor());
+    let mut above = comments(prefix).unwrap_or_default();
+    take_key_comments(table, &mut above);
+    let end = comments(suffix);
+    table.decor_mut().clear();
+
+    let fits = match first_value(table) {
+        _ if above.is_empty() && end.is_none() => true,
+        Some(value) if lines == 1 => match end {
+            Some(end) if comments(decor::get(value.decor()).1).is_none() => {
+                value.decor_mut().set_suffix(end);
+                true
+            }
+            Some(_) => false,
+            None => true,
+        },
+        _ => false,
+    };
+    if !fits {
+        return Err(Error::new(
+            ErrorCode::InvalidValue,
+            format!(
+                "Table {} has comments that dotted keys can't hold, as it isn't written on a                  single line (remove them first)",
+                key
+            ),
+        ));
+    }
+    Ok((!above.is_empty()).then_some(above))
+}
+
+// take the comments above the keys a dotted table's lines are written with, below the table's
+// own key, as they can't go in the middle of `a.b = 1`
+fn take_key_comments(table: &mut Table, taken: &mut String) {
+    let keys: Vec<String> = table.iter().map(|(key, _)| key.to_string()).collect();
+
+    for key in keys {
+        let dotted = match table.get(&key) {
+            Some(Item::Value(_)) => false,
+            Some(Item::Table(table)) if table.is_dotted() => true,
+            _ => continue,
+        };
+
+        let key_decor = table.key_decor_mut(&key).expect("key was just listed");
+        let (prefix, _) = decor::get(key_decor);
+        taken.push_str(&comments(prefix).unwrap_or_default());
+        match dotted {
+            true => key_decor.clear(),
+            false => key_decor.set_prefix(""),
+        }
+
+        if let Some(Item::Table(table)) = table.get_mut(&key) {
+            take_key_comments(table, taken);
+        }
+    }
+}
+
+// the value on the first line a dotted table is written as
+fn first_value(table: &mut Table) -> Option<&mut Value> {
+    table.iter_mut().find_map(|(_, item)| match item {
+        Item::Value(value) => Some(value),
+        Item::Table(table) if table.is_dotted() => first_value(table),
+        _ => None,
+    })
+}
+
+// mark a table as implicit (its `[header]` is left out if it has no values of its own, as for
+// `[a]` when only `[a.b]` is written) or not, optionally along with every table inside it
+pub fn set_implicit(table: &mut Table, implicit: bool, recursive: bool) {
+    table.set_implicit(implicit);
+
+    if recursive {
+        for (_, item) in table.iter_mut() {
+            if let Item::Table(table) = item {
+                set_implicit(table, implicit, true);
+            }
+        }
+    }
+}